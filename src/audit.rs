@@ -0,0 +1,57 @@
+use serde::Serialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+/// One line of the audit log, written as JSON.
+#[derive(Serialize)]
+pub(crate) struct AuditEntry<'a> {
+    pub micros: u64,
+    pub trace_id: &'a str,
+    /// None if the message was too malformed to carry a command byte.
+    pub command: Option<u8>,
+    pub payload_bytes: usize,
+    pub result: &'static str,
+    pub latency_micros: u64,
+}
+
+/// An append-only record of every request, separate from the operational log so that it can be
+/// parsed line by line. Once the file grows past the maximum size, it's rotated to `<path>.1`.
+pub(crate) struct AuditLog {
+    path: String,
+    max_bytes: u64,
+    file: Mutex<(File, u64)>,
+}
+
+impl AuditLog {
+    pub(crate) fn open(path: String, max_bytes: u64) -> io::Result<AuditLog> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let len = file.metadata()?.len();
+
+        Ok(AuditLog {
+            path,
+            max_bytes,
+            file: Mutex::new((file, len)),
+        })
+    }
+
+    pub(crate) fn record(&self, entry: &AuditEntry) -> io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        let mut file = self.file.lock().unwrap();
+
+        if file.1 > self.max_bytes {
+            fs::rename(&self.path, format!("{}.1", self.path))?;
+            let rotated = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            *file = (rotated, 0);
+        }
+
+        file.0.write_all(&line)?;
+        file.1 += line.len() as u64;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+use rocksdb::{Options, DB};
+use slog::Logger;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Sleeps for the interval, waking periodically to check the shutdown flag. Returns false if
+/// shutdown was requested, in which case the caller should exit.
+pub(crate) fn wait(shutdown: &AtomicBool, interval: Duration) -> bool {
+    let mut remaining = interval;
+
+    while remaining > Duration::from_secs(0) {
+        if shutdown.load(Ordering::SeqCst) {
+            return false;
+        }
+
+        let step = remaining.min(SHUTDOWN_POLL_INTERVAL);
+        thread::sleep(step);
+        remaining -= step;
+    }
+
+    !shutdown.load(Ordering::SeqCst)
+}
+
+pub(crate) fn spawn_stats_dump(
+    db: Arc<DB>,
+    opts: Arc<Options>,
+    path: String,
+    interval: Duration,
+    max_bytes: u64,
+    shutdown: Arc<AtomicBool>,
+    logger: Logger,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        while wait(&shutdown, interval) {
+            if let Err(e) = dump_stats(&db, &opts, &path, max_bytes) {
+                error!(logger, "stats_dump"; "msg" => e.to_string(), "path" => &path);
+            }
+        }
+    })
+}
+
+/// Periodically flushes memtables, bounding how much acknowledged data only exists in memory and
+/// could be lost in a crash, without paying for a sync on every write.
+pub(crate) fn spawn_auto_flush(
+    db: Arc<DB>,
+    interval: Duration,
+    shutdown: Arc<AtomicBool>,
+    logger: Logger,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        while wait(&shutdown, interval) {
+            let start = Instant::now();
+            match crate::flush_all(&db) {
+                Ok(()) => debug!(logger, "auto_flush";
+                    "duration_micros" => start.elapsed().as_micros() as u64),
+                Err(e) => error!(logger, "auto_flush"; "msg" => e.to_string()),
+            }
+        }
+    })
+}
+
+fn dump_stats(db: &DB, opts: &Options, path: &str, max_bytes: u64) -> io::Result<()> {
+    if let Ok(metadata) = fs::metadata(path) {
+        if metadata.len() > max_bytes {
+            fs::rename(path, format!("{}.1", path))?;
+        }
+    }
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let statistics = opts.get_statistics().unwrap_or_default();
+    let db_stats = db
+        .property_value("rocksdb.stats")
+        .ok()
+        .and_then(|value| value)
+        .unwrap_or_default();
+
+    writeln!(file, "--- {}", wx::util::get_system_micros())?;
+    writeln!(file, "{}", statistics)?;
+    writeln!(file, "{}", db_stats)?;
+
+    Ok(())
+}
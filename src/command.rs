@@ -0,0 +1,233 @@
+/// Commands understood by the store. The first byte of every message identifies the command,
+/// with the remaining bytes being a command-specific payload.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    Put = 0,
+    Get = 1,
+    PutEvent = 2,
+    GetEvents = 3,
+    GetAllEvents = 4,
+    ClearEvents = 5,
+    Compact = 6,
+    Checkpoint = 7,
+    DeleteEvent = 8,
+    PutFetchFailure = 9,
+    GetFetchFailures = 10,
+    GetFetchFailuresSince = 11,
+    Delete = 12,
+    PutNamespaced = 13,
+    GetNamespaced = 14,
+    DeleteNamespaced = 15,
+    Flush = 16,
+    RekeyEvents = 17,
+    GetEventsCompressed = 18,
+    CatchUp = 19,
+    ImportEvents = 20,
+    ProbeSchema = 21,
+    EventsHistogramByTime = 22,
+    DeleteByPrefix = 23,
+    Info = 24,
+    GetFetchFailureByKey = 25,
+    Swap = 26,
+    DrainEventsBefore = 27,
+    CfStats = 28,
+    GetEventsNear = 29,
+    ExportEventsByType = 30,
+    GetRaw = 31,
+    GetEventsColumnar = 32,
+    GetConfig = 33,
+    EventsDigest = 34,
+    GetEventsChunk = 35,
+    CommitOffset = 36,
+    GetOffset = 37,
+    GetEventsBySeq = 38,
+    ValidateEvent = 39,
+    EventSizeStats = 40,
+    NormalizeCursor = 41,
+    WriteProbe = 42,
+    GetEventsByLocation = 43,
+    DeleteEvents = 44,
+    GetEventHeaders = 45,
+    WaitForEvent = 46,
+    GetEventsSse = 47,
+    ReplaceWindow = 48,
+    CompactionBacklog = 49,
+    GetEventsByType = 50,
+    Poll = 51,
+    VerifyCheckpoint = 52,
+    GetEventsWithKeys = 53,
+    SetDbOption = 54,
+    OldestActiveByType = 55,
+    CompactEvents = 56,
+    SearchEvents = 57,
+    GetEventTotals = 58,
+    LatestEvent = 59,
+    Pause = 60,
+    Resume = 61,
+    SstFiles = 62,
+    IngestLagStats = 63,
+    FollowFrom = 64,
+    GetEventsLastN = 65,
+    RollupDay = 66,
+    GetRollups = 67,
+    GetEventsNdjsonGz = 68,
+    ClaimNextEvent = 69,
+    GetEventsRedacted = 70,
+    ErrorStats = 71,
+    MigrateKeyEncoding = 72,
+    EventsDiff = 73,
+    PutEventBatch = 74,
+    ReplicateTo = 75,
+    WriteThroughput = 76,
+    CompareAndSwap = 77,
+    TopEventTypes = 78,
+    AnnotateEvent = 79,
+    GetAnnotations = 80,
+    VerifyIndexes = 81,
+    SetRetentionPolicy = 82,
+    GetRetentionPolicy = 83,
+    SweepEvents = 84,
+    GetRecentPaged = 85,
+    StorageEfficiency = 86,
+    GetExpiringSoon = 87,
+    SwapStore = 88,
+    SampleEvents = 89,
+    GetEventsProto = 90,
+}
+
+impl Command {
+    pub fn from(byte: u8) -> Option<Command> {
+        match byte {
+            0 => Some(Command::Put),
+            1 => Some(Command::Get),
+            2 => Some(Command::PutEvent),
+            3 => Some(Command::GetEvents),
+            4 => Some(Command::GetAllEvents),
+            5 => Some(Command::ClearEvents),
+            6 => Some(Command::Compact),
+            7 => Some(Command::Checkpoint),
+            8 => Some(Command::DeleteEvent),
+            9 => Some(Command::PutFetchFailure),
+            10 => Some(Command::GetFetchFailures),
+            11 => Some(Command::GetFetchFailuresSince),
+            12 => Some(Command::Delete),
+            13 => Some(Command::PutNamespaced),
+            14 => Some(Command::GetNamespaced),
+            15 => Some(Command::DeleteNamespaced),
+            16 => Some(Command::Flush),
+            17 => Some(Command::RekeyEvents),
+            18 => Some(Command::GetEventsCompressed),
+            19 => Some(Command::CatchUp),
+            20 => Some(Command::ImportEvents),
+            21 => Some(Command::ProbeSchema),
+            22 => Some(Command::EventsHistogramByTime),
+            23 => Some(Command::DeleteByPrefix),
+            24 => Some(Command::Info),
+            25 => Some(Command::GetFetchFailureByKey),
+            26 => Some(Command::Swap),
+            27 => Some(Command::DrainEventsBefore),
+            28 => Some(Command::CfStats),
+            29 => Some(Command::GetEventsNear),
+            30 => Some(Command::ExportEventsByType),
+            31 => Some(Command::GetRaw),
+            32 => Some(Command::GetEventsColumnar),
+            33 => Some(Command::GetConfig),
+            34 => Some(Command::EventsDigest),
+            35 => Some(Command::GetEventsChunk),
+            36 => Some(Command::CommitOffset),
+            37 => Some(Command::GetOffset),
+            38 => Some(Command::GetEventsBySeq),
+            39 => Some(Command::ValidateEvent),
+            40 => Some(Command::EventSizeStats),
+            41 => Some(Command::NormalizeCursor),
+            42 => Some(Command::WriteProbe),
+            43 => Some(Command::GetEventsByLocation),
+            44 => Some(Command::DeleteEvents),
+            45 => Some(Command::GetEventHeaders),
+            46 => Some(Command::WaitForEvent),
+            47 => Some(Command::GetEventsSse),
+            48 => Some(Command::ReplaceWindow),
+            49 => Some(Command::CompactionBacklog),
+            50 => Some(Command::GetEventsByType),
+            51 => Some(Command::Poll),
+            52 => Some(Command::VerifyCheckpoint),
+            53 => Some(Command::GetEventsWithKeys),
+            54 => Some(Command::SetDbOption),
+            55 => Some(Command::OldestActiveByType),
+            56 => Some(Command::CompactEvents),
+            57 => Some(Command::SearchEvents),
+            58 => Some(Command::GetEventTotals),
+            59 => Some(Command::LatestEvent),
+            60 => Some(Command::Pause),
+            61 => Some(Command::Resume),
+            62 => Some(Command::SstFiles),
+            63 => Some(Command::IngestLagStats),
+            64 => Some(Command::FollowFrom),
+            65 => Some(Command::GetEventsLastN),
+            66 => Some(Command::RollupDay),
+            67 => Some(Command::GetRollups),
+            68 => Some(Command::GetEventsNdjsonGz),
+            69 => Some(Command::ClaimNextEvent),
+            70 => Some(Command::GetEventsRedacted),
+            71 => Some(Command::ErrorStats),
+            72 => Some(Command::MigrateKeyEncoding),
+            73 => Some(Command::EventsDiff),
+            74 => Some(Command::PutEventBatch),
+            75 => Some(Command::ReplicateTo),
+            76 => Some(Command::WriteThroughput),
+            77 => Some(Command::CompareAndSwap),
+            78 => Some(Command::TopEventTypes),
+            79 => Some(Command::AnnotateEvent),
+            80 => Some(Command::GetAnnotations),
+            81 => Some(Command::VerifyIndexes),
+            82 => Some(Command::SetRetentionPolicy),
+            83 => Some(Command::GetRetentionPolicy),
+            84 => Some(Command::SweepEvents),
+            85 => Some(Command::GetRecentPaged),
+            86 => Some(Command::StorageEfficiency),
+            87 => Some(Command::GetExpiringSoon),
+            88 => Some(Command::SwapStore),
+            89 => Some(Command::SampleEvents),
+            90 => Some(Command::GetEventsProto),
+            _ => None,
+        }
+    }
+
+    pub fn value(self) -> u8 {
+        self as u8
+    }
+
+    /// Admin commands are destructive or expensive, so they are only accepted on the admin
+    /// socket. Everything else is a data command, accepted on either socket.
+    pub fn is_admin(self) -> bool {
+        matches!(
+            self,
+            Command::ClearEvents
+                | Command::Compact
+                | Command::Checkpoint
+                | Command::DeleteEvent
+                | Command::Flush
+                | Command::RekeyEvents
+                | Command::ImportEvents
+                | Command::DrainEventsBefore
+                | Command::ExportEventsByType
+                | Command::GetRaw
+                | Command::DeleteEvents
+                | Command::ReplaceWindow
+                | Command::VerifyCheckpoint
+                | Command::GetEventsWithKeys
+                | Command::SetDbOption
+                | Command::CompactEvents
+                | Command::Pause
+                | Command::Resume
+                | Command::SstFiles
+                | Command::RollupDay
+                | Command::MigrateKeyEncoding
+                | Command::ReplicateTo
+                | Command::VerifyIndexes
+                | Command::SetRetentionPolicy
+                | Command::SweepEvents
+                | Command::SwapStore
+        )
+    }
+}
@@ -0,0 +1,237 @@
+use crate::retry::Retry;
+use crate::KeyEncoding;
+use rocksdb::{DBCompressionType, MemtableFactory};
+use slog::{Discard, Logger};
+use std::time::Duration;
+use wx::domain::EventType;
+
+const DEFAULT_EVENT_THRESHOLD_MICROS: u64 = 1000 * 1000 * 60 * 60; // 1 hr
+const DEFAULT_FETCH_FAILURE_THRESHOLD_MICROS: u64 = 1000 * 1000 * 60 * 60 * 24; // 24 hr
+const DEFAULT_IMPORT_BATCH_SIZE: usize = 1000;
+const DEFAULT_WARMUP_MAX_EVENTS: usize = 100_000;
+const DEFAULT_MAX_CONCURRENT_READERS: usize = 16;
+const DEFAULT_MAX_OPEN_ITERATORS: usize = 32;
+const DEFAULT_LONG_ITERATOR_THRESHOLD: Duration = Duration::from_secs(60 * 5);
+const DEFAULT_MAX_KEY_LENGTH: usize = 4096;
+const DEFAULT_MAX_SAMPLE_SIZE: usize = 10_000;
+const DEFAULT_WRITE_RETRIES: u32 = 3;
+const DEFAULT_WRITE_RETRY_BACKOFF: Duration = Duration::from_millis(10);
+const DEFAULT_OPEN_RETRIES: u32 = 5;
+const DEFAULT_OPEN_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+const DEFAULT_STATS_DUMP_INTERVAL: Duration = Duration::from_secs(60 * 10);
+const DEFAULT_STATS_DUMP_MAX_BYTES: u64 = 10 * 1024 * 1024;
+const DEFAULT_AUDIT_LOG_MAX_BYTES: u64 = 100 * 1024 * 1024;
+const DEFAULT_SLOW_REQUEST_THRESHOLD: Duration = Duration::from_secs(1);
+const DEFAULT_BLOCK_CACHE_BYTES: usize = 8 * 1024 * 1024; // RocksDB's default
+
+/// Memtable representation for the events column family.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Memtable {
+    /// RocksDB's default, a skiplist kept sorted as it's written.
+    SkipList,
+    /// An unsorted vector, sorted once when flushed. Inserts are cheaper, which helps bulk loads
+    /// and heavy ingest, but reads of unflushed events have to sort it first, and writes to it
+    /// can't run concurrently.
+    Vector,
+}
+
+impl Memtable {
+    pub fn name(self) -> &'static str {
+        match self {
+            Memtable::SkipList => "skiplist",
+            Memtable::Vector => "vector",
+        }
+    }
+
+    pub(crate) fn factory(self) -> Option<MemtableFactory> {
+        match self {
+            Memtable::SkipList => None,
+            Memtable::Vector => Some(MemtableFactory::Vector),
+        }
+    }
+}
+
+/// Settings used to open a `Store`. Anything not explicitly set falls back to `Default`.
+pub struct Config {
+    pub event_threshold_micros: u64,
+    pub fetch_failure_threshold_micros: u64,
+    pub compression_type: DBCompressionType,
+    /// Memtable for the events column family. See `Memtable` for the tradeoffs.
+    pub event_memtable: Memtable,
+    /// How event keys are stored. Big-endian keys are smaller and always sort correctly. Events
+    /// stored under the other encoding are migrated when the store is opened, which takes a while
+    /// on a large store. Ignored once `Store::migrate_key_encoding` has chosen an encoding. Decimal
+    /// by default, for compatibility with existing tooling.
+    pub key_encoding: KeyEncoding,
+    /// Caps concurrent flushes and compactions. Fewer jobs leave more I/O for foreground reads
+    /// during ingest bursts, at the cost of compaction falling behind. RocksDB's default if None.
+    pub max_background_jobs: Option<i32>,
+    /// Limits flush and compaction writes to this many bytes per second. Keeps `get_events`
+    /// latency stable under heavy writes, but if set below the sustained ingest rate, compaction
+    /// backlog grows until writes are stalled. Unlimited if None.
+    pub rate_limit_bytes_per_sec: Option<i64>,
+    /// Once the write-ahead logs together exceed this size, memtables are flushed so the oldest
+    /// logs can be deleted. Smaller means less disk and faster recovery after a crash, but more
+    /// frequent flushes. RocksDB's default if None.
+    pub max_total_wal_size: Option<u64>,
+    /// Reads SST files with direct I/O, bypassing the OS page cache, so reads are served from the
+    /// block cache or disk and aren't disturbed by other processes competing for the page cache.
+    /// Off by default. Not every file system supports it, in which case opening fails.
+    pub direct_reads: bool,
+    /// Writes flushes and compactions with direct I/O, so background writes don't evict pages
+    /// that foreground reads rely on. Off by default.
+    pub direct_io_for_flush_and_compaction: bool,
+    /// Size of the LRU cache holding uncompressed blocks of the events column family, which serves
+    /// nearly every read. The other column families keep RocksDB's default cache.
+    pub block_cache_bytes: usize,
+    /// Caps the size of archived write-ahead logs kept on disk. RocksDB's default if None.
+    pub wal_size_limit_mb: Option<u64>,
+    /// Reuses this many old log files instead of creating new ones, avoiding file system churn
+    /// under heavy ingest. RocksDB's default if None.
+    pub recycle_log_file_num: Option<usize>,
+    /// KV puts and deletes with a longer key, in bytes, are rejected. Namespaced keys count the app
+    /// id towards the limit.
+    pub max_key_length: usize,
+    /// SampleEvents requests for more events are rejected, since the whole sample is held in
+    /// memory.
+    pub max_sample_size: usize,
+    pub logger: Logger,
+    /// Prepends a CRC32 to each stored event, verified on read so that corrupt records are logged
+    /// and skipped. Off by default, since stores written without it can't be read with it.
+    pub event_checksums: bool,
+    /// When set, KV values and events are encrypted at rest with the 32 byte AES-256 key in this
+    /// file. Values written before it was enabled are still readable. Off by default.
+    pub encryption_key_path: Option<String>,
+    /// Events of these types are dropped as RocksDB compacts once they're older than the paired
+    /// age, which is usually shorter than the event threshold, for low-value products such as
+    /// tests. Each event's type is read from its value, but only once it's old enough to drop.
+    /// Replaced by any policy set with `SetRetentionPolicy`. Empty by default.
+    pub type_retention: Vec<(EventType, Duration)>,
+    /// `Event` fields blanked in `GetEventsRedacted` responses, by name. Any of ext_uri, location,
+    /// md, outlook, report, text, title, warning, and watch. Optional fields become None, and the
+    /// title becomes empty. Empty by default.
+    pub redacted_fields: Vec<String>,
+    /// Lets RocksDB drop events and fetch failures older than their thresholds as it compacts,
+    /// rather than keeping them until explicitly cleared. KV pairs are never expired. Off by
+    /// default, since anything outside the window is otherwise still reachable via GetAllEvents.
+    ///
+    /// This isn't RocksDB's DBWithTTL, whose single TTL would cover every column family, KV pairs
+    /// included, where events and fetch failures have thresholds of their own. A compaction filter
+    /// compares each key's micros to its threshold instead, so age is measured from ingest rather
+    /// than from the write, and expired records stay readable until a compaction reaches them.
+    pub ttl_expiry: bool,
+    /// Drops annotations as RocksDB compacts once the event they're on is older than the event
+    /// threshold, rather than keeping them after the event is gone. Off by default.
+    pub expire_annotations: bool,
+    /// Prefixes `get_events` responses with `ENVELOPE_VERSION`, so clients can branch on the layout
+    /// as it evolves. Off by default, since existing clients expect the bare envelope.
+    pub envelope_version: bool,
+    /// Imports write a batch every this many records, bounding memory regardless of input size.
+    pub import_batch_size: usize,
+    /// Puts that fail because RocksDB is stalling writes are retried with backoff, smoothing
+    /// ingest through compaction pressure instead of failing the client.
+    pub write_retry: Retry,
+    /// Opening is retried while another process holds the DB lock, as happens briefly during
+    /// rolling restarts. Other open failures are not retried.
+    pub open_retry: Retry,
+    /// Reads of the event column family beyond this many block until one finishes, bounding the
+    /// iterators and snapshots held open at once.
+    pub max_concurrent_readers: usize,
+    /// Streaming reads (long polls, chunked and streamed responses, exports, and replication)
+    /// beyond this many are rejected with a "too many open iterators" error, since their iterators
+    /// pin SST files and hold up compaction cleanup for as long as they're open.
+    pub max_open_iterators: usize,
+    /// Streaming reads held open longer than this are logged as a warning when they close.
+    pub long_iterator_threshold: Duration,
+    /// Reads through the recent event window on startup to warm the block cache. Off by default.
+    pub warmup: bool,
+    /// Upper bound on events read during warmup, so it can't delay startup excessively.
+    pub warmup_max_events: usize,
+    /// When set, RocksDB statistics and DB properties are periodically appended to this file.
+    /// Off by default to avoid surprise disk usage.
+    pub stats_dump_path: Option<String>,
+    pub stats_dump_interval: Duration,
+    /// Once the dump file grows past this size, it's rotated to `<path>.1` and started over.
+    pub stats_dump_max_bytes: u64,
+    /// When set, memtables are flushed to disk this often, bounding how much data a crash could
+    /// lose. Off by default, leaving flushes to RocksDB.
+    pub auto_flush_interval: Option<Duration>,
+    /// When set, every request is appended to this file as a line of JSON with its command,
+    /// payload size, result, and latency. Off by default.
+    pub audit_log_path: Option<String>,
+    /// Once the audit log grows past this size, it's rotated to `<path>.1` and started over.
+    pub audit_log_max_bytes: u64,
+    /// Errors for malformed payloads say what was wrong with them, such as ending early or holding
+    /// an unknown variant, which helps diagnose clients whose codec has drifted from the server's.
+    /// The payload itself is never included. Off by default, which keeps messages stable.
+    pub error_detail: bool,
+    /// Requests taking longer than this are logged as a warning, on top of the usual request log,
+    /// so tail latency stands out.
+    pub slow_request_threshold: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            event_threshold_micros: DEFAULT_EVENT_THRESHOLD_MICROS,
+            fetch_failure_threshold_micros: DEFAULT_FETCH_FAILURE_THRESHOLD_MICROS,
+            compression_type: DBCompressionType::Lz4hc,
+            event_memtable: Memtable::SkipList,
+            key_encoding: KeyEncoding::Decimal,
+            max_background_jobs: None,
+            rate_limit_bytes_per_sec: None,
+            max_total_wal_size: None,
+            wal_size_limit_mb: None,
+            direct_reads: false,
+            direct_io_for_flush_and_compaction: false,
+            block_cache_bytes: DEFAULT_BLOCK_CACHE_BYTES,
+            recycle_log_file_num: None,
+            max_key_length: DEFAULT_MAX_KEY_LENGTH,
+            max_sample_size: DEFAULT_MAX_SAMPLE_SIZE,
+            logger: Logger::root(Discard, o!()),
+            event_checksums: false,
+            encryption_key_path: None,
+            type_retention: Vec::new(),
+            redacted_fields: Vec::new(),
+            ttl_expiry: false,
+            expire_annotations: false,
+            envelope_version: false,
+            import_batch_size: DEFAULT_IMPORT_BATCH_SIZE,
+            open_retry: Retry {
+                retries: DEFAULT_OPEN_RETRIES,
+                backoff: DEFAULT_OPEN_RETRY_BACKOFF,
+            },
+            write_retry: Retry {
+                retries: DEFAULT_WRITE_RETRIES,
+                backoff: DEFAULT_WRITE_RETRY_BACKOFF,
+            },
+            max_concurrent_readers: DEFAULT_MAX_CONCURRENT_READERS,
+            max_open_iterators: DEFAULT_MAX_OPEN_ITERATORS,
+            long_iterator_threshold: DEFAULT_LONG_ITERATOR_THRESHOLD,
+            warmup: false,
+            warmup_max_events: DEFAULT_WARMUP_MAX_EVENTS,
+            stats_dump_path: None,
+            stats_dump_interval: DEFAULT_STATS_DUMP_INTERVAL,
+            stats_dump_max_bytes: DEFAULT_STATS_DUMP_MAX_BYTES,
+            auto_flush_interval: None,
+            audit_log_path: None,
+            audit_log_max_bytes: DEFAULT_AUDIT_LOG_MAX_BYTES,
+            slow_request_threshold: DEFAULT_SLOW_REQUEST_THRESHOLD,
+            error_detail: false,
+        }
+    }
+}
+
+impl Config {
+    pub fn compression_name(&self) -> &'static str {
+        match self.compression_type {
+            DBCompressionType::None => "none",
+            DBCompressionType::Snappy => "snappy",
+            DBCompressionType::Zlib => "zlib",
+            DBCompressionType::Bz2 => "bz2",
+            DBCompressionType::Lz4 => "lz4",
+            DBCompressionType::Lz4hc => "lz4hc",
+            DBCompressionType::Zstd => "zstd",
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use aes_gcm::aead::generic_array::GenericArray;
+use aes_gcm::aead::{Aead, NewAead};
+use aes_gcm::Aes256Gcm;
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+
+/// Prefixes every encrypted value, so values written before encryption was enabled can still be
+/// read as plaintext.
+const MAGIC: &[u8] = b"wxe\x01";
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+/// Encrypts values at rest with AES-256-GCM. Each value gets a random nonce, stored alongside it.
+pub(crate) struct Cipher {
+    aead: Aes256Gcm,
+}
+
+impl Cipher {
+    /// Loads a raw 32 byte key from a file. The key itself must never be logged.
+    pub(crate) fn from_key_file(path: &str) -> io::Result<Cipher> {
+        let key = fs::read(path)?;
+        if key.len() != KEY_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "encryption key must be 32 bytes",
+            ));
+        }
+
+        Ok(Cipher {
+            aead: Aes256Gcm::new(GenericArray::from_slice(&key)),
+        })
+    }
+
+    pub(crate) fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let ciphertext = self
+            .aead
+            .encrypt(GenericArray::from_slice(&nonce), plaintext)
+            .expect("encryption failure");
+        let mut value = Vec::with_capacity(MAGIC.len() + NONCE_LEN + ciphertext.len());
+        value.extend_from_slice(MAGIC);
+        value.extend_from_slice(&nonce);
+        value.extend_from_slice(&ciphertext);
+
+        value
+    }
+
+    /// Returns None if the value is encrypted but fails authentication, meaning it's corrupt or
+    /// was written with a different key. Unencrypted values are returned as they are.
+    pub(crate) fn decrypt<'a>(&self, value: &'a [u8]) -> Option<Cow<'a, [u8]>> {
+        if !value.starts_with(MAGIC) || value.len() < MAGIC.len() + NONCE_LEN {
+            return Some(Cow::Borrowed(value));
+        }
+
+        let (nonce, ciphertext) = value[MAGIC.len()..].split_at(NONCE_LEN);
+        self.aead
+            .decrypt(GenericArray::from_slice(nonce), ciphertext)
+            .ok()
+            .map(Cow::Owned)
+    }
+}
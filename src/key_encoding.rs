@@ -0,0 +1,80 @@
+use crate::{parse_key, wx_error};
+use std::convert::TryInto;
+use wx::error::Error;
+
+/// How event keys are encoded in the events column family. Either way, keys sort by ingest_ts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyEncoding {
+    /// The bytes of a stringified u64, which only sort correctly while every key has the same
+    /// number of digits.
+    Decimal,
+    /// Fixed-width big-endian u64 bytes. Always sorts correctly, and is less than half the size.
+    BigEndian,
+}
+
+impl KeyEncoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyEncoding::Decimal => "decimal",
+            KeyEncoding::BigEndian => "big_endian",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<KeyEncoding> {
+        match name {
+            "decimal" => Some(KeyEncoding::Decimal),
+            "big_endian" => Some(KeyEncoding::BigEndian),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn other(self) -> KeyEncoding {
+        match self {
+            KeyEncoding::Decimal => KeyEncoding::BigEndian,
+            KeyEncoding::BigEndian => KeyEncoding::Decimal,
+        }
+    }
+
+    /// The encoding a stored key is in, for when both may be present.
+    pub(crate) fn of(key: &[u8]) -> KeyEncoding {
+        if KeyEncoding::BigEndian.matches(key) {
+            KeyEncoding::BigEndian
+        } else {
+            KeyEncoding::Decimal
+        }
+    }
+
+    pub(crate) fn encode(self, micros: u64) -> Vec<u8> {
+        match self {
+            KeyEncoding::Decimal => micros.to_string().into_bytes(),
+            KeyEncoding::BigEndian => micros.to_be_bytes().to_vec(),
+        }
+    }
+
+    pub(crate) fn decode(self, key: &[u8]) -> Result<u64, Error> {
+        match self {
+            KeyEncoding::Decimal => parse_key(key),
+            KeyEncoding::BigEndian => key
+                .try_into()
+                .map(u64::from_be_bytes)
+                .map_err(|_| wx_error("invalid stored key")),
+        }
+    }
+
+    /// Human readable form of a stored key, for debugging.
+    pub(crate) fn display(self, key: &[u8]) -> String {
+        match self {
+            KeyEncoding::Decimal => String::from_utf8_lossy(key).to_string(),
+            KeyEncoding::BigEndian => key.iter().map(|byte| format!("{:02x}", byte)).collect(),
+        }
+    }
+
+    /// Whether a stored key is in this encoding. Big-endian keys for any realistic micros start
+    /// with a zero byte, so they're never mistaken for decimal digits.
+    pub(crate) fn matches(self, key: &[u8]) -> bool {
+        match self {
+            KeyEncoding::Decimal => !key.is_empty() && key.iter().all(u8::is_ascii_digit),
+            KeyEncoding::BigEndian => key.len() == 8 && !KeyEncoding::Decimal.matches(key),
+        }
+    }
+}
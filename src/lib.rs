@@ -1,121 +1,4691 @@
-use bincode::{deserialize, serialize};
-use rocksdb::{DBCompressionType, Options, DB};
+#[macro_use]
+extern crate slog;
+
+mod audit;
+mod background;
+mod command;
+mod config;
+mod crypto;
+mod key_encoding;
+mod listener;
+pub mod proto;
+mod read_limiter;
+mod response;
+mod retry;
+mod throughput;
+
+pub use command::Command;
+pub use config::{Config, Memtable};
+pub use key_encoding::KeyEncoding;
+pub use listener::{spawn_listener, SharedStore, Transport};
+pub use response::{
+    Annotation, CfStats, CompactionBacklog, ErrorCount, EventHeader, IndexReport, Info, LagStats,
+    RetentionPolicy, Settings, SizeStats, SstFile, StorageEfficiency, Throughput,
+};
+pub use retry::Retry;
+
+use audit::{AuditEntry, AuditLog};
+use bincode::{deserialize, serialize, serialized_size, ErrorKind};
+use crypto::Cipher;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use read_limiter::{ReadLimiter, ReadPermit};
+use rocksdb::checkpoint::Checkpoint;
+use rocksdb::{
+    BlockBasedOptions, Cache, ColumnFamily, ColumnFamilyDescriptor, CompactionDecision,
+    DBCompressionType, DBRawIterator, Options, SliceTransform, WriteBatch, DB,
+};
+use serde::Deserialize;
+use slog::Logger;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::convert::{TryFrom, TryInto};
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
 use std::mem;
-use wx::domain::Event;
+use std::str;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, RwLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use throughput::WriteRate;
+use twox_hash::XxHash64;
+use wx::domain::{Coordinates, Event, EventType, Location};
 use wx::error::{Error, WxError};
-use wx::store::Command;
+use wx::store::Status;
 use zmq::Message;
 
+/// Identifies which socket a message was received on. Admin commands are only accepted on the
+/// admin socket so that ordinary data clients can't reach destructive operations. The admin socket
+/// accepts data commands too.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Socket {
+    Data,
+    Admin,
+}
+
+/// Events live in their own column family, keyed by micros in the configured `KeyEncoding`, and
+/// fetch failures in another, keyed by big-endian micros. Consumer offsets are keyed by consumer
+/// id, and the sequence column family maps each event's big-endian sequence number to its key. The
+/// event types column family indexes each event's key under a prefix of its type, and event totals
+/// counts every event ever stored by its serialized type. Rollups hold daily counts per type, keyed
+/// by big-endian day followed by the serialized type, and claims hold the lease expiry of each
+/// claimed event under its key. Annotations hold notes on events, keyed by the event's big-endian
+/// ingest_ts followed by the big-endian micros the note was added. The store's own records, such
+/// as its format, live in the metadata column family, out of reach of KV commands. Everything else
+/// is a free-form key in the default column family.
+const ANNOTATIONS_CF: &str = "annotations";
+const EVENTS_CF: &str = "events";
+const EVENT_TOTALS_CF: &str = "event_totals";
+const EVENT_TYPES_CF: &str = "event_types";
+const FETCH_FAILURES_CF: &str = "fetch_failures";
+/// Where `rekey_events` moved events, from their ingest_ts to their key's micros, both big-endian.
+const INGEST_TS_KEYS_CF: &str = "ingest_ts_keys";
+/// The reverse of `INGEST_TS_KEYS_CF`, from a moved event's key micros to its ingest_ts.
+const KEY_INGEST_TS_CF: &str = "key_ingest_ts";
+const METADATA_CF: &str = "metadata";
+const OFFSETS_CF: &str = "offsets";
+const ROLLUPS_CF: &str = "rollups";
+const SEQUENCE_CF: &str = "sequence";
+const CLAIMS_CF: &str = "claims";
+const COLUMN_FAMILIES: [&str; 12] = [
+    ANNOTATIONS_CF,
+    CLAIMS_CF,
+    EVENTS_CF,
+    EVENT_TOTALS_CF,
+    EVENT_TYPES_CF,
+    FETCH_FAILURES_CF,
+    INGEST_TS_KEYS_CF,
+    KEY_INGEST_TS_CF,
+    METADATA_CF,
+    OFFSETS_CF,
+    ROLLUPS_CF,
+    SEQUENCE_CF,
+];
+
+const DEFAULT_CF: &str = "default";
+
+/// RocksDB's recommended defaults for the rate limiter's refill period and fairness.
+const RATE_LIMIT_REFILL_MICROS: i64 = 100 * 1000;
+const RATE_LIMIT_FAIRNESS: i32 = 10;
+
+/// Events timestamped further in the future than this fail validation.
+const MAX_EVENT_CLOCK_SKEW_MICROS: u64 = 1000 * 1000 * 60 * 60 * 24; // 24 hr
+
+/// Mutable column family options that `SetDbOption` may change on a running store. Anything else is
+/// rejected, so operators can't reach options that are unsafe to change live.
+pub const SETTABLE_DB_OPTIONS: [&str; 8] = [
+    "write_buffer_size",
+    "max_write_buffer_number",
+    "disable_auto_compactions",
+    "level0_file_num_compaction_trigger",
+    "level0_slowdown_writes_trigger",
+    "level0_stop_writes_trigger",
+    "target_file_size_base",
+    "max_bytes_for_level_base",
+];
+
+/// Longest a `WaitForEvent` request can block, regardless of the timeout it asks for. Each waiting
+/// request ties up a worker thread.
+const MAX_WAIT_MILLIS: u64 = 60 * 1000;
+
+/// Size stats measure at most about this many events, striding through larger windows.
+const MAX_SIZE_SAMPLES: u64 = 100_000;
+
+const MICROS_PER_DAY: u64 = 1000 * 1000 * 60 * 60 * 24;
+
+/// KV writes are serialized per key by one of this many locks, chosen by the key's hash.
+const KV_LOCK_STRIPES: usize = 64;
+
+/// Annotations are serialized per event by one of this many locks, chosen by its ingest_ts.
+const ANNOTATION_LOCK_STRIPES: usize = 16;
+
+/// Longest `replicate_to` waits on the target to accept or acknowledge a batch.
+const REPLICATION_TIMEOUT_MILLIS: i32 = 30 * 1000;
+
+/// Longest summary returned in an `EventHeader`, in chars.
+pub const HEADER_SUMMARY_CHARS: usize = 200;
+
+/// Length of the optional CRC32 prepended to stored events.
+const CHECKSUM_LEN: usize = 4;
+
+/// Separates an app id from its keys, so apps sharing a store don't collide in the flat keyspace.
+const NAMESPACE_SEPARATOR: char = '\u{1f}';
+
+/// Scratch key written by `write_probe`. Clients can't write keys starting with the separator, see
+/// `is_reserved_key`.
+const WRITE_PROBE_KEY: &[u8] = b"\x1fwx_storage_write_probe";
+const WRITE_PROBE_VALUE: &[u8] = b"probe";
+
+/// Records the on-disk format in the metadata column family, as a tuple of the `SCHEMA_VERSION` and
+/// `KeyEncoding` name that events are stored with. Older builds kept it under the same key in the
+/// default column family, reserved the same way as `WRITE_PROBE_KEY`.
+const FORMAT_KEY: &[u8] = b"\x1fwx_storage_format";
+
+/// Progress of an unfinished `migrate_key_encoding` in the metadata column family, as a tuple of
+/// the target `KeyEncoding` name, the number of events to move when it started, and the number
+/// moved so far.
+const MIGRATION_KEY: &[u8] = b"\x1fwx_storage_migration";
+
+/// The `RetentionPolicy` last set by `set_retention_policy` in the metadata column family, which
+/// takes over from `Config::type_retention`.
+const RETENTION_KEY: &[u8] = b"\x1fwx_storage_retention";
+
+/// The `KeyEncoding` name last chosen by `migrate_key_encoding` in the metadata column family,
+/// which takes over from `Config::key_encoding`.
+const KEY_ENCODING_KEY: &[u8] = b"\x1fwx_storage_key_encoding";
+
+/// Version of the stored `Event` format. Bump this whenever a change to `Event` alters how stored
+/// events deserialize.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Set on the command byte when the message carries a trace id.
+pub const TRACE_FLAG: u8 = 0x80;
+
+/// Layout of `get_events` responses, sent as a leading byte when `Config::envelope_version` is set.
+/// Version 1 is a u64 count followed by the events.
+pub const ENVELOPE_VERSION: u8 = 1;
+
+/// Leading byte on compressed responses, telling clients how to decompress the rest.
+pub const ZSTD_FLAG: u8 = 1;
+pub const GZIP_FLAG: u8 = 2;
+const ZSTD_LEVEL: i32 = 3;
+
+/// `Event` fields that can be redacted. Timestamps and the type are left, since they carry nothing
+/// sensitive and clients need them to page and filter.
+const REDACTABLE_FIELDS: [&str; 9] = [
+    "ext_uri", "location", "md", "outlook", "report", "text", "title", "warning", "watch",
+];
+
+pub(crate) fn wx_error(message: &str) -> Error {
+    Error::Wx(<WxError>::new(message))
+}
+
 fn convert_error(e: rocksdb::Error) -> Error {
-    Error::Wx(<WxError>::new(&e.to_string()))
+    wx_error(e.as_ref())
+}
+
+/// Splits a message into its command byte, optional trace id, and payload. If the high bit of the
+/// command byte is set, the next byte is the length of a UTF-8 trace id that precedes the payload.
+fn parse_header(msg: &[u8]) -> Result<(u8, Option<String>, &[u8]), Error> {
+    if msg.is_empty() {
+        return Err(wx_error("invalid message length"));
+    }
+
+    if msg[0] & TRACE_FLAG == 0 {
+        return Ok((msg[0], None, &msg[1..]));
+    }
+
+    let len = *msg.get(1).ok_or_else(|| wx_error("invalid trace id"))? as usize;
+    if msg.len() < 2 + len {
+        return Err(wx_error("invalid trace id"));
+    }
+
+    let trace_id = str::from_utf8(&msg[2..2 + len]).map_err(|_| wx_error("invalid trace id"))?;
+
+    Ok((
+        msg[0] & !TRACE_FLAG,
+        Some(trace_id.to_string()),
+        &msg[2 + len..],
+    ))
+}
+
+/// Reduces an error to its kind, for counting. Runs of digits become `N`, so messages differing
+/// only in keys, sizes, or offsets count as one kind.
+fn error_kind(e: &Error) -> String {
+    let mut kind = String::new();
+    let mut in_digits = false;
+
+    for c in e.to_string().chars() {
+        if !c.is_ascii_digit() {
+            kind.push(c);
+        } else if !in_digits {
+            kind.push('N');
+        }
+        in_digits = c.is_ascii_digit();
+    }
+
+    kind
+}
+
+/// Trace ids for requests that didn't supply one. Unique for the life of the process.
+fn generate_trace_id(request: u64) -> String {
+    format!("{:x}-{:x}", wx::util::get_system_micros(), request)
 }
 
-pub fn process_msg(msg: &Message, store: &Store) -> Result<Vec<u8>, Error> {
-    if msg.len() == 0 {
-        return Err(Error::Wx(<WxError>::new("invalid message length")));
+pub fn process_msg(msg: &Message, store: &Store, socket: Socket) -> Result<Vec<u8>, Error> {
+    let start = Instant::now();
+    let request = store.requests.fetch_add(1, Ordering::SeqCst);
+    let (command_byte, trace_id, payload) = match parse_header(msg) {
+        Ok(header) => header,
+        Err(e) => {
+            store.record_error(None, &e);
+            let latency_micros = start.elapsed().as_micros() as u64;
+            let trace_id = generate_trace_id(request);
+            store.audit(&trace_id, None, msg.len(), false, latency_micros);
+            return Err(e);
+        }
+    };
+    let trace_id = trace_id.unwrap_or_else(|| generate_trace_id(request));
+    let command = Command::from(command_byte);
+
+    let result = match command {
+        Some(command) if command.is_admin() && socket != Socket::Admin => {
+            Err(wx_error("command not permitted on this socket"))
+        }
+        Some(command) => execute(command, payload, store),
+        None => Err(wx_error("unknown command")),
+    };
+
+    if let Err(e) = &result {
+        store.record_error(command, e);
     }
 
-    let payload = &msg[1..];
-    let command = Command::from(msg[0]);
+    let latency_micros = start.elapsed().as_micros() as u64;
+    match &result {
+        Ok(_) => info!(store.logger, "request";
+            "trace_id" => &trace_id, "command" => ?command, "latency_micros" => latency_micros,
+            "result" => "ok"),
+        Err(e) => info!(store.logger, "request";
+            "trace_id" => &trace_id, "command" => ?command, "latency_micros" => latency_micros,
+            "result" => "error", "error" => e.to_string()),
+    }
+    if latency_micros > store.slow_request_micros {
+        warn!(store.logger, "slow_request";
+            "trace_id" => &trace_id, "command" => ?command, "latency_micros" => latency_micros);
+    }
+    store.audit(
+        &trace_id,
+        Some(command_byte),
+        payload.len(),
+        result.is_ok(),
+        latency_micros,
+    );
+
+    result
+}
 
+fn execute(command: Command, payload: &[u8], store: &Store) -> Result<Vec<u8>, Error> {
     match command {
-        Some(Command::Put) => store.put(payload).map_err(convert_error),
-        Some(Command::Get) => store.get(payload).map_err(convert_error),
-        Some(Command::PutEvent) => store.put_event(payload).map_err(convert_error),
-        Some(Command::GetEvents) => store.get_events(payload, false).map_err(convert_error),
-        Some(Command::GetAllEvents) => store.get_events(payload, true).map_err(convert_error),
-        _ => Err(Error::Wx(<WxError>::new("unknown command"))),
+        Command::Put => store.put(payload),
+        Command::Get => store.get(payload),
+        Command::PutEvent => store.put_event(payload),
+        Command::GetEvents => store.get_events(payload, false),
+        Command::GetAllEvents => store.get_events(payload, true),
+        Command::ClearEvents => store.clear_events().map_err(convert_error),
+        Command::Compact => store.compact(),
+        Command::Checkpoint => store.checkpoint(payload),
+        Command::DeleteEvent => store.delete_event(payload),
+        Command::PutFetchFailure => store.put_fetch_failure(payload),
+        Command::GetFetchFailures => store.get_fetch_failures(),
+        Command::GetFetchFailuresSince => store.get_fetch_failures_since(payload),
+        Command::Delete => store.delete(payload),
+        Command::PutNamespaced => store.put_namespaced(payload),
+        Command::GetNamespaced => store.get_namespaced(payload),
+        Command::DeleteNamespaced => store.delete_namespaced(payload),
+        Command::Flush => store.flush().map_err(convert_error),
+        Command::RekeyEvents => store.rekey_events(),
+        Command::GetEventsCompressed => store.get_events_compressed(payload),
+        Command::CatchUp => store.catch_up(payload),
+        Command::ImportEvents => store.import_events(payload),
+        Command::ProbeSchema => store.probe_schema(),
+        Command::EventsHistogramByTime => store.events_histogram_by_time(payload),
+        Command::DeleteByPrefix => store.delete_by_prefix(payload),
+        Command::Info => store.info(),
+        Command::GetFetchFailureByKey => store.get_fetch_failure_by_key(payload),
+        Command::Swap => store.swap(payload),
+        Command::DrainEventsBefore => store.drain_events_before(payload),
+        Command::CfStats => store.cf_stats(),
+        Command::GetEventsNear => store.get_events_near(payload),
+        Command::ExportEventsByType => store.export_events_by_type(payload),
+        Command::GetRaw => store.get_raw(payload),
+        Command::GetEventsColumnar => store.get_events_columnar(payload),
+        Command::GetConfig => store.get_config(),
+        Command::EventsDigest => store.events_digest(payload),
+        Command::GetEventsChunk => store.get_events_chunk(payload),
+        Command::CommitOffset => store.commit_offset(payload),
+        Command::GetOffset => store.get_offset(payload),
+        Command::GetEventsBySeq => store.get_events_by_seq(payload),
+        Command::ValidateEvent => store.validate_event(payload),
+        Command::EventSizeStats => store.event_size_stats(),
+        Command::NormalizeCursor => store.normalize_cursor(payload),
+        Command::WriteProbe => store.write_probe(),
+        Command::GetEventsByLocation => store.get_events_by_location(payload),
+        Command::DeleteEvents => store.delete_events(payload),
+        Command::GetEventHeaders => store.get_event_headers(payload),
+        Command::WaitForEvent => store.wait_for_event(payload),
+        Command::GetEventsSse => store.get_events_sse(payload),
+        Command::ReplaceWindow => store.replace_window(payload),
+        Command::CompactionBacklog => store.compaction_backlog(),
+        Command::GetEventsByType => store.get_events_by_type(payload),
+        Command::Poll => store.poll(payload),
+        Command::VerifyCheckpoint => store.verify_checkpoint(payload),
+        Command::GetEventsWithKeys => store.get_events_with_keys(),
+        Command::SetDbOption => store.set_db_option(payload),
+        Command::OldestActiveByType => store.oldest_active_by_type(),
+        Command::CompactEvents => store.compact_events(),
+        Command::SearchEvents => store.search_events(payload),
+        Command::GetEventTotals => store.get_event_totals(),
+        Command::LatestEvent => store.latest_event(),
+        Command::Pause => store.pause(),
+        Command::Resume => store.resume(),
+        Command::SstFiles => store.sst_files(),
+        Command::IngestLagStats => store.ingest_lag_stats(),
+        Command::FollowFrom => store.follow_from(payload),
+        Command::GetEventsLastN => store.get_events_last_n(payload),
+        Command::RollupDay => store.rollup_day(payload),
+        Command::GetRollups => store.get_rollups(payload),
+        Command::GetEventsNdjsonGz => store.get_events_ndjson_gz(payload),
+        Command::ClaimNextEvent => store.claim_next_event(payload),
+        Command::GetEventsRedacted => store.get_events_redacted(payload),
+        Command::ErrorStats => store.error_stats(),
+        Command::MigrateKeyEncoding => store.migrate_key_encoding(payload),
+        Command::EventsDiff => store.events_diff(payload),
+        Command::PutEventBatch => store.put_event_batch(payload),
+        Command::ReplicateTo => store.replicate_to(payload),
+        Command::WriteThroughput => store.write_throughput(),
+        Command::CompareAndSwap => store.compare_and_swap(payload),
+        Command::TopEventTypes => store.top_event_types(payload),
+        Command::AnnotateEvent => store.annotate_event(payload),
+        Command::GetAnnotations => store.get_annotations(payload),
+        Command::VerifyIndexes => store.verify_indexes(payload),
+        Command::SetRetentionPolicy => store.set_retention_policy(payload),
+        Command::GetRetentionPolicy => store.get_retention_policy(),
+        Command::SweepEvents => store.sweep_events(),
+        Command::GetRecentPaged => store.get_recent_paged(payload),
+        Command::StorageEfficiency => store.storage_efficiency(),
+        Command::GetExpiringSoon => store.get_expiring_soon(payload),
+        Command::SwapStore => store.swap_store(payload),
+        Command::SampleEvents => store.sample_events(payload),
+        Command::GetEventsProto => store.get_events_proto(payload),
+    }
+}
+
+fn namespaced_key(app: &str, key: &str) -> Result<String, Error> {
+    if app.is_empty() || app.contains(NAMESPACE_SEPARATOR) {
+        return Err(wx_error("invalid app id"));
+    }
+
+    Ok(format!("{}{}{}", app, NAMESPACE_SEPARATOR, key))
+}
+
+/// Keys the store keeps for itself in the default column family start with the namespace
+/// separator, which no client key needs to.
+fn is_reserved_key(key: &[u8]) -> bool {
+    key.first() == Some(&(NAMESPACE_SEPARATOR as u8))
+}
+
+/// Whether a location's point, or any vertex of its polygon, falls within the bounding box.
+fn in_bounds(
+    location: &Location,
+    (min_lat, min_lon, max_lat, max_lon): (f32, f32, f32, f32),
+) -> bool {
+    let contains = |c: &Coordinates| {
+        c.lat >= min_lat && c.lat <= max_lat && c.lon >= min_lon && c.lon <= max_lon
+    };
+
+    location.point.iter().any(contains)
+        || location.poly.iter().any(|poly| poly.iter().any(contains))
+}
+
+/// Describes why a payload couldn't be deserialized, for clients diagnosing codec drift. Only the
+/// kind of failure is described, never the offending bytes, since payloads may be sensitive.
+fn describe_decode_error(e: &ErrorKind) -> String {
+    match e {
+        ErrorKind::Io(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+            String::from("payload ended early")
+        }
+        ErrorKind::InvalidUtf8Encoding(_) => String::from("string is not valid UTF-8"),
+        ErrorKind::InvalidBoolEncoding(_) => String::from("bool is not 0 or 1"),
+        ErrorKind::InvalidCharEncoding => String::from("char is not valid"),
+        ErrorKind::InvalidTagEncoding(tag) => format!("unknown variant tag {}", tag),
+        ErrorKind::SizeLimit => String::from("payload too large"),
+        _ => String::from("payload is malformed"),
+    }
+}
+
+/// Returns the event following a valid CRC32, or None if the checksum doesn't match.
+fn verify_checksum(value: &[u8]) -> Option<&[u8]> {
+    if value.len() < CHECKSUM_LEN {
+        return None;
+    }
+
+    let (checksum, event) = value.split_at(CHECKSUM_LEN);
+    if checksum == &crc32fast::hash(event).to_le_bytes()[..] {
+        Some(event)
+    } else {
+        None
+    }
+}
+
+fn parse_key(key: &[u8]) -> Result<u64, Error> {
+    str::from_utf8(key)
+        .ok()
+        .and_then(|key| key.parse().ok())
+        .ok_or_else(|| wx_error("invalid stored key"))
+}
+
+/// The last sequence number assigned to an event, so numbering resumes without regressing after a
+/// restart.
+fn last_sequence(db: &DB) -> u64 {
+    let mut iter = db.raw_iterator_cf(db.cf_handle(SEQUENCE_CF).unwrap());
+    iter.seek_to_last();
+
+    iter.key()
+        .and_then(|key| parse_sequence(key).ok())
+        .unwrap_or(0)
+}
+
+/// The newest key micros an event has been moved to by `rekey_events`, or 0 if none has.
+fn rekeyed_through(db: &DB) -> u64 {
+    let mut iter = db.raw_iterator_cf(db.cf_handle(KEY_INGEST_TS_CF).unwrap());
+    iter.seek_to_last();
+
+    iter.key()
+        .and_then(|key| parse_sequence(key).ok())
+        .unwrap_or(0)
+}
+
+fn parse_sequence(key: &[u8]) -> Result<u64, Error> {
+    let bytes = key
+        .try_into()
+        .map_err(|_| wx_error("invalid stored sequence"))?;
+
+    Ok(u64::from_be_bytes(bytes))
+}
+
+/// Sends a `PutEventBatch` request keeping the events' ingest_ts, and waits for the reply, which
+/// is prefixed with a status byte like any other response from the store's listeners.
+fn send_event_batch(sock: &zmq::Socket, events: &[Event]) -> Result<(), Error> {
+    let mut request = [Command::PutEventBatch.value()].to_vec();
+    request.extend_from_slice(&serialize(&(events, true)).unwrap());
+    sock.send(request, 0)
+        .map_err(|e| wx_error(&e.to_string()))?;
+    let reply = sock.recv_bytes(0).map_err(|e| wx_error(&e.to_string()))?;
+
+    match reply.split_first() {
+        Some((&status, _)) if status == Status::OkByte.value() => Ok(()),
+        Some((_, error)) => Err(wx_error(
+            &deserialize::<String>(error).unwrap_or_else(|_| String::from("replication failed")),
+        )),
+        None => Err(wx_error("empty reply from replication target")),
+    }
+}
+
+/// Splits a key in the annotations column family into the event's ingest_ts and when the note was
+/// added.
+fn parse_annotation_key(key: &[u8]) -> Result<(u64, u64), Error> {
+    if key.len() != 16 {
+        return Err(wx_error("invalid stored annotation"));
+    }
+    let (ingest_ts, annotated_ts) = key.split_at(8);
+
+    Ok((
+        u64::from_be_bytes(ingest_ts.try_into().unwrap()),
+        u64::from_be_bytes(annotated_ts.try_into().unwrap()),
+    ))
+}
+
+/// Key in the event types column family indexing an event key under its type.
+fn type_key(event_type: &EventType, key: &[u8]) -> Vec<u8> {
+    let mut type_key = serialize(event_type).unwrap();
+    type_key.extend_from_slice(key);
+    type_key
+}
+
+/// Splits a key of the event types column family into its serialized `EventType` and the event
+/// key after it. The type's length comes from decoding it rather than being assumed, so it holds
+/// however bincode encodes the variant. None if the key doesn't start with a type.
+fn split_type_key(type_key: &[u8]) -> Option<(&[u8], &[u8])> {
+    let event_type: EventType = deserialize(type_key).ok()?;
+    let len = serialized_size(&event_type).ok()? as usize;
+
+    Some(type_key.split_at(len))
+}
+
+/// Prefix extractor of the event types column family, grouping its keys by type.
+fn type_prefix(type_key: &[u8]) -> &[u8] {
+    split_type_key(type_key).map_or(type_key, |(prefix, _)| prefix)
+}
+
+fn is_type_key(type_key: &[u8]) -> bool {
+    split_type_key(type_key).is_some()
+}
+
+/// The event key indexed by a key of the event types column family.
+fn indexed_key(type_key: &[u8]) -> &[u8] {
+    split_type_key(type_key).map_or(type_key, |(_, key)| key)
+}
+
+fn type_prefix_extractor() -> SliceTransform {
+    SliceTransform::create("event_type", type_prefix, Some(is_type_key))
+}
+
+/// Reads a numeric entry, such as "raw key size", from RocksDB's `aggregated-table-properties`,
+/// which lists them as "name=value" separated by semicolons. Missing entries are 0.
+fn table_property(properties: &str, name: &str) -> u64 {
+    properties
+        .split(';')
+        .filter_map(|entry| {
+            let mut parts = entry.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) if key.trim() == name => value.trim().parse().ok(),
+                _ => None,
+            }
+        })
+        .next()
+        .unwrap_or(0)
+}
+
+/// Reads a ticker from RocksDB's statistics dump, where each is a line such as
+/// "rocksdb.bytes.written COUNT : 1024". Missing tickers are 0.
+fn ticker(statistics: &str, name: &str) -> u64 {
+    statistics
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                (Some(ticker), Some("COUNT"), Some(":"), Some(count)) if ticker == name => {
+                    count.parse().ok()
+                }
+                _ => None,
+            }
+        })
+        .next()
+        .unwrap_or(0)
+}
+
+/// Flushes the memtables of the default and every other column family to disk.
+fn flush_all(db: &DB) -> Result<(), rocksdb::Error> {
+    db.flush()?;
+
+    for name in COLUMN_FAMILIES.iter() {
+        db.flush_cf(db.cf_handle(name).unwrap())?;
+    }
+
+    Ok(())
+}
+
+fn cf_descriptor(
+    name: &str,
+    config: &Config,
+    cipher: Option<Arc<Cipher>>,
+    type_retention: TypeRetention,
+) -> ColumnFamilyDescriptor {
+    let mut cf_opts = cf_options(config.compression_type);
+    let threshold_micros = match name {
+        EVENTS_CF | EVENT_TYPES_CF | CLAIMS_CF => Some(config.event_threshold_micros),
+        FETCH_FAILURES_CF => Some(config.fetch_failure_threshold_micros),
+        _ => None,
+    };
+    let micros_key: fn(&[u8]) -> &[u8] = match name {
+        EVENT_TYPES_CF => indexed_key,
+        _ => |key| key,
+    };
+    let ttl_micros = threshold_micros.filter(|_| config.ttl_expiry);
+    if name == EVENTS_CF {
+        // Installed even without any rules, since they can be set at runtime. A column family only
+        // has one compaction filter, so this one applies the TTL too.
+        let filter =
+            type_retention_filter(type_retention, ttl_micros, config.event_checksums, cipher);
+        cf_opts.set_compaction_filter("type_retention", filter);
+    } else if name == ANNOTATIONS_CF && config.expire_annotations {
+        let threshold_micros = config.event_threshold_micros;
+        cf_opts.set_compaction_filter("ttl", annotation_ttl_filter(threshold_micros));
+    } else if let Some(threshold_micros) = ttl_micros {
+        cf_opts.set_compaction_filter("ttl", ttl_filter(threshold_micros, micros_key));
+    }
+    if name == EVENTS_CF {
+        let mut table_opts = BlockBasedOptions::default();
+        table_opts.set_block_cache(&Cache::new_lru_cache(config.block_cache_bytes).unwrap());
+        cf_opts.set_block_based_table_factory(&table_opts);
+    }
+    if let (EVENTS_CF, Some(factory)) = (name, config.event_memtable.factory()) {
+        cf_opts.set_memtable_factory(factory);
+    }
+    if name == EVENT_TYPES_CF {
+        // Lets seeks within a type skip files and blocks holding only other types
+        cf_opts.set_prefix_extractor(type_prefix_extractor());
+    }
+
+    ColumnFamilyDescriptor::new(name, cf_opts)
+}
+
+/// Describes a column family of another store, such as a checkpoint, opened read-only. No
+/// compaction filters are installed, since nothing is compacted.
+fn inspection_descriptor(name: &str) -> ColumnFamilyDescriptor {
+    let mut cf_opts = Options::default();
+    if name == EVENT_TYPES_CF {
+        cf_opts.set_prefix_extractor(type_prefix_extractor());
+    }
+
+    ColumnFamilyDescriptor::new(name, cf_opts)
+}
+
+/// RocksDB reports a DB locked by another process, or another store in this one, as an IO error
+/// mentioning the lock file.
+fn is_lock_error(e: &rocksdb::Error) -> bool {
+    let message = e.to_string();
+    message.starts_with("IO error") && message.to_lowercase().contains("lock")
+}
+
+fn cf_options(compression_type: DBCompressionType) -> Options {
+    let mut opts = Options::default();
+    opts.set_compression_type(compression_type);
+    opts
+}
+
+/// Micros from a key in either encoding, since the encoding in use can change while a column
+/// family's compaction filter is installed.
+fn decode_any(key: &[u8]) -> Result<u64, Error> {
+    KeyEncoding::of(key).decode(key)
+}
+
+/// Drops records keyed by micros, or by micros after a prefix that `micros_key` strips, once
+/// they're older than the threshold. This runs during compaction, so expired records linger until
+/// RocksDB compacts the files holding them.
+fn ttl_filter(
+    threshold_micros: u64,
+    micros_key: fn(&[u8]) -> &[u8],
+) -> impl FnMut(u32, &[u8], &[u8]) -> CompactionDecision + Send + 'static {
+    move |_level, key, _value| match decode_any(micros_key(key)) {
+        Ok(micros) if micros + threshold_micros < wx::util::get_system_micros() => {
+            CompactionDecision::Remove
+        }
+        _ => CompactionDecision::Keep,
+    }
+}
+
+/// Drops annotations once the event they're on is older than the threshold, going by the ingest_ts
+/// leading their keys.
+fn annotation_ttl_filter(
+    threshold_micros: u64,
+) -> impl FnMut(u32, &[u8], &[u8]) -> CompactionDecision + Send + 'static {
+    move |_level, key, _value| match parse_annotation_key(key) {
+        Ok((ingest_ts, _)) if ingest_ts + threshold_micros < wx::util::get_system_micros() => {
+            CompactionDecision::Remove
+        }
+        _ => CompactionDecision::Keep,
+    }
+}
+
+/// Per-type retention as pairs of serialized type and micros, shared between the store and the
+/// events compaction filter so changes apply to the next compaction.
+type TypeRetention = Arc<RwLock<Vec<(Vec<u8>, u64)>>>;
+
+/// Drops events older than their type's retention, or older than `ttl_micros` regardless of type.
+/// Only events old enough to be dropped are read to find their type. Events that can't be read are
+/// kept, leaving them for readers to report.
+fn type_retention_filter(
+    retention: TypeRetention,
+    ttl_micros: Option<u64>,
+    event_checksums: bool,
+    cipher: Option<Arc<Cipher>>,
+) -> impl FnMut(u32, &[u8], &[u8]) -> CompactionDecision + Send + 'static {
+    move |_level, key, value| {
+        let age = match decode_any(key) {
+            Ok(micros) => wx::util::get_system_micros().saturating_sub(micros),
+            Err(_) => return CompactionDecision::Keep,
+        };
+        if ttl_micros.is_some_and(|ttl_micros| age > ttl_micros) {
+            return CompactionDecision::Remove;
+        }
+        let retention = retention.read().unwrap();
+        let min_retention = retention.iter().map(|(_, micros)| *micros).min();
+        if min_retention.is_none_or(|min_retention| age <= min_retention) {
+            return CompactionDecision::Keep;
+        }
+
+        let value = if event_checksums {
+            match verify_checksum(value) {
+                Some(event) => event,
+                None => return CompactionDecision::Keep,
+            }
+        } else {
+            value
+        };
+        let value = match &cipher {
+            Some(cipher) => cipher.decrypt(value),
+            None => Some(Cow::Borrowed(value)),
+        };
+        let event_type = match value.and_then(|value| deserialize::<Event>(&value).ok()) {
+            Some(event) => serialize(&event.event_type).unwrap(),
+            None => return CompactionDecision::Keep,
+        };
+
+        match retention
+            .iter()
+            .find(|(retained, _)| *retained == event_type)
+        {
+            Some((_, micros)) if age > *micros => CompactionDecision::Remove,
+            _ => CompactionDecision::Keep,
+        }
+    }
+}
+
+/// Wraps concatenated values in an envelope of a u64 count followed by the values, which clients
+/// deserialize as a `Vec`.
+fn envelope(count: u64, buffer: &[u8]) -> Vec<u8> {
+    let mut envelope = Vec::new();
+    envelope.extend_from_slice(&count.to_le_bytes());
+    envelope.extend_from_slice(buffer);
+
+    envelope
+}
+
+/// Reads every value from the iterator's current position onwards into an envelope.
+fn read_envelope(iter: &mut DBRawIterator) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    let mut count: u64 = 0;
+
+    while iter.valid() {
+        let value = iter.value().unwrap();
+        buffer.extend_from_slice(value);
+        count += 1;
+        iter.next();
+    }
+
+    envelope(count, &buffer)
+}
+
+/// Held for the whole of a streaming read. Its iterators pin SST files against compaction cleanup
+/// for as long as it's open, so one held longer than `long_iterator_threshold` is logged as it
+/// closes.
+struct Stream<'a> {
+    store: &'a Store,
+    command: Command,
+    opened: Instant,
+    _permit: ReadPermit<'a>,
+}
+
+impl<'a> Drop for Stream<'a> {
+    fn drop(&mut self) {
+        let held = self.opened.elapsed();
+        if held > self.store.long_stream_threshold {
+            warn!(self.store.logger, "stream";
+                "msg" => "long-lived streaming read", "command" => format!("{:?}", self.command),
+                "held_millis" => held.as_millis() as u64);
+        }
     }
 }
 
 pub struct Store {
-    db: DB,
+    db: Arc<DB>,
+    /// Kept for the statistics RocksDB collects through them.
+    opts: Arc<Options>,
     event_threshold_micros: u64,
+    fetch_failure_threshold_micros: u64,
+    compression_type: &'static str,
+    /// The `KeyEncoding` new event keys are written in, which only changes once a key migration
+    /// finishes. See `migrate_key_encoding`.
+    key_encoding: AtomicU8,
+    /// Set while a key migration has left events stored under both encodings.
+    migrating: AtomicBool,
+    ttl_expiry: bool,
+    import_batch_size: usize,
+    retry: Retry,
+    /// The last sequence number assigned. Held by every write to the events column family,
+    /// including deletes, so `rekey_events` and `migrate_key_encoding` can move events with
+    /// nothing landing in between.
+    sequence: Mutex<u64>,
+    /// The last ingest micros assigned to a new event, so assigned keys keep increasing even when
+    /// the clock repeats or steps back. Only read and written under the sequence lock.
+    last_micros: AtomicU64,
+    /// The newest key micros `rekey_events` has moved an event to, or 0. Keys after it always
+    /// match their event's ingest_ts, so they're never looked up. Only raised, and only under the
+    /// sequence lock.
+    rekeyed_through: AtomicU64,
+    /// Counts event writes, signaled after each so `wait_for_event` wakes when something lands.
+    event_writes: (Mutex<u64>, Condvar),
+    write_rate: WriteRate,
+    /// Serializes polls, so concurrent polls by one consumer can't both return the same events.
+    polls: Mutex<()>,
+    redacted_fields: Vec<String>,
+    /// The policy in force. See `set_retention_policy`.
+    retention: Mutex<RetentionPolicy>,
+    /// Its per-type rules, as read by the events compaction filter.
+    type_retention: TypeRetention,
+    /// Held by every write to a KV key, including swaps, deletes by prefix, and the write probe, so
+    /// `compare_and_swap` and `swap` can read and write keys with nothing landing in between. Keys
+    /// share locks by hash. See `kv_lock` and `kv_locks_for`.
+    kv_locks: Vec<Mutex<()>>,
+    /// Held while annotating an event, from checking it exists to writing the note, so notes
+    /// added to one event at once get distinct keys. Separate from `kv_locks`, so annotating never
+    /// waits on KV writes.
+    annotation_locks: Vec<Mutex<()>>,
+    /// Serializes claims, so no two claimants can take the same event.
+    claims: Mutex<()>,
+    max_key_length: usize,
+    max_sample_size: usize,
+    slow_request_micros: u64,
+    error_detail: bool,
+    event_checksums: bool,
+    cipher: Option<Arc<Cipher>>,
+    envelope_version: bool,
+    readers: ReadLimiter,
+    /// Permits for streaming reads, which are rejected rather than queued when none are free.
+    streams: ReadLimiter,
+    long_stream_threshold: Duration,
+    logger: Logger,
+    audit_log: Option<AuditLog>,
+    requests: AtomicU64,
+    errors: AtomicU64,
+    /// Failed requests by command name and kind of error, for `error_stats`.
+    error_counts: Mutex<BTreeMap<(String, String), u64>>,
+    started: Instant,
+    shutdown: Arc<AtomicBool>,
+    /// While set, client writes are rejected. See `pause`.
+    paused: AtomicBool,
+    /// Staging store path accepted by `swap_store`, waiting for the host to swap it in, and whether
+    /// writes were already paused when it was accepted.
+    pending_swap: Mutex<Option<(String, bool)>>,
+    warmup_duration: Option<Duration>,
+    workers: Vec<JoinHandle<()>>,
 }
 
 impl Store {
-    pub fn new(
-        path: &str,
-        event_threshold_micros: u64,
-    ) -> Store {
+    pub fn new(path: &str, event_threshold_micros: u64) -> Store {
+        let config = Config {
+            event_threshold_micros,
+            ..Config::default()
+        };
+
+        Store::with_config(path, config)
+    }
+
+    /// Like `open`, but panics if the store can't be opened.
+    pub fn with_config(path: &str, config: Config) -> Store {
+        Store::open(path, config).unwrap()
+    }
+
+    /// Opens the store at `path`, creating it if it's missing, and migrates anything written by
+    /// older builds before returning. Fails if the store can't be opened, its format can't be
+    /// read, or it was written by a newer schema version.
+    pub fn open(path: &str, config: Config) -> Result<Store, Error> {
+        if let Some(field) = config
+            .redacted_fields
+            .iter()
+            .find(|field| !REDACTABLE_FIELDS.contains(&field.as_str()))
+        {
+            return Err(wx_error(&format!("unknown redacted field: {}", field)));
+        }
         let mut opts = Options::default();
         opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
         opts.enable_statistics();
-        opts.set_compression_type(DBCompressionType::Lz4hc);
-        let db = DB::open(&opts, path).unwrap();
+        opts.set_compression_type(config.compression_type);
+        // Memtables of every column family are flushed together, so the SST files on disk never
+        // hold half of a write batch, whether or not the WAL is there to replay the rest
+        opts.set_atomic_flush(true);
+        let compression_type = config.compression_name();
+        if let Some(size) = config.max_total_wal_size {
+            opts.set_max_total_wal_size(size);
+        }
+        if let Some(size) = config.wal_size_limit_mb {
+            opts.set_wal_size_limit_mb(size);
+        }
+        if let Some(num) = config.recycle_log_file_num {
+            opts.set_recycle_log_file_num(num);
+        }
+        opts.set_use_direct_reads(config.direct_reads);
+        opts.set_use_direct_io_for_flush_and_compaction(config.direct_io_for_flush_and_compaction);
+        if let Some(jobs) = config.max_background_jobs {
+            opts.set_max_background_jobs(jobs);
+        }
+        if config.event_memtable != Memtable::SkipList {
+            // Only the skiplist supports concurrent writes
+            opts.set_allow_concurrent_memtable_write(false);
+        }
+        if let Some(bytes_per_sec) = config.rate_limit_bytes_per_sec {
+            opts.set_ratelimiter(bytes_per_sec, RATE_LIMIT_REFILL_MICROS, RATE_LIMIT_FAIRNESS);
+        }
+        // Loaded before opening, since the type retention filter needs it to read events
+        let cipher = config
+            .encryption_key_path
+            .as_ref()
+            .map(|path| Cipher::from_key_file(path).map(Arc::new))
+            .transpose()
+            .map_err(|e| wx_error(&e.to_string()))?;
+        let audit_log = config
+            .audit_log_path
+            .as_ref()
+            .map(|path| AuditLog::open(path.clone(), config.audit_log_max_bytes))
+            .transpose()
+            .map_err(|e| wx_error(&e.to_string()))?;
+        // Any stored policy is only readable once open, and replaces these before serving requests
+        let type_retention: TypeRetention = Arc::new(RwLock::new(
+            config
+                .type_retention
+                .iter()
+                .map(|(event_type, age)| (serialize(event_type).unwrap(), age.as_micros() as u64))
+                .collect(),
+        ));
+        let db = config
+            .open_retry
+            .run_if(
+                || {
+                    let cfs: Vec<_> = COLUMN_FAMILIES
+                        .iter()
+                        .map(|name| {
+                            cf_descriptor(name, &config, cipher.clone(), type_retention.clone())
+                        })
+                        .collect();
+                    DB::open_cf_descriptors(&opts, path, cfs)
+                },
+                |e| {
+                    let lock_held = is_lock_error(e);
+                    if lock_held {
+                        warn!(config.logger, "open";
+                            "msg" => "DB lock held, retrying", "path" => path);
+                    }
+                    lock_held
+                },
+            )
+            .map_err(convert_error)?;
+        let db = Arc::new(db);
+        let sequence = Mutex::new(last_sequence(&db));
+        let rekeyed_through = AtomicU64::new(rekeyed_through(&db));
+        let opts = Arc::new(opts);
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let mut workers = Vec::new();
+
+        if let Some(stats_dump_path) = config.stats_dump_path {
+            workers.push(background::spawn_stats_dump(
+                db.clone(),
+                opts.clone(),
+                stats_dump_path,
+                config.stats_dump_interval,
+                config.stats_dump_max_bytes,
+                shutdown.clone(),
+                config.logger.clone(),
+            ));
+        }
+
+        if let Some(interval) = config.auto_flush_interval {
+            workers.push(background::spawn_auto_flush(
+                db.clone(),
+                interval,
+                shutdown.clone(),
+                config.logger.clone(),
+            ));
+        }
 
-        Store {
+        let mut store = Store {
             db,
-            event_threshold_micros,
+            opts,
+            event_threshold_micros: config.event_threshold_micros,
+            fetch_failure_threshold_micros: config.fetch_failure_threshold_micros,
+            compression_type,
+            key_encoding: AtomicU8::new(config.key_encoding as u8),
+            migrating: AtomicBool::new(false),
+            ttl_expiry: config.ttl_expiry,
+            import_batch_size: config.import_batch_size,
+            retry: config.write_retry,
+            sequence,
+            last_micros: AtomicU64::new(0),
+            rekeyed_through,
+            event_writes: (Mutex::new(0), Condvar::new()),
+            write_rate: WriteRate::new(),
+            polls: Mutex::new(()),
+            redacted_fields: config.redacted_fields,
+            retention: Mutex::new(RetentionPolicy {
+                type_retention: config
+                    .type_retention
+                    .into_iter()
+                    .map(|(event_type, age)| (event_type, age.as_micros() as u64))
+                    .collect(),
+                max_events: None,
+                max_bytes: None,
+            }),
+            type_retention,
+            claims: Mutex::new(()),
+            kv_locks: (0..KV_LOCK_STRIPES).map(|_| Mutex::new(())).collect(),
+            annotation_locks: (0..ANNOTATION_LOCK_STRIPES)
+                .map(|_| Mutex::new(()))
+                .collect(),
+            max_key_length: config.max_key_length,
+            max_sample_size: config.max_sample_size,
+            slow_request_micros: config.slow_request_threshold.as_micros() as u64,
+            error_detail: config.error_detail,
+            event_checksums: config.event_checksums,
+            cipher,
+            envelope_version: config.envelope_version,
+            readers: ReadLimiter::new(config.max_concurrent_readers),
+            streams: ReadLimiter::new(config.max_open_iterators),
+            long_stream_threshold: config.long_iterator_threshold,
+            audit_log,
+            logger: config.logger,
+            requests: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            error_counts: Mutex::new(BTreeMap::new()),
+            started: Instant::now(),
+            shutdown,
+            paused: AtomicBool::new(false),
+            pending_swap: Mutex::new(None),
+            warmup_duration: None,
+            workers,
+        };
+
+        store.migrate_metadata()?;
+        if let Some(value) = store.metadata(KEY_ENCODING_KEY)? {
+            let chosen = deserialize::<String>(&value)
+                .ok()
+                .and_then(|name| KeyEncoding::from_name(&name))
+                .ok_or_else(|| wx_error("invalid stored key encoding"))?;
+            if chosen != store.key_encoding() {
+                warn!(store.logger, "open";
+                    "msg" => "using the key encoding chosen by MigrateKeyEncoding, not the config",
+                    "key_encoding" => chosen.name(), "configured" => store.key_encoding().name());
+                store.set_key_encoding(chosen);
+            }
+        }
+        if store.metadata(FORMAT_KEY)?.is_none() {
+            let moved = store.migrate_legacy_events()?;
+            if moved > 0 {
+                info!(store.logger, "open";
+                    "msg" => "moved events out of the default column family", "events" => moved);
+            }
+        }
+        let (schema_version, key_encoding) = store.detect_format()?;
+        if schema_version > SCHEMA_VERSION {
+            return Err(wx_error(&format!(
+                "store has schema version {}, but this build only reads up to {}",
+                schema_version, SCHEMA_VERSION
+            )));
+        }
+        let migration = store.metadata(MIGRATION_KEY)?;
+        if key_encoding != store.key_encoding() {
+            let migrated = store.migrate_event_keys()?;
+            info!(store.logger, "open";
+                "msg" => "migrated event keys", "events" => migrated,
+                "key_encoding" => store.key_encoding().name());
+        }
+        if migration.is_some() && key_encoding == store.key_encoding() {
+            // Picks up an unfinished MigrateKeyEncoding where it left off, so the stored format
+            // stays as it was until it finishes
+            store.set_key_encoding(KeyEncoding::Decimal);
+            store.migrating.store(true, Ordering::SeqCst);
+            info!(store.logger, "open"; "msg" => "resuming key migration");
+        } else {
+            store.write_format()?;
+        }
+
+        let migrated = store.migrate_fetch_failure_keys()?;
+        if migrated > 0 {
+            info!(store.logger, "open";
+                "msg" => "migrated fetch failure keys", "fetch_failures" => migrated);
+        }
+
+        if let Some(value) = store.metadata(RETENTION_KEY)? {
+            let policy =
+                deserialize(&value).map_err(|_| wx_error("invalid stored retention policy"))?;
+            store.apply_retention_policy(policy);
         }
+
+        let indexed = store.backfill_type_index()?;
+        if indexed > 0 {
+            info!(store.logger, "open"; "msg" => "indexed event types", "events" => indexed);
+        }
+
+        if config.warmup {
+            store.warmup_duration = Some(store.warm_up(config.warmup_max_events));
+        }
+
+        Ok(store)
     }
 
-    pub fn put(&self, payload: &[u8]) -> Result<Vec<u8>, rocksdb::Error> {
-        let kv: (&str, &[u8]) = deserialize(payload).unwrap();
-        let key = kv.0.as_bytes();
-        self.db.put(&key, kv.1)?;
+    fn metadata(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.db
+            .get_cf(self.cf(METADATA_CF), key)
+            .map(|value| value.map(|value| value.to_vec()))
+            .map_err(convert_error)
+    }
 
-        Ok(key.to_vec())
+    /// Moves records that older builds kept in the default column family, where KV commands could
+    /// reach them, into the metadata column family.
+    fn migrate_metadata(&self) -> Result<(), Error> {
+        let mut batch = WriteBatch::default();
+
+        for key in [FORMAT_KEY, MIGRATION_KEY, RETENTION_KEY].iter() {
+            if let Some(value) = self.db.get(key).map_err(convert_error)? {
+                batch.put_cf(self.cf(METADATA_CF), key, &value[..]);
+                batch.delete(key);
+            }
+        }
+
+        self.db.write(batch).map_err(convert_error)
     }
 
-    pub fn get(&self, key: &[u8]) -> Result<Vec<u8>, rocksdb::Error> {
-        match self.db.get(key)? {
-            Some(value) => Ok(value.to_vec()),
-            None => Ok(vec![]),
+    /// Moves events into the events column family from the default one, where stores from before
+    /// it existed kept them under decimal keys. Those keys share the default column family with
+    /// free-form KV pairs, so a key is only taken to be an event if it's all digits and holds an
+    /// event with that ingest_ts. Returns the number of events moved.
+    fn migrate_legacy_events(&self) -> Result<u64, Error> {
+        let cf = self.cf(EVENTS_CF);
+        let mut iter = self.db.raw_iterator();
+        let mut batch = WriteBatch::default();
+        let mut totals = Vec::new();
+        let mut moved: u64 = 0;
+        iter.seek_to_first();
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            let micros = Some(key)
+                .filter(|key| KeyEncoding::Decimal.matches(key))
+                .and_then(|key| parse_key(key).ok());
+            let event = micros.and_then(|micros| {
+                deserialize::<Event>(value)
+                    .ok()
+                    .filter(|event| event.ingest_ts == micros)
+            });
+            if let Some(event) = event {
+                batch.delete(key);
+                batch.put_cf(cf, key, self.encode_event(&event));
+                self.count_event(&mut batch, &mut totals, &event.event_type)
+                    .map_err(convert_error)?;
+                moved += 1;
+            }
+            iter.next();
         }
+
+        self.db.write(batch).map_err(convert_error)?;
+
+        Ok(moved)
     }
 
-    /**
-     * To get lexigraphical sorting to work the intended way, we use the bytes of a stringified
-     * u64 as the key. This is an internal quirk that we don't want to expose, which is why we
-     * return u64 bytes and not the actual key used.
-     */
-    pub fn put_event(&self, value: &[u8]) -> Result<Vec<u8>, rocksdb::Error> {
-        let micros = wx::util::get_system_micros();
-        let key = micros.to_string();
-        let mut event: Event = deserialize(&value).unwrap();
-        event.ingest_ts = micros;
-        let value = serialize(&event).unwrap();
-        self.db.put(&key.as_bytes(), &value)?;
+    /// The schema version and key encoding the store's events were written with. Stores from before
+    /// the format was recorded all hold version 1 events, so only their key encoding has to be
+    /// inferred, from the first key. An empty store is taken to be in the configured format.
+    fn detect_format(&self) -> Result<(u32, KeyEncoding), Error> {
+        if let Some(value) = self.metadata(FORMAT_KEY)? {
+            let (schema_version, key_encoding): (u32, String) =
+                deserialize(&value).map_err(|_| wx_error("invalid stored format"))?;
+            let key_encoding = KeyEncoding::from_name(&key_encoding)
+                .ok_or_else(|| wx_error("invalid stored key encoding"))?;
+            return Ok((schema_version, key_encoding));
+        }
 
-        let micros_bytes = serialize(&micros).unwrap();
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        iter.seek_to_first();
+        let key_encoding = match iter.key() {
+            Some(key) if KeyEncoding::BigEndian.matches(key) => KeyEncoding::BigEndian,
+            Some(_) => KeyEncoding::Decimal,
+            None => self.key_encoding(),
+        };
 
-        Ok(micros_bytes)
+        Ok((1, key_encoding))
     }
 
-    pub fn get_events(&self, key: &[u8], get_all: bool) -> Result<Vec<u8>, rocksdb::Error> {
-        let mut buffer = Vec::new();
-        let mut count: u64 = 0;
-        let mut iter = self.db.raw_iterator();
+    /// Records the format this build writes, once any migration on open has finished.
+    fn write_format(&self) -> Result<(), Error> {
+        let format = serialize(&(SCHEMA_VERSION, self.key_encoding().name())).unwrap();
+        self.db
+            .put_cf(self.cf(METADATA_CF), FORMAT_KEY, format)
+            .map_err(convert_error)
+    }
 
-        if get_all {
-            iter.seek_to_first()
-        } else if key.is_empty() {
-            let micros = wx::util::get_system_micros() - self.event_threshold_micros;
-            let micros = micros.to_string();
-            iter.seek(&micros.as_bytes());
-        } else {
-            // If the key is still valid and would be returned, then we need to skip it since
-            // it has already been seen by the requester.
-            let key: &str = deserialize(&key).unwrap(); // TODO this panics
-            let key = key.parse::<u64>().unwrap();
-            let key = key + 1;
-            let key = key.to_string();
-            iter.seek(&key.as_bytes());
+    /// Rewrites events stored under the other key encoding into the configured one, so switching
+    /// `key_encoding` on an existing store is just a restart. Sequence entries follow their events.
+    /// It's written as a single batch before any requests are served, so readers never see a mix
+    /// of encodings. This also finishes off any unfinished `migrate_key_encoding` to the configured
+    /// encoding. Returns the number of events migrated.
+    fn migrate_event_keys(&self) -> Result<u64, Error> {
+        let other = self.key_encoding().other();
+        let cf = self.cf(EVENTS_CF);
+        let mut iter = self.db.raw_iterator_cf(cf);
+        let mut batch = WriteBatch::default();
+        let mut moved: u64 = 0;
+        iter.seek_to_first();
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if other.matches(key) {
+                let new_key = self.key_encoding().encode(other.decode(key)?);
+                batch.delete_cf(cf, key);
+                batch.put_cf(cf, &new_key, value);
+                if let Some(event) = self.read_event(key, value) {
+                    batch.delete_cf(self.cf(EVENT_TYPES_CF), type_key(&event.event_type, key));
+                    self.index_event(&mut batch, &event.event_type, &new_key);
+                }
+                moved += 1;
+            }
+            iter.next();
         }
 
+        self.reencode_sequence(&mut batch, other)?;
+        batch.delete_cf(self.cf(METADATA_CF), MIGRATION_KEY);
+        self.db.write(batch).map_err(convert_error)?;
+
+        Ok(moved)
+    }
+
+    /// Points sequence entries still holding keys in the `from` encoding at their events' keys in
+    /// the other one.
+    fn reencode_sequence(&self, batch: &mut WriteBatch, from: KeyEncoding) -> Result<(), Error> {
+        let sequence_cf = self.cf(SEQUENCE_CF);
+        let mut iter = self.db.raw_iterator_cf(sequence_cf);
+        iter.seek_to_first();
+
         while iter.valid() {
-            let value = unsafe { iter.value_inner().unwrap() };
-            buffer.extend_from_slice(&value);
-            count += 1;
+            let (sequence, key) = (iter.key().unwrap(), iter.value().unwrap());
+            if from.matches(key) {
+                let new_key = from.other().encode(from.decode(key)?);
+                batch.put_cf(sequence_cf, sequence, new_key);
+            }
+            iter.next();
+        }
+
+        Ok(())
+    }
+
+    /// Rewrites fetch failures stored under the decimal keys of older builds into big-endian ones.
+    /// Decimal keys sort after every big-endian one, so there's nothing to do unless the last key
+    /// is decimal. Returns the number of fetch failures migrated.
+    fn migrate_fetch_failure_keys(&self) -> Result<u64, Error> {
+        let cf = self.cf(FETCH_FAILURES_CF);
+        let mut iter = self.db.raw_iterator_cf(cf);
+        iter.seek_to_last();
+        if !iter
+            .key()
+            .is_some_and(|key| KeyEncoding::Decimal.matches(key))
+        {
+            return Ok(0);
+        }
+
+        let mut batch = WriteBatch::default();
+        let mut moved: u64 = 0;
+        iter.seek_to_first();
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if KeyEncoding::Decimal.matches(key) {
+                let new_key = KeyEncoding::BigEndian.encode(KeyEncoding::Decimal.decode(key)?);
+                batch.delete_cf(cf, key);
+                batch.put_cf(cf, &new_key, value);
+                moved += 1;
+            }
+            iter.next();
+        }
+
+        self.db.write(batch).map_err(convert_error)?;
+
+        Ok(moved)
+    }
+
+    /// Builds the type index for stores written before it existed. It's only needed once, so
+    /// this does nothing if anything has been indexed. Returns the number of events indexed.
+    fn backfill_type_index(&self) -> Result<u64, Error> {
+        let mut index = self.db.raw_iterator_cf(self.cf(EVENT_TYPES_CF));
+        index.seek_to_first();
+        if index.valid() {
+            return Ok(0);
+        }
+
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        let mut batch = WriteBatch::default();
+        let mut indexed: u64 = 0;
+        iter.seek_to_first();
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if let Some(event) = self.read_event(key, value) {
+                self.index_event(&mut batch, &event.event_type, key);
+                indexed += 1;
+            }
             iter.next();
         }
 
-        let mut events_envelope = Vec::new();
-        let count: [u8; 8] = unsafe { mem::transmute(count) };
-        events_envelope.extend_from_slice(&count);
-        events_envelope.extend_from_slice(&buffer);
+        self.db.write(batch).map_err(convert_error)?;
+
+        Ok(indexed)
+    }
 
-        Ok(events_envelope)
+    /// The most event reads that have been in flight at once, never more than the configured
+    /// `max_concurrent_readers`.
+    pub fn peak_concurrent_readers(&self) -> usize {
+        self.readers.peak()
+    }
+
+    fn record_error(&self, command: Option<Command>, e: &Error) {
+        self.errors.fetch_add(1, Ordering::SeqCst);
+        let command = command.map_or_else(|| String::from("unknown"), |c| format!("{:?}", c));
+        let mut counts = self.error_counts.lock().unwrap();
+        *counts.entry((command, error_kind(e))).or_insert(0) += 1;
+    }
+
+    /// Appends to the audit log, if enabled. Failures are logged rather than failing the request.
+    fn audit(
+        &self,
+        trace_id: &str,
+        command: Option<u8>,
+        payload_bytes: usize,
+        ok: bool,
+        latency_micros: u64,
+    ) {
+        if let Some(audit_log) = &self.audit_log {
+            let entry = AuditEntry {
+                micros: wx::util::get_system_micros(),
+                trace_id,
+                command,
+                payload_bytes,
+                result: if ok { "ok" } else { "error" },
+                latency_micros,
+            };
+
+            if let Err(e) = audit_log.record(&entry) {
+                error!(self.logger, "audit"; "msg" => e.to_string());
+            }
+        }
+    }
+
+    /// How long the optional startup warmup took, if it ran.
+    pub fn warmup_duration(&self) -> Option<Duration> {
+        self.warmup_duration
+    }
+
+    /// Reads through the recent event window so the first `get_events` calls after a restart are
+    /// served from a warm block cache. Bounded so it can't delay startup excessively.
+    fn warm_up(&self, max_events: usize) -> Duration {
+        let start = Instant::now();
+
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        self.seek_window(&mut iter);
+        let mut count = 0;
+
+        while iter.valid() && count < max_events {
+            let _ = iter.value();
+            count += 1;
+            iter.next();
+        }
+
+        start.elapsed()
+    }
+
+    /// Admits a streaming read, which iterates for far longer than other reads. Rather than
+    /// queueing behind the others, it's rejected once `max_open_iterators` are open.
+    fn open_stream(&self, command: Command) -> Result<Stream<'_>, Error> {
+        let permit = self
+            .streams
+            .try_acquire()
+            .ok_or_else(|| wx_error("too many open iterators"))?;
+
+        Ok(Stream {
+            store: self,
+            command,
+            opened: Instant::now(),
+            _permit: permit,
+        })
+    }
+
+    fn cf(&self, name: &str) -> &ColumnFamily {
+        self.db.cf_handle(name).unwrap()
+    }
+
+    fn key_encoding(&self) -> KeyEncoding {
+        if self.key_encoding.load(Ordering::SeqCst) == KeyEncoding::BigEndian as u8 {
+            KeyEncoding::BigEndian
+        } else {
+            KeyEncoding::Decimal
+        }
+    }
+
+    fn set_key_encoding(&self, key_encoding: KeyEncoding) {
+        self.key_encoding
+            .store(key_encoding as u8, Ordering::SeqCst);
+    }
+
+    /// The encoding of a stored event key, which during a key migration may not be the current one.
+    fn stored_encoding(&self, key: &[u8]) -> KeyEncoding {
+        if self.migrating.load(Ordering::SeqCst) {
+            KeyEncoding::of(key)
+        } else {
+            self.key_encoding()
+        }
+    }
+
+    fn decode_key(&self, key: &[u8]) -> Result<u64, Error> {
+        self.stored_encoding(key).decode(key)
+    }
+
+    /// A stored event key for logs, as its micros, or in the readable form of its encoding if it
+    /// won't decode, since big-endian keys are mostly unprintable bytes.
+    fn display_key(&self, key: &[u8]) -> String {
+        match self.decode_key(key) {
+            Ok(micros) => micros.to_string(),
+            Err(_) => self.stored_encoding(key).display(key),
+        }
+    }
+
+    /// The ingest_ts of the event stored under a key, which is the key's micros unless
+    /// `rekey_events` moved the event there.
+    fn key_ingest_ts(&self, key: &[u8]) -> Result<u64, Error> {
+        let micros = self.decode_key(key)?;
+        if micros > self.rekeyed_through.load(Ordering::SeqCst) {
+            return Ok(micros);
+        }
+
+        match self
+            .db
+            .get_cf(self.cf(KEY_INGEST_TS_CF), micros.to_be_bytes())
+            .map_err(convert_error)?
+        {
+            Some(ingest_ts) => parse_sequence(&ingest_ts),
+            None => Ok(micros),
+        }
+    }
+
+    /// The micros of the key an event with this ingest_ts is stored under, or None if there can't
+    /// be one, because `rekey_events` moved the event and it's since been deleted, or moved
+    /// another event into its key.
+    fn ingest_ts_key(&self, ingest_ts: u64) -> Result<Option<u64>, Error> {
+        if ingest_ts > self.rekeyed_through.load(Ordering::SeqCst) {
+            return Ok(Some(ingest_ts));
+        }
+
+        let moved_to = self
+            .db
+            .get_cf(self.cf(INGEST_TS_KEYS_CF), ingest_ts.to_be_bytes())
+            .map_err(convert_error)?
+            .map(|micros| parse_sequence(&micros))
+            .transpose()?;
+        let micros = moved_to.unwrap_or(ingest_ts);
+        let moved_from = self
+            .db
+            .get_cf(self.cf(KEY_INGEST_TS_CF), micros.to_be_bytes())
+            .map_err(convert_error)?
+            .map(|ingest_ts| parse_sequence(&ingest_ts))
+            .transpose()?;
+
+        // Both lookups must agree, since the entries of an event compaction expired, or that was
+        // deleted unreadable, outlive it
+        Ok(match (moved_to, moved_from) {
+            (None, None) => Some(ingest_ts),
+            (Some(_), Some(moved_from)) if moved_from == ingest_ts => Some(micros),
+            _ => None,
+        })
+    }
+
+    /// Every key an event with this ingest_ts could be stored under.
+    fn event_keys(&self, ingest_ts: u64) -> Result<Vec<Vec<u8>>, Error> {
+        let micros = match self.ingest_ts_key(ingest_ts)? {
+            Some(micros) => micros,
+            None => return Ok(Vec::new()),
+        };
+        let key_encoding = self.key_encoding();
+        let mut keys = vec![key_encoding.encode(micros)];
+        if self.migrating.load(Ordering::SeqCst) {
+            keys.push(key_encoding.other().encode(micros));
+        }
+
+        Ok(keys)
+    }
+
+    /// Positions an iterator at the first key after `prefix` holding an event ingested at or after
+    /// `micros`. While a key migration has left both encodings in place, big-endian keys always
+    /// belong to older events than decimal keys, matching how they sort, so decimal keys are only
+    /// sought once there are no big-endian keys left at or after `micros`.
+    fn seek_micros(&self, iter: &mut DBRawIterator, prefix: &[u8], micros: u64) {
+        self.seek_key_micros(iter, prefix, micros);
+
+        // Events `rekey_events` moved sit at keys after their ingest_ts, but still in ingest
+        // order, so any older than sought come first
+        if micros <= self.rekeyed_through.load(Ordering::SeqCst) {
+            while let Some(key) = iter.key() {
+                if !key.starts_with(prefix) {
+                    break;
+                }
+                match self.key_ingest_ts(&key[prefix.len()..]) {
+                    Ok(ingest_ts) if ingest_ts < micros => iter.next(),
+                    _ => break,
+                }
+            }
+        }
+    }
+
+    /// Positions an iterator at the first key after `prefix` holding an event key at or after
+    /// `micros`, whatever the ingest_ts of the event stored there.
+    fn seek_key_micros(&self, iter: &mut DBRawIterator, prefix: &[u8], micros: u64) {
+        if !self.migrating.load(Ordering::SeqCst) {
+            iter.seek([prefix, &self.key_encoding().encode(micros)].concat());
+            return;
+        }
+
+        iter.seek([prefix, &KeyEncoding::BigEndian.encode(micros)].concat());
+        let found = match iter.key() {
+            Some(key) => {
+                key.starts_with(prefix) && KeyEncoding::BigEndian.matches(&key[prefix.len()..])
+            }
+            None => false,
+        };
+        if !found {
+            iter.seek([prefix, &KeyEncoding::Decimal.encode(micros)].concat());
+        }
+    }
+
+    /// Positions an events iterator at the start of the window defined by the event threshold.
+    fn seek_window(&self, iter: &mut DBRawIterator) {
+        let micros = wx::util::get_system_micros() - self.event_threshold_micros;
+        self.seek_micros(iter, &[], micros);
+    }
+
+    /// Positions an events iterator at the first event newer than the cursor, which is the
+    /// ingest_ts of the last event the requester has seen.
+    fn seek_after(&self, iter: &mut DBRawIterator, cursor: u64) {
+        self.seek_micros(iter, &[], cursor + 1);
+    }
+
+    /// Serializes an event for storage, prepending a CRC32 of the serialized bytes when checksums
+    /// are enabled.
+    fn encode_event(&self, event: &Event) -> Vec<u8> {
+        let value = serialize(event).unwrap();
+        let value = self.encrypt(&value).into_owned();
+        if !self.event_checksums {
+            return value;
+        }
+
+        let mut encoded = Vec::with_capacity(CHECKSUM_LEN + value.len());
+        encoded.extend_from_slice(&crc32fast::hash(&value).to_le_bytes());
+        encoded.extend_from_slice(&value);
+
+        encoded
+    }
+
+    /// Returns the serialized event from a stored value, or None if it fails its checksum, in
+    /// which case the corrupt record is logged so it can be skipped.
+    fn decode_event<'a>(&self, key: &[u8], value: &'a [u8]) -> Option<Cow<'a, [u8]>> {
+        let event = if self.event_checksums {
+            match verify_checksum(value) {
+                Some(event) => event,
+                None => {
+                    warn!(self.logger, "checksum"; "msg" => "skipping corrupt event",
+                        "key" => self.display_key(key));
+                    return None;
+                }
+            }
+        } else {
+            value
+        };
+
+        let decrypted = self.decrypt(event);
+        if decrypted.is_none() {
+            warn!(self.logger, "encryption"; "msg" => "skipping undecryptable event",
+                "key" => self.display_key(key));
+        }
+
+        decrypted
+    }
+
+    /// Encrypts a value for storage if encryption at rest is enabled.
+    fn encrypt<'a>(&self, value: &'a [u8]) -> Cow<'a, [u8]> {
+        match &self.cipher {
+            Some(cipher) => Cow::Owned(cipher.encrypt(value)),
+            None => Cow::Borrowed(value),
+        }
+    }
+
+    /// Decrypts a stored value if encryption at rest is enabled. Values written before it was
+    /// enabled are returned as they are. None if an encrypted value fails authentication.
+    fn decrypt<'a>(&self, value: &'a [u8]) -> Option<Cow<'a, [u8]>> {
+        match &self.cipher {
+            Some(cipher) => cipher.decrypt(value),
+            None => Some(Cow::Borrowed(value)),
+        }
+    }
+
+    fn read_event(&self, key: &[u8], value: &[u8]) -> Option<Event> {
+        self.read_event_with_bytes(key, value)
+            .map(|(_, event)| event)
+    }
+
+    /// Returns both the serialized event from a stored value and the event itself, or None if it
+    /// fails its checksum or won't deserialize, in which case it's logged so it can be skipped.
+    fn read_event_with_bytes<'a>(
+        &self,
+        key: &[u8],
+        value: &'a [u8],
+    ) -> Option<(Cow<'a, [u8]>, Event)> {
+        let value = self.decode_event(key, value)?;
+        match deserialize(&value) {
+            Ok(event) => Some((value, event)),
+            Err(_) => {
+                warn!(self.logger, "deserialize"; "msg" => "skipping unreadable event",
+                    "key" => self.display_key(key));
+                None
+            }
+        }
+    }
+
+    /// Reads every event from the iterator's current position onwards into an envelope, skipping
+    /// any that fail their checksum.
+    fn read_events(&self, iter: &mut DBRawIterator) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        let mut count: u64 = 0;
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if let Some(value) = self.decode_event(key, value) {
+                buffer.extend_from_slice(&value);
+                count += 1;
+            }
+            iter.next();
+        }
+
+        envelope(count, &buffer)
+    }
+
+    pub fn put(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        self.check_not_paused()?;
+        let kv: (&str, &[u8]) = self.parse_payload(payload, "invalid put")?;
+        let key = kv.0.as_bytes();
+        self.check_key(key)?;
+        let value = self.encrypt(kv.1);
+        let _lock = self.kv_lock(key);
+        self.retry
+            .run(|| self.db.put(key, &value))
+            .map_err(convert_error)?;
+
+        Ok(key.to_vec())
+    }
+
+    fn kv_lock(&self, key: &[u8]) -> MutexGuard<'_, ()> {
+        self.kv_locks[self.kv_stripe(key)].lock().unwrap()
+    }
+
+    /// Locks the stripes of several keys at once. Each stripe is locked once, in order, so writes
+    /// locking overlapping sets of keys can't deadlock.
+    fn kv_locks_for<'a, I>(&self, keys: I) -> Vec<MutexGuard<'_, ()>>
+    where
+        I: IntoIterator<Item = &'a [u8]>,
+    {
+        let stripes: BTreeSet<usize> = keys.into_iter().map(|key| self.kv_stripe(key)).collect();
+
+        stripes
+            .into_iter()
+            .map(|stripe| self.kv_locks[stripe].lock().unwrap())
+            .collect()
+    }
+
+    fn kv_stripe(&self, key: &[u8]) -> usize {
+        let mut hasher = XxHash64::default();
+        hasher.write(key);
+
+        hasher.finish() as usize % self.kv_locks.len()
+    }
+
+    /// Optimistic concurrency for coordination keys. The payload is a tuple of the key, the value
+    /// it's expected to hold, or None if it's expected to be absent, and the value to write. The
+    /// new value is only written if the current one matches, which is checked under the key's
+    /// lock, so no put or delete of the key can land in between. Returns whether it was swapped.
+    pub fn compare_and_swap(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        self.check_not_paused()?;
+        let (key, expected, new): (&str, Option<&[u8]>, &[u8]) =
+            self.parse_payload(payload, "invalid compare and swap")?;
+        let key = key.as_bytes();
+        self.check_key(key)?;
+        let value = self.encrypt(new);
+        let _lock = self.kv_lock(key);
+
+        let current = match self.db.get(key).map_err(convert_error)? {
+            Some(current) => Some(
+                self.decrypt(&current)
+                    .map(Cow::into_owned)
+                    .ok_or_else(|| wx_error("unable to decrypt value"))?,
+            ),
+            None => None,
+        };
+        let swapped = current.as_deref() == expected;
+        if swapped {
+            self.retry
+                .run(|| self.db.put(key, &value))
+                .map_err(convert_error)?;
+        }
+
+        Ok(serialize(&swapped).unwrap())
+    }
+
+    fn check_not_paused(&self) -> Result<(), Error> {
+        if self.paused.load(Ordering::SeqCst) {
+            return Err(wx_error("paused"));
+        }
+
+        Ok(())
+    }
+
+    /// Rejects keys clients may not write, before anything is written. Reserved keys belong to the
+    /// store, and over-long keys bloat index blocks and usually mean a client bug.
+    fn check_key(&self, key: &[u8]) -> Result<(), Error> {
+        if is_reserved_key(key) {
+            return Err(wx_error("reserved key"));
+        }
+        if key.len() > self.max_key_length {
+            return Err(wx_error(&format!(
+                "key length {} exceeds maximum of {}",
+                key.len(),
+                self.max_key_length
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// For debugging codec mismatches. The payload is a tuple of column family name and the key
+    /// as stored, so event keys are stringified micros. Returns the stored bytes uninterpreted,
+    /// including any checksum, as a length-prefixed `Vec<u8>`.
+    pub fn get_raw(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let (cf_name, key): (&str, &[u8]) = self.parse_payload(payload, "invalid raw key")?;
+        let value = match cf_name {
+            DEFAULT_CF => self.db.get(key),
+            _ if COLUMN_FAMILIES.contains(&cf_name) => self.db.get_cf(self.cf(cf_name), key),
+            _ => return Err(wx_error("unknown column family")),
+        }
+        .map_err(convert_error)?;
+        let value = value.map_or_else(Vec::new, |value| value.to_vec());
+
+        Ok(serialize(&value).unwrap())
+    }
+
+    pub fn get(&self, key: &[u8]) -> Result<Vec<u8>, Error> {
+        match self.db.get(key).map_err(convert_error)? {
+            Some(value) => self
+                .decrypt(&value)
+                .map(Cow::into_owned)
+                .ok_or_else(|| wx_error("unable to decrypt value")),
+            None => Ok(vec![]),
+        }
+    }
+
+    pub fn delete(&self, key: &[u8]) -> Result<Vec<u8>, Error> {
+        self.check_not_paused()?;
+        self.check_key(key)?;
+        let _lock = self.kv_lock(key);
+        self.db.delete(key).map_err(convert_error)?;
+
+        Ok(vec![])
+    }
+
+    /// Exchanges the values of two keys in a single write. The payload is a tuple of both keys and
+    /// a create-if-absent flag. When the flag is set, a missing key takes the other's value and the
+    /// other is deleted, otherwise a missing key is an error and nothing is written. Both keys stay
+    /// locked from read to write, so no other write to either can land in between.
+    pub fn swap(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        self.check_not_paused()?;
+        let (a, b, create_if_absent): (&str, &str, bool) =
+            self.parse_payload(payload, "invalid swap")?;
+        self.check_key(a.as_bytes())?;
+        self.check_key(b.as_bytes())?;
+        let _locks = self.kv_locks_for(vec![a.as_bytes(), b.as_bytes()]);
+        let a_value = self.db.get(a.as_bytes()).map_err(convert_error)?;
+        let b_value = self.db.get(b.as_bytes()).map_err(convert_error)?;
+
+        if !create_if_absent && (a_value.is_none() || b_value.is_none()) {
+            return Err(wx_error("key not found"));
+        }
+
+        let mut batch = WriteBatch::default();
+        for (key, value) in [(a, b_value), (b, a_value)].iter() {
+            match value {
+                Some(value) => batch.put(key.as_bytes(), &value[..]),
+                None => batch.delete(key.as_bytes()),
+            };
+        }
+        self.db.write(batch).map_err(convert_error)?;
+
+        Ok(vec![])
+    }
+
+    /// Deletes every key in the default column family starting with the given string prefix.
+    /// Events and fetch failures live in their own column families, so they're never touched, and
+    /// reserved keys are skipped. An empty prefix is rejected rather than deleting everything. The
+    /// matching keys can't be known until they're found, so every KV lock is held from the scan
+    /// to the write, and no put can land in between. Returns the number of keys deleted.
+    pub fn delete_by_prefix(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        self.check_not_paused()?;
+        let prefix: String = self.parse_payload(payload, "invalid prefix")?;
+        if prefix.is_empty() {
+            return Err(wx_error("invalid prefix"));
+        }
+        let prefix = prefix.as_bytes();
+        let _locks: Vec<_> = self
+            .kv_locks
+            .iter()
+            .map(|lock| lock.lock().unwrap())
+            .collect();
+        let mut keys = Vec::new();
+        let mut iter = self.db.raw_iterator();
+        iter.seek(prefix);
+
+        while iter.valid() {
+            let key = iter.key().unwrap();
+            if !key.starts_with(prefix) {
+                break;
+            }
+            if !is_reserved_key(key) {
+                keys.push(key.to_vec());
+            }
+            iter.next();
+        }
+
+        let mut batch = WriteBatch::default();
+        for key in keys.iter() {
+            batch.delete(key);
+        }
+        self.db.write(batch).map_err(convert_error)?;
+
+        Ok(serialize(&(keys.len() as u64)).unwrap())
+    }
+
+    /// Like `put`, but the key is scoped to an app id so that apps can't clobber each other's
+    /// keys. The payload is a tuple of app id, key, and value. Returns the key without the app id.
+    pub fn put_namespaced(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        self.check_not_paused()?;
+        let (app, key, value): (&str, &str, &[u8]) =
+            self.parse_payload(payload, "invalid namespaced put")?;
+        let namespaced = namespaced_key(app, key)?;
+        self.check_key(namespaced.as_bytes())?;
+        let value = self.encrypt(value);
+        let _lock = self.kv_lock(namespaced.as_bytes());
+        self.retry
+            .run(|| self.db.put(namespaced.as_bytes(), &value))
+            .map_err(convert_error)?;
+
+        Ok(key.as_bytes().to_vec())
+    }
+
+    /// The payload is a tuple of app id and key.
+    pub fn get_namespaced(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let (app, key): (&str, &str) = self.parse_payload(payload, "invalid namespaced key")?;
+        let namespaced = namespaced_key(app, key)?;
+        self.get(namespaced.as_bytes())
+    }
+
+    /// The payload is a tuple of app id and key.
+    pub fn delete_namespaced(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let (app, key): (&str, &str) = self.parse_payload(payload, "invalid namespaced key")?;
+        let namespaced = namespaced_key(app, key)?;
+        self.delete(namespaced.as_bytes())
+    }
+
+    /**
+     * Events are keyed by their ingest micros, in the configured `KeyEncoding`. This is an
+     * internal quirk that we don't want to expose, which is why we return u64 bytes and not the
+     * actual key used.
+     */
+    pub fn put_event(&self, value: &[u8]) -> Result<Vec<u8>, Error> {
+        self.check_not_paused()?;
+        let mut event: Event = self.parse_payload(value, "invalid event")?;
+        let cf = self.cf(EVENTS_CF);
+        let sequence_cf = self.cf(SEQUENCE_CF);
+
+        // Held until the write lands, so sequence numbers become visible in order, and taken
+        // before the key is assigned, so concurrent writers can't be given the same one
+        let mut sequence = self.sequence.lock().unwrap();
+        let micros = wx::util::get_system_micros().max(self.last_micros() + 1);
+        let key = self.key_encoding().encode(micros);
+        event.ingest_ts = micros;
+        let value = self.encode_event(&event);
+        let next = *sequence + 1;
+        self.retry
+            .run(|| {
+                let mut batch = WriteBatch::default();
+                batch.put_cf(cf, &key, &value);
+                batch.put_cf(sequence_cf, next.to_be_bytes(), &key);
+                self.index_event(&mut batch, &event.event_type, &key);
+                self.count_event(&mut batch, &mut Vec::new(), &event.event_type)?;
+                self.db.write(batch)
+            })
+            .map_err(convert_error)?;
+        *sequence = next;
+        self.last_micros.store(micros, Ordering::SeqCst);
+        drop(sequence);
+        self.notify_event_writes(1);
+
+        let micros_bytes = serialize(&micros).unwrap();
+
+        Ok(micros_bytes)
+    }
+
+    /// The last ingest micros assigned to a new event. Callers must hold the sequence lock.
+    fn last_micros(&self) -> u64 {
+        self.last_micros.load(Ordering::SeqCst)
+    }
+
+    /// Adds an event's key to the type index. Deletes remove the entry along with the event, but
+    /// compaction drops expired events on its own, so readers of the index must still skip keys
+    /// whose event is gone.
+    fn index_event(&self, batch: &mut WriteBatch, event_type: &EventType, key: &[u8]) {
+        batch.put_cf(self.cf(EVENT_TYPES_CF), type_key(event_type, key), b"");
+    }
+
+    /// Deletes a stored event and its type index entry in the batch, along with where
+    /// `rekey_events` moved it, if it did. An event that can't be read has no type to find its
+    /// entry by, so that entry is left for `verify_indexes` to repair.
+    fn unindex_event(&self, batch: &mut WriteBatch, key: &[u8], value: &[u8]) {
+        batch.delete_cf(self.cf(EVENTS_CF), key);
+        if let Some(event) = self.read_event(key, value) {
+            batch.delete_cf(self.cf(EVENT_TYPES_CF), type_key(&event.event_type, key));
+            match self.decode_key(key) {
+                Ok(micros) if micros != event.ingest_ts => {
+                    batch.delete_cf(self.cf(KEY_INGEST_TS_CF), micros.to_be_bytes());
+                    batch.delete_cf(self.cf(INGEST_TS_KEYS_CF), event.ingest_ts.to_be_bytes());
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// The key for a new event keeping its ingest_ts, as imported and replicated events do. Events
+    /// `rekey_events` moved fill every key up to the newest one it moved, so an event ingested
+    /// before that has no key that keeps the events in ingest order, and is rejected.
+    fn kept_key(&self, ingest_ts: u64) -> Result<Vec<u8>, Error> {
+        if ingest_ts <= self.rekeyed_through.load(Ordering::SeqCst) {
+            return Err(wx_error("event predates the last rekey"));
+        }
+
+        Ok(self.key_encoding().encode(ingest_ts))
+    }
+
+    /// Adds an event to its type's lifetime total in the batch. Totals of types already counted
+    /// in the batch are carried in `pending`, since the batch can't be read back. Callers must
+    /// hold the sequence lock, so no other write can update the totals in between.
+    fn count_event(
+        &self,
+        batch: &mut WriteBatch,
+        pending: &mut Vec<(Vec<u8>, u64)>,
+        event_type: &EventType,
+    ) -> Result<(), rocksdb::Error> {
+        let cf = self.cf(EVENT_TOTALS_CF);
+        let key = serialize(event_type).unwrap();
+        let index = match pending
+            .iter()
+            .position(|(pending_key, _)| *pending_key == key)
+        {
+            Some(index) => index,
+            None => {
+                let total = self
+                    .db
+                    .get_cf(cf, &key)?
+                    .and_then(|value| deserialize::<u64>(&value).ok())
+                    .unwrap_or(0);
+                pending.push((key, total));
+                pending.len() - 1
+            }
+        };
+
+        let (key, total) = &mut pending[index];
+        *total += 1;
+        batch.put_cf(cf, key, serialize(total).unwrap());
+
+        Ok(())
+    }
+
+    /// Deserializes a command payload, failing with `message` if it's malformed. With
+    /// `error_detail`, the message also says what was wrong with it.
+    fn parse_payload<'a, T: Deserialize<'a>>(
+        &self,
+        payload: &'a [u8],
+        message: &str,
+    ) -> Result<T, Error> {
+        deserialize(payload).map_err(|e| {
+            if self.error_detail {
+                wx_error(&format!("{}: {}", message, describe_decode_error(&e)))
+            } else {
+                wx_error(message)
+            }
+        })
+    }
+
+    /// Cursors for `get_events` are the serialized string of the last ingest_ts seen.
+    fn parse_cursor(&self, cursor: &[u8]) -> Result<u64, Error> {
+        let cursor: &str = self.parse_payload(cursor, "invalid cursor")?;
+        cursor.parse().map_err(|_| {
+            if self.error_detail {
+                wx_error("invalid cursor: not a number")
+            } else {
+                wx_error("invalid cursor")
+            }
+        })
+    }
+
+    /// Wakes anything waiting on event writes, and counts them towards `write_throughput`.
+    fn notify_event_writes(&self, count: u64) {
+        self.write_rate.record(count);
+        let (writes, signal) = &self.event_writes;
+        *writes.lock().unwrap() += 1;
+        signal.notify_all();
+    }
+
+    /// Long-poll counterpart to `get_events`. Returns an envelope of events newer than the u64
+    /// ingest_ts in the payload as soon as there are any, blocking until one is written or the
+    /// timeout in millis elapses. An empty envelope means the timeout was reached.
+    pub fn wait_for_event(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let (cursor, timeout_millis): (u64, u64) = self.parse_payload(payload, "invalid wait")?;
+        let _stream = self.open_stream(Command::WaitForEvent)?;
+        let deadline = Instant::now() + Duration::from_millis(timeout_millis.min(MAX_WAIT_MILLIS));
+        let (writes, signal) = &self.event_writes;
+
+        loop {
+            // Taken before reading, so a write landing after the read is never missed
+            let seen = *writes.lock().unwrap();
+            let envelope = {
+                let _permit = self.readers.acquire();
+                let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+                self.seek_after(&mut iter, cursor);
+                self.read_events(&mut iter)
+            };
+
+            if envelope.len() > mem::size_of::<u64>() || Instant::now() >= deadline {
+                return Ok(envelope);
+            }
+
+            let mut guard = writes.lock().unwrap();
+            while *guard == seen {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                guard = signal.wait_timeout(guard, deadline - now).unwrap().0;
+            }
+        }
+    }
+
+    /// Lets ingesters catch bad data before committing it. Deserializes the payload as an `Event`
+    /// and checks basic invariants, returning a `Vec<String>` describing each problem found. A
+    /// payload that isn't an event is described like a rejected PutEvent, with detail only when
+    /// `error_detail` is set. Nothing is written.
+    pub fn validate_event(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut problems = Vec::new();
+
+        match self.parse_payload::<Event>(payload, "payload is not a valid event") {
+            Ok(event) => {
+                if event.event_ts == 0 {
+                    problems.push(String::from("event_ts is missing"));
+                } else if event.event_ts
+                    > wx::util::get_system_micros() + MAX_EVENT_CLOCK_SKEW_MICROS
+                {
+                    problems.push(String::from("event_ts is too far in the future"));
+                }
+
+                if event.title.trim().is_empty() {
+                    problems.push(String::from("title is missing"));
+                }
+            }
+            Err(e) => problems.push(e.to_string()),
+        }
+
+        Ok(serialize(&problems).unwrap())
+    }
+
+    pub fn get_events(&self, key: &[u8], get_all: bool) -> Result<Vec<u8>, Error> {
+        let _permit = self.readers.acquire();
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        self.seek_events(&mut iter, key, get_all)?;
+
+        let envelope = self.read_events(&mut iter);
+        if !self.envelope_version {
+            return Ok(envelope);
+        }
+
+        let mut response = Vec::with_capacity(envelope.len() + 1);
+        response.push(ENVELOPE_VERSION);
+        response.extend_from_slice(&envelope);
+
+        Ok(response)
+    }
+
+    /// Events from the last u64 micros in the payload, for dashboards showing "the last N minutes"
+    /// without tracking a cursor. Equivalent to `get_events` with a cursor of that long ago, so it
+    /// isn't limited to the configured window.
+    pub fn get_events_last_n(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let duration_micros: u64 = self.parse_payload(payload, "invalid duration")?;
+        let since = wx::util::get_system_micros().saturating_sub(duration_micros);
+
+        self.get_events(&serialize(&since.to_string()).unwrap(), false)
+    }
+
+    /// Pages backwards from the newest event, for "latest first" feeds. The payload is a tuple of
+    /// an optional u64 cursor and a u32 limit. Returns up to that many events ingested before the
+    /// cursor, or the newest events without one, newest first and preceded by the cursor for the
+    /// next older page, which is None once there's nothing older. Cursors are ingest_ts, and live
+    /// ingest only adds newer events, so events arriving between pages never shift the older ones.
+    pub fn get_recent_paged(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let (cursor, limit): (Option<u64>, u32) =
+            self.parse_payload(payload, "invalid page request")?;
+        if limit == 0 {
+            return Err(wx_error("invalid page size"));
+        }
+
+        let _permit = self.readers.acquire();
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        match cursor {
+            Some(cursor) => {
+                self.seek_micros(&mut iter, &[], cursor);
+                if iter.valid() {
+                    iter.prev();
+                } else {
+                    iter.seek_to_last();
+                }
+            }
+            None => iter.seek_to_last(),
+        }
+        let mut buffer = Vec::new();
+        let mut count: u64 = 0;
+        let mut oldest = cursor;
+
+        while iter.valid() && count < u64::from(limit) {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if let Some(value) = self.decode_event(key, value) {
+                buffer.extend_from_slice(&value);
+                count += 1;
+            }
+            oldest = Some(self.key_ingest_ts(key)?);
+            iter.prev();
+        }
+
+        let next_cursor = if iter.valid() { oldest } else { None };
+        let mut response = serialize(&next_cursor).unwrap();
+        response.extend_from_slice(&envelope(count, &buffer));
+
+        Ok(response)
+    }
+
+    /// Positions an events iterator for `get_events`: at the first event if getting all, at the
+    /// start of the window if there's no cursor, and otherwise just after the cursor.
+    fn seek_events(
+        &self,
+        iter: &mut DBRawIterator,
+        key: &[u8],
+        get_all: bool,
+    ) -> Result<(), Error> {
+        if get_all {
+            iter.seek_to_first()
+        } else if key.is_empty() {
+            self.seek_window(iter);
+        } else {
+            // If the key is still valid and would be returned, then we need to skip it since
+            // it has already been seen by the requester.
+            self.seek_after(iter, self.parse_cursor(key)?);
+        }
+
+        Ok(())
+    }
+
+    /// The forgiving counterpart to the strict cursor parsing in `get_events`, giving clients with
+    /// a bad saved cursor a way to recover. Digits are salvaged from a malformed cursor where
+    /// possible. Returns the ingest_ts of the nearest event at or after the cursor, or None if
+    /// there isn't one. If nothing can be salvaged, the first event in the window is used instead.
+    pub fn normalize_cursor(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let cursor = self.parse_cursor(payload).ok().or_else(|| {
+            let digits: String = String::from_utf8_lossy(payload)
+                .chars()
+                .filter(char::is_ascii_digit)
+                .collect();
+            digits.parse::<u64>().ok()
+        });
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+
+        match cursor {
+            Some(cursor) => self.seek_micros(&mut iter, &[], cursor),
+            None => self.seek_window(&mut iter),
+        }
+
+        let key = match iter.key() {
+            Some(key) => Some(self.key_ingest_ts(key)?),
+            None => None,
+        };
+
+        Ok(serialize(&key).unwrap())
+    }
+
+    /// A clock-independent polling cursor. Takes the u64 sequence number of the last event seen, or
+    /// 0 to start, and returns the sequence number to poll from next followed by an envelope of
+    /// every event written since. Events deleted since they were written are skipped.
+    pub fn get_events_by_seq(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let last_seen: u64 = self.parse_payload(payload, "invalid sequence")?;
+        let (_, response) = self.read_since_sequence(last_seen)?;
+
+        Ok(response)
+    }
+
+    /// Blocking counterpart to `get_events_by_seq`, for replicas and consumers tailing the store.
+    /// Takes a tuple of the u64 sequence number of the last event seen and a timeout in millis,
+    /// returning as soon as anything is written past it. Sequence numbers become visible in
+    /// order, so following from each response's sequence never misses or repeats an event.
+    pub fn follow_from(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let (last_seen, timeout_millis): (u64, u64) =
+            self.parse_payload(payload, "invalid follow")?;
+        let _stream = self.open_stream(Command::FollowFrom)?;
+        let deadline = Instant::now() + Duration::from_millis(timeout_millis.min(MAX_WAIT_MILLIS));
+        let (writes, signal) = &self.event_writes;
+
+        loop {
+            // Taken before reading, so a write landing after the read is never missed
+            let seen = *writes.lock().unwrap();
+            let (next, response) = self.read_since_sequence(last_seen)?;
+
+            if next > last_seen || Instant::now() >= deadline {
+                return Ok(response);
+            }
+
+            let mut guard = writes.lock().unwrap();
+            while *guard == seen {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                guard = signal.wait_timeout(guard, deadline - now).unwrap().0;
+            }
+        }
+    }
+
+    /// Reads every event with a sequence number after `last_seen`, returning the last sequence
+    /// number read along with the response for `get_events_by_seq`.
+    fn read_since_sequence(&self, last_seen: u64) -> Result<(u64, Vec<u8>), Error> {
+        let _permit = self.readers.acquire();
+        let events_cf = self.cf(EVENTS_CF);
+        let snapshot = self.db.snapshot();
+        let mut iter = snapshot.raw_iterator_cf(self.cf(SEQUENCE_CF));
+        let mut buffer = Vec::new();
+        let mut count: u64 = 0;
+        let mut next = last_seen;
+        iter.seek(last_seen.saturating_add(1).to_be_bytes());
+
+        while iter.valid() {
+            let (sequence, key) = (iter.key().unwrap(), iter.value().unwrap());
+            next = parse_sequence(sequence)?;
+
+            let mut value = snapshot.get_cf(events_cf, key).map_err(convert_error)?;
+            if value.is_none() && self.migrating.load(Ordering::SeqCst) {
+                // Sequence entries are only rewritten once a key migration finishes
+                let encoding = KeyEncoding::of(key);
+                let moved = encoding.other().encode(encoding.decode(key)?);
+                value = snapshot.get_cf(events_cf, moved).map_err(convert_error)?;
+            }
+            if let Some(value) = value {
+                if let Some(value) = self.decode_event(key, &value) {
+                    buffer.extend_from_slice(&value);
+                    count += 1;
+                }
+            }
+            iter.next();
+        }
+
+        let mut response = serialize(&next).unwrap();
+        response.extend_from_slice(&envelope(count, &buffer));
+
+        Ok((next, response))
+    }
+
+    /// Like `get_events`, but returns an `EventHeader` per event rather than the whole thing, so
+    /// list views don't pay for bodies they never show.
+    pub fn get_event_headers(&self, key: &[u8]) -> Result<Vec<u8>, Error> {
+        let _permit = self.readers.acquire();
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        self.seek_events(&mut iter, key, false)?;
+        let mut headers = Vec::new();
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if let Some(event) = self.read_event(key, value) {
+                headers.push(EventHeader {
+                    ingest_ts: event.ingest_ts,
+                    event_type: event.event_type,
+                    title: event.title,
+                    summary: event.text.map(|text| {
+                        text.lines()
+                            .next()
+                            .unwrap_or_default()
+                            .chars()
+                            .take(HEADER_SUMMARY_CHARS)
+                            .collect()
+                    }),
+                    expires_ts: event.expires_ts,
+                });
+            }
+            iter.next();
+        }
+
+        Ok(serialize(&headers).unwrap())
+    }
+
+    /// Events of one type in the window, oldest first. This is a prefix scan of the type index, so
+    /// only events of that type are read. Index entries left behind by expired events are skipped.
+    pub fn get_events_by_type(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let event_type: EventType = self.parse_payload(payload, "invalid event type")?;
+        let prefix = serialize(&event_type).unwrap();
+        let _permit = self.readers.acquire();
+        let cf = self.cf(EVENTS_CF);
+        let snapshot = self.db.snapshot();
+        let mut iter = snapshot.raw_iterator_cf(self.cf(EVENT_TYPES_CF));
+        let start = wx::util::get_system_micros() - self.event_threshold_micros;
+        self.seek_micros(&mut iter, &prefix, start);
+        let mut buffer = Vec::new();
+        let mut count: u64 = 0;
+
+        while iter.valid() {
+            let type_key = iter.key().unwrap();
+            if !type_key.starts_with(&prefix) {
+                break;
+            }
+
+            let key = &type_key[prefix.len()..];
+            if let Some(stored) = snapshot.get_cf(cf, key).map_err(convert_error)? {
+                if let Some((value, event)) = self.read_event_with_bytes(key, &stored) {
+                    // The key may have been reused by an event of another type since
+                    if event.event_type == event_type {
+                        buffer.extend_from_slice(&value);
+                        count += 1;
+                    }
+                }
+            }
+            iter.next();
+        }
+
+        Ok(envelope(count, &buffer))
+    }
+
+    /// Every event alongside the key it's stored under, in key order. The key is otherwise hidden,
+    /// but seeing exactly how keys sort is invaluable when diagnosing cursor bugs.
+    pub fn get_events_with_keys(&self) -> Result<Vec<u8>, Error> {
+        let _permit = self.readers.acquire();
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        let mut events = Vec::new();
+        iter.seek_to_first();
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if let Some(event) = self.read_event(key, value) {
+                events.push((self.stored_encoding(key).display(key), event));
+            }
+            iter.next();
+        }
+
+        Ok(serialize(&events).unwrap())
+    }
+
+    /// The oldest ingest_ts of each event type in the window, for spotting stale or stuck feeds.
+    /// There are only a handful of types, so they're collected as pairs, which serialize exactly
+    /// like a map.
+    pub fn oldest_active_by_type(&self) -> Result<Vec<u8>, Error> {
+        let _permit = self.readers.acquire();
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        self.seek_window(&mut iter);
+        let mut oldest: Vec<(EventType, u64)> = Vec::new();
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if let Some(event) = self.read_event(key, value) {
+                match oldest.iter_mut().find(|(t, _)| *t == event.event_type) {
+                    Some((_, ingest_ts)) => *ingest_ts = (*ingest_ts).min(event.ingest_ts),
+                    None => oldest.push((event.event_type, event.ingest_ts)),
+                }
+            }
+            iter.next();
+        }
+
+        Ok(serialize(&oldest).unwrap())
+    }
+
+    /// A uniform random sample of events in the window, for QA and model validation. The payload is
+    /// a tuple of the u32 sample size and an optional u64 seed, which makes the sample reproducible
+    /// for as long as the window holds the same events. Reservoir sampling picks them in a single
+    /// pass, holding no more than the sample in memory, so sizes over `max_sample_size` are
+    /// rejected. Returned oldest first.
+    pub fn sample_events(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let (size, seed): (u32, Option<u64>) = self.parse_payload(payload, "invalid sample")?;
+        let size = size as usize;
+        if size > self.max_sample_size {
+            return Err(wx_error(&format!(
+                "sample size {} exceeds maximum of {}",
+                size, self.max_sample_size
+            )));
+        }
+        let mut rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let _permit = self.readers.acquire();
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        self.seek_window(&mut iter);
+        let mut sample: Vec<(usize, Vec<u8>)> = Vec::with_capacity(size);
+        let mut seen = 0;
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if let Some(value) = self.decode_event(key, value) {
+                if sample.len() < size {
+                    sample.push((seen, value.into_owned()));
+                } else {
+                    let slot = rng.gen_range(0, seen + 1);
+                    if slot < size {
+                        sample[slot] = (seen, value.into_owned());
+                    }
+                }
+                seen += 1;
+            }
+            iter.next();
+        }
+
+        sample.sort_by_key(|(position, _)| *position);
+        let mut buffer = Vec::new();
+        for (_, value) in &sample {
+            buffer.extend_from_slice(value);
+        }
+
+        Ok(envelope(sample.len() as u64, &buffer))
+    }
+
+    /// Events in the window due to lapse within the u64 lookahead micros in the payload, so
+    /// dashboards can highlight warnings about to expire. Events that have already expired, or
+    /// don't expire, are skipped. Returned soonest first.
+    pub fn get_expiring_soon(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let lookahead: u64 = self.parse_payload(payload, "invalid lookahead")?;
+        let now = wx::util::get_system_micros();
+        let until = now.saturating_add(lookahead);
+        let _permit = self.readers.acquire();
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        self.seek_window(&mut iter);
+        let mut expiring = Vec::new();
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if let Some((value, event)) = self.read_event_with_bytes(key, value) {
+                match event.expires_ts {
+                    Some(expires_ts) if expires_ts > now && expires_ts <= until => {
+                        expiring.push((expires_ts, value.into_owned()))
+                    }
+                    _ => {}
+                }
+            }
+            iter.next();
+        }
+
+        expiring.sort_by_key(|(expires_ts, _)| *expires_ts);
+        let mut buffer = Vec::new();
+        for (_, value) in &expiring {
+            buffer.extend_from_slice(value);
+        }
+
+        Ok(envelope(expiring.len() as u64, &buffer))
+    }
+
+    /// The event types driving volume in the window. The payload is an optional u32 limit on how
+    /// many to return. Returns pairs of type and count, busiest first, with ties broken by the
+    /// type's serialized form so the order is stable between calls.
+    pub fn top_event_types(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let limit: Option<u32> = self.parse_payload(payload, "invalid limit")?;
+        let mut counts: Vec<(Vec<u8>, u64)> = self.count_window_types()?.into_iter().collect();
+        counts.sort_by(|(a_type, a_count), (b_type, b_count)| {
+            b_count.cmp(a_count).then_with(|| a_type.cmp(b_type))
+        });
+        counts.truncate(limit.map_or(counts.len(), |limit| limit as usize));
+
+        let mut top: Vec<(EventType, u64)> = Vec::with_capacity(counts.len());
+        for (event_type, count) in counts {
+            top.push((deserialize(&event_type).unwrap(), count));
+        }
+
+        Ok(serialize(&top).unwrap())
+    }
+
+    /// Counts the events of each type in the window, keyed by serialized type.
+    fn count_window_types(&self) -> Result<BTreeMap<Vec<u8>, u64>, Error> {
+        let _permit = self.readers.acquire();
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        self.seek_window(&mut iter);
+        let mut counts = BTreeMap::new();
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if let Some(event) = self.read_event(key, value) {
+                *counts
+                    .entry(serialize(&event.event_type).unwrap())
+                    .or_insert(0) += 1;
+            }
+            iter.next();
+        }
+
+        Ok(counts)
+    }
+
+    /// Attaches a note to an event, such as "false alarm" or "verified", without touching the
+    /// event itself. The payload is a tuple of the event's u64 ingest_ts and the note. An event can
+    /// hold any number of notes. They outlive the event unless `expire_annotations` is set.
+    pub fn annotate_event(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        self.check_not_paused()?;
+        let (ingest_ts, note): (u64, &str) = self.parse_payload(payload, "invalid annotation")?;
+        if note.is_empty() {
+            return Err(wx_error("invalid note"));
+        }
+
+        // Held from the existence check through the put, so notes added to the same event at once
+        // can't both claim a key
+        let stripe = ingest_ts as usize % self.annotation_locks.len();
+        let _lock = self.annotation_locks[stripe].lock().unwrap();
+        let events_cf = self.cf(EVENTS_CF);
+        let mut found = false;
+        for key in self.event_keys(ingest_ts)? {
+            found |= self
+                .db
+                .get_cf(events_cf, &key)
+                .map_err(convert_error)?
+                .is_some();
+        }
+        if !found {
+            return Err(wx_error("event not found"));
+        }
+
+        // Notes added to the same event in the same micro mustn't overwrite each other
+        let cf = self.cf(ANNOTATIONS_CF);
+        let mut annotated_ts = wx::util::get_system_micros();
+        let key = loop {
+            let mut key = ingest_ts.to_be_bytes().to_vec();
+            key.extend_from_slice(&annotated_ts.to_be_bytes());
+            if self.db.get_cf(cf, &key).map_err(convert_error)?.is_none() {
+                break key;
+            }
+            annotated_ts += 1;
+        };
+        self.db
+            .put_cf(cf, &key, self.encrypt(note.as_bytes()))
+            .map_err(convert_error)?;
+
+        Ok(vec![])
+    }
+
+    /// Notes attached by `annotate_event`. The payload is a tuple of u64 ingest_ts and optional
+    /// u64 end micros. Without an end, only the notes on that event are returned, otherwise the
+    /// notes on every event ingested from the first up to the end, exclusive. Returns a
+    /// `Vec<Annotation>`, ordered by event and then by when the note was added.
+    pub fn get_annotations(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let (start, end): (u64, Option<u64>) =
+            self.parse_payload(payload, "invalid annotation range")?;
+        let end = end.unwrap_or_else(|| start.saturating_add(1));
+        let mut iter = self.db.raw_iterator_cf(self.cf(ANNOTATIONS_CF));
+        let mut annotations = Vec::new();
+        iter.seek(start.to_be_bytes());
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            let (ingest_ts, annotated_ts) = parse_annotation_key(key)?;
+            if ingest_ts >= end {
+                break;
+            }
+
+            let note = self
+                .decrypt(value)
+                .ok_or_else(|| wx_error("unable to decrypt value"))?;
+            annotations.push(Annotation {
+                ingest_ts,
+                annotated_ts,
+                note: String::from_utf8_lossy(&note).into_owned(),
+            });
+            iter.next();
+        }
+
+        Ok(serialize(&annotations).unwrap())
+    }
+
+    /// Checks the type index against the events it indexes, for confirming integrity after a crash
+    /// or restore. Missing entries are events the index doesn't list, so `GetEventsByType` can't
+    /// see them. Orphaned entries point at events that are gone or now hold another type. Deletes
+    /// remove their entries, but entries for events that compaction dropped for their age are
+    /// left until the index's own TTL expires them, so those aren't counted. The bool payload asks
+    /// for both kinds to be repaired in a single batch, with event writes held off meanwhile.
+    /// Returns an `IndexReport` of what was found, before any repair.
+    pub fn verify_indexes(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let repair: bool = self.parse_payload(payload, "invalid repair flag")?;
+        let events_cf = self.cf(EVENTS_CF);
+        let types_cf = self.cf(EVENT_TYPES_CF);
+        let type_retention = self.type_retention.read().unwrap().clone();
+        let now = wx::util::get_system_micros();
+        let _sequence = if repair {
+            Some(self.sequence.lock().unwrap())
+        } else {
+            None
+        };
+        let _permit = self.readers.acquire();
+        let snapshot = self.db.snapshot();
+        let mut batch = WriteBatch::default();
+        let mut report = IndexReport {
+            events: 0,
+            missing: 0,
+            orphaned: 0,
+        };
+
+        let mut iter = snapshot.raw_iterator_cf(events_cf);
+        iter.seek_to_first();
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if let Some(event) = self.read_event(key, value) {
+                report.events += 1;
+                let indexed = snapshot
+                    .get_cf(types_cf, type_key(&event.event_type, key))
+                    .map_err(convert_error)?;
+                if indexed.is_none() {
+                    report.missing += 1;
+                    self.index_event(&mut batch, &event.event_type, key);
+                }
+            }
+            iter.next();
+        }
+
+        let mut iter = snapshot.raw_iterator_cf(types_cf);
+        iter.seek_to_first();
+        while iter.valid() {
+            let type_key = iter.key().unwrap();
+            let (prefix, key) = split_type_key(type_key).unwrap_or((&[], type_key));
+            let indexed = match snapshot.get_cf(events_cf, key).map_err(convert_error)? {
+                // Unreadable events are left alone, since they can't be told apart
+                Some(value) => self
+                    .read_event(key, &value)
+                    .is_none_or(|event| serialize(&event.event_type).unwrap() == prefix),
+                // Expired events are compaction's to drop, and their entries the index TTL's
+                None => decode_any(key).is_ok_and(|micros| {
+                    let age = now.saturating_sub(micros);
+                    (self.ttl_expiry && age > self.event_threshold_micros)
+                        || type_retention
+                            .iter()
+                            .any(|(retained, micros)| *retained == prefix && age > *micros)
+                }),
+            };
+            if !indexed {
+                report.orphaned += 1;
+                batch.delete_cf(types_cf, type_key);
+            }
+            iter.next();
+        }
+
+        if repair {
+            self.db.write(batch).map_err(convert_error)?;
+            info!(self.logger, "verify_indexes"; "msg" => "repaired type index",
+                "missing" => report.missing, "orphaned" => report.orphaned);
+        }
+
+        Ok(serialize(&report).unwrap())
+    }
+
+    /// Replaces the retention policy with the `RetentionPolicy` in the payload. Per-type rules
+    /// apply from the next compaction, as with `Config::type_retention`, which the policy replaces.
+    /// The event count and byte caps are only enforced by `sweep_events`, since a compaction filter
+    /// sees one record at a time. The policy is stored, so it survives restarts.
+    pub fn set_retention_policy(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let policy: RetentionPolicy = self.parse_payload(payload, "invalid retention policy")?;
+        self.db
+            .put_cf(
+                self.cf(METADATA_CF),
+                RETENTION_KEY,
+                serialize(&policy).unwrap(),
+            )
+            .map_err(convert_error)?;
+        info!(self.logger, "set_retention_policy";
+            "type_retention" => format!("{:?}", policy.type_retention),
+            "max_events" => policy.max_events, "max_bytes" => policy.max_bytes);
+        self.apply_retention_policy(policy);
+
+        Ok(vec![])
+    }
+
+    fn apply_retention_policy(&self, policy: RetentionPolicy) {
+        *self.type_retention.write().unwrap() = policy
+            .type_retention
+            .iter()
+            .map(|(event_type, micros)| (serialize(event_type).unwrap(), *micros))
+            .collect();
+        *self.retention.lock().unwrap() = policy;
+    }
+
+    /// The `RetentionPolicy` in force, whether set at runtime or configured.
+    pub fn get_retention_policy(&self) -> Result<Vec<u8>, Error> {
+        Ok(serialize(&*self.retention.lock().unwrap()).unwrap())
+    }
+
+    /// Enforces the retention policy now, rather than waiting on compaction. Events older than
+    /// their type's retention are deleted, then the oldest of the rest until they're within the
+    /// event count and byte caps. Their type index entries are deleted with them. Returns the u64
+    /// number of events deleted.
+    pub fn sweep_events(&self) -> Result<Vec<u8>, Error> {
+        let (max_events, max_bytes) = {
+            let policy = self.retention.lock().unwrap();
+            (policy.max_events, policy.max_bytes)
+        };
+        let type_retention = self.type_retention.read().unwrap().clone();
+        let min_retention = type_retention.iter().map(|(_, micros)| *micros).min();
+        let now = wx::util::get_system_micros();
+        let cf = self.cf(EVENTS_CF);
+        let _sequence = self.sequence.lock().unwrap();
+        let snapshot = self.db.snapshot();
+        let mut iter = snapshot.raw_iterator_cf(cf);
+        let mut batch = WriteBatch::default();
+        let mut kept = Vec::new();
+        let mut deleted: u64 = 0;
+        iter.seek_to_first();
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            let age = now.saturating_sub(self.key_ingest_ts(key)?);
+            let expired = min_retention.is_some_and(|min_retention| age > min_retention)
+                && self.read_event(key, value).is_some_and(|event| {
+                    let event_type = serialize(&event.event_type).unwrap();
+                    type_retention
+                        .iter()
+                        .any(|(retained, micros)| *retained == event_type && age > *micros)
+                });
+            if expired {
+                self.unindex_event(&mut batch, key, value);
+                deleted += 1;
+            } else {
+                kept.push((key.to_vec(), (key.len() + value.len()) as u64));
+            }
+            iter.next();
+        }
+
+        let mut events = kept.len() as u64;
+        let mut bytes: u64 = kept.iter().map(|(_, size)| size).sum();
+
+        for (key, size) in kept {
+            if max_events.is_none_or(|max_events| events <= max_events)
+                && max_bytes.is_none_or(|max_bytes| bytes <= max_bytes)
+            {
+                break;
+            }
+            if let Some(value) = snapshot.get_cf(cf, &key).map_err(convert_error)? {
+                self.unindex_event(&mut batch, &key, &value);
+            }
+            events -= 1;
+            bytes -= size;
+            deleted += 1;
+        }
+
+        self.db.write(batch).map_err(convert_error)?;
+        if deleted > 0 {
+            info!(self.logger, "sweep_events"; "deleted" => deleted, "events" => events);
+        }
+
+        Ok(serialize(&deleted).unwrap())
+    }
+
+    /// How many events of each type have ever been stored, regardless of whether they've since
+    /// expired or been deleted. Events stored before totals were kept aren't counted. Collected as
+    /// pairs, which serialize exactly like a map.
+    pub fn get_event_totals(&self) -> Result<Vec<u8>, Error> {
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENT_TOTALS_CF));
+        let mut totals: Vec<(EventType, u64)> = Vec::new();
+        iter.seek_to_first();
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            let event_type = deserialize(key).map_err(|_| wx_error("invalid stored total"))?;
+            let total = deserialize(value).map_err(|_| wx_error("invalid stored total"))?;
+            totals.push((event_type, total));
+            iter.next();
+        }
+
+        Ok(serialize(&totals).unwrap())
+    }
+
+    /// Events in the window located within a bounding box, for geographic views. This scans the
+    /// window rather than keeping a spatial index, which is cheap enough at the window's size.
+    /// Events without a location never match.
+    pub fn get_events_by_location(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let bounds: (f32, f32, f32, f32) = self.parse_payload(payload, "invalid bounding box")?;
+        let _permit = self.readers.acquire();
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        self.seek_window(&mut iter);
+        let mut buffer = Vec::new();
+        let mut count: u64 = 0;
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if let Some((value, event)) = self.read_event_with_bytes(key, value) {
+                if event
+                    .location
+                    .as_ref()
+                    .is_some_and(|location| in_bounds(location, bounds))
+                {
+                    buffer.extend_from_slice(&value);
+                    count += 1;
+                }
+            }
+            iter.next();
+        }
+
+        Ok(envelope(count, &buffer))
+    }
+
+    /// Same events as `get_events`, transposed into one vector per `Event` field so analysts can
+    /// load them straight into a dataframe. The response is a tuple of the vectors in `Event`
+    /// field order.
+    pub fn get_events_columnar(&self, key: &[u8]) -> Result<Vec<u8>, Error> {
+        let _permit = self.readers.acquire();
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        self.seek_events(&mut iter, key, false)?;
+        let mut events = Vec::new();
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            events.extend(self.read_event(key, value));
+            iter.next();
+        }
+
+        let event_ts: Vec<_> = events.iter().map(|e| e.event_ts).collect();
+        let event_type: Vec<_> = events.iter().map(|e| &e.event_type).collect();
+        let expires_ts: Vec<_> = events.iter().map(|e| &e.expires_ts).collect();
+        let ext_uri: Vec<_> = events.iter().map(|e| &e.ext_uri).collect();
+        let ingest_ts: Vec<_> = events.iter().map(|e| e.ingest_ts).collect();
+        let location: Vec<_> = events.iter().map(|e| &e.location).collect();
+        let md: Vec<_> = events.iter().map(|e| &e.md).collect();
+        let outlook: Vec<_> = events.iter().map(|e| &e.outlook).collect();
+        let report: Vec<_> = events.iter().map(|e| &e.report).collect();
+        let text: Vec<_> = events.iter().map(|e| &e.text).collect();
+        let title: Vec<_> = events.iter().map(|e| &e.title).collect();
+        let valid_ts: Vec<_> = events.iter().map(|e| &e.valid_ts).collect();
+        let warning: Vec<_> = events.iter().map(|e| &e.warning).collect();
+        let watch: Vec<_> = events.iter().map(|e| &e.watch).collect();
+        let columns = (
+            event_ts, event_type, expires_ts, ext_uri, ingest_ts, location, md, outlook, report,
+            text, title, valid_ts, warning, watch,
+        );
+
+        Ok(serialize(&columns).unwrap())
+    }
+
+    /// For large transfers that the client wants to pull at its own pace. The payload is a tuple of
+    /// continuation token and chunk size, where the token is the ingest_ts of the last event
+    /// received, or 0 to start from the beginning. Returns the token for the next chunk, None once
+    /// there's nothing left, followed by the chunk's envelope.
+    pub fn get_events_chunk(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let (cursor, chunk_size): (u64, u32) =
+            self.parse_payload(payload, "invalid chunk request")?;
+        if chunk_size == 0 {
+            return Err(wx_error("invalid chunk size"));
+        }
+
+        let _stream = self.open_stream(Command::GetEventsChunk)?;
+        let _permit = self.readers.acquire();
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        self.seek_after(&mut iter, cursor);
+        let mut buffer = Vec::new();
+        let mut count: u64 = 0;
+        let mut last_seen = cursor;
+
+        while iter.valid() && count < u64::from(chunk_size) {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if let Some((value, event)) = self.read_event_with_bytes(key, value) {
+                last_seen = event.ingest_ts;
+                buffer.extend_from_slice(&value);
+                count += 1;
+            }
+            iter.next();
+        }
+
+        let next_token = if iter.valid() { Some(last_seen) } else { None };
+        let mut response = serialize(&next_token).unwrap();
+        response.extend_from_slice(&envelope(count, &buffer));
+
+        Ok(response)
+    }
+
+    /// Scan-based search of the window for events whose title or text contains a term, ignoring
+    /// case. Paged like `get_events_chunk`: the payload is a tuple of term, continuation token,
+    /// and limit, and the response is the token for the next page followed by an envelope.
+    pub fn search_events(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let (term, cursor, limit): (&str, u64, u32) =
+            self.parse_payload(payload, "invalid search")?;
+        if term.is_empty() || limit == 0 {
+            return Err(wx_error("invalid search"));
+        }
+
+        let term = term.to_lowercase();
+        let _permit = self.readers.acquire();
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        match cursor {
+            0 => self.seek_window(&mut iter),
+            cursor => self.seek_after(&mut iter, cursor),
+        }
+        let mut buffer = Vec::new();
+        let mut count: u64 = 0;
+        let mut last_seen = cursor;
+
+        while iter.valid() && count < u64::from(limit) {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if let Some((value, event)) = self.read_event_with_bytes(key, value) {
+                let matches = event.title.to_lowercase().contains(&term)
+                    || event
+                        .text
+                        .as_ref()
+                        .is_some_and(|text| text.to_lowercase().contains(&term));
+                if matches {
+                    last_seen = event.ingest_ts;
+                    buffer.extend_from_slice(&value);
+                    count += 1;
+                }
+            }
+            iter.next();
+        }
+
+        let next_token = if iter.valid() { Some(last_seen) } else { None };
+        let mut response = serialize(&next_token).unwrap();
+        response.extend_from_slice(&envelope(count, &buffer));
+
+        Ok(response)
+    }
+
+    /// Same as `get_events`, but the envelope is zstd-compressed for slow links and prefixed with
+    /// `ZSTD_FLAG`. This is independent of the on-disk compression.
+    pub fn get_events_compressed(&self, key: &[u8]) -> Result<Vec<u8>, Error> {
+        let envelope = self.get_events(key, false)?;
+        let compressed =
+            zstd::encode_all(&envelope[..], ZSTD_LEVEL).map_err(|e| wx_error(&e.to_string()))?;
+        let mut payload = Vec::with_capacity(compressed.len() + 1);
+        payload.push(ZSTD_FLAG);
+        payload.extend_from_slice(&compressed);
+
+        Ok(payload)
+    }
+
+    /// Same events as `get_events`, as gzipped newline-delimited JSON prefixed with `GZIP_FLAG`, so
+    /// external analytics tools can pull a window over a slow link without a bincode decoder.
+    pub fn get_events_ndjson_gz(&self, key: &[u8]) -> Result<Vec<u8>, Error> {
+        let _stream = self.open_stream(Command::GetEventsNdjsonGz)?;
+        let _permit = self.readers.acquire();
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        self.seek_events(&mut iter, key, false)?;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if let Some(event) = self.read_event(key, value) {
+                serde_json::to_writer(&mut encoder, &event)
+                    .map_err(|e| wx_error(&e.to_string()))?;
+                encoder
+                    .write_all(b"\n")
+                    .map_err(|e| wx_error(&e.to_string()))?;
+            }
+            iter.next();
+        }
+
+        let compressed = encoder.finish().map_err(|e| wx_error(&e.to_string()))?;
+        let mut payload = Vec::with_capacity(compressed.len() + 1);
+        payload.push(GZIP_FLAG);
+        payload.extend_from_slice(&compressed);
+
+        Ok(payload)
+    }
+
+    /// Same events as `get_events`, but with the configured `redacted_fields` blanked, so consumers
+    /// that shouldn't see them never receive them at all.
+    pub fn get_events_redacted(&self, key: &[u8]) -> Result<Vec<u8>, Error> {
+        let _permit = self.readers.acquire();
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        self.seek_events(&mut iter, key, false)?;
+        let mut buffer = Vec::new();
+        let mut count: u64 = 0;
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if let Some(mut event) = self.read_event(key, value) {
+                self.redact(&mut event);
+                buffer.extend_from_slice(&serialize(&event).unwrap());
+                count += 1;
+            }
+            iter.next();
+        }
+
+        Ok(envelope(count, &buffer))
+    }
+
+    /// Same events as `get_events`, encoded as the `Events` protobuf message of
+    /// `proto/event.proto`, for clients that don't read bincode.
+    pub fn get_events_proto(&self, key: &[u8]) -> Result<Vec<u8>, Error> {
+        let _permit = self.readers.acquire();
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        self.seek_events(&mut iter, key, false)?;
+        let mut events = Vec::new();
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if let Some(event) = self.read_event(key, value) {
+                events.push(proto::Event::try_from(&event)?);
+            }
+            iter.next();
+        }
+
+        Ok(prost::Message::encode_to_vec(&proto::Events { events }))
+    }
+
+    fn redact(&self, event: &mut Event) {
+        for field in &self.redacted_fields {
+            match field.as_str() {
+                "ext_uri" => event.ext_uri = None,
+                "location" => event.location = None,
+                "md" => event.md = None,
+                "outlook" => event.outlook = None,
+                "report" => event.report = None,
+                "text" => event.text = None,
+                "title" => event.title = String::new(),
+                "warning" => event.warning = None,
+                "watch" => event.watch = None,
+                _ => unreachable!("redacted fields are validated on open"),
+            }
+        }
+    }
+
+    /// Same as `get_events`, but rendered as Server-Sent Events for HTTP gateways feeding browser
+    /// dashboards. Each event is a frame of its ingest_ts as the id, which browsers send back as
+    /// Last-Event-ID on reconnect, and its JSON as the data. JSON is rendered compactly, so it
+    /// always fits on a single data line.
+    pub fn get_events_sse(&self, key: &[u8]) -> Result<Vec<u8>, Error> {
+        let _stream = self.open_stream(Command::GetEventsSse)?;
+        let _permit = self.readers.acquire();
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        self.seek_events(&mut iter, key, false)?;
+        let mut text = String::new();
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if let Some(event) = self.read_event(key, value) {
+                let json = serde_json::to_string(&event).map_err(|e| wx_error(&e.to_string()))?;
+                text.push_str(&format!("id: {}\ndata: {}\n\n", event.ingest_ts, json));
+            }
+            iter.next();
+        }
+
+        Ok(text.into_bytes())
+    }
+
+    /// For clients reconnecting after a gap. Takes the u64 ingest_ts of the last event seen and
+    /// returns the high-water mark followed by an envelope of every event since. The high-water
+    /// mark is the ingest_ts of the newest event at the time of the call, and is what the client
+    /// should resume from, so nothing is missed or duplicated between calls.
+    pub fn catch_up(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let last_seen: u64 = self.parse_payload(payload, "invalid timestamp")?;
+        let _permit = self.readers.acquire();
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        self.seek_after(&mut iter, last_seen);
+        let envelope = self.read_events(&mut iter);
+
+        // The iterator reads from an implicit snapshot, so the newest event it sees is the last one
+        // included in the envelope, if any were.
+        let mut high_water_mark = last_seen;
+        iter.seek_to_last();
+        if iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if let Some(event) = self.read_event(key, value) {
+                high_water_mark = high_water_mark.max(event.ingest_ts);
+            }
+        }
+
+        let mut response = serialize(&high_water_mark).unwrap();
+        response.extend_from_slice(&envelope);
+
+        Ok(response)
+    }
+
+    /// Returns the schema version along with the raw bytes of the newest stored event, or None if
+    /// there are no events to sample. Clients can attempt to deserialize the sample with their own
+    /// `Event` definition to catch schema drift.
+    pub fn probe_schema(&self) -> Result<Vec<u8>, Error> {
+        let _permit = self.readers.acquire();
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        iter.seek_to_last();
+        let mut sample = None;
+        if iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            sample = self.decode_event(key, value).map(Cow::into_owned);
+        }
+
+        Ok(serialize(&(SCHEMA_VERSION, sample)).unwrap())
+    }
+
+    /// The newest stored event, as a cheap way to check that events are still flowing. Corrupt
+    /// events are skipped, as elsewhere, so the newest readable one is returned.
+    pub fn latest_event(&self) -> Result<Vec<u8>, Error> {
+        let _permit = self.readers.acquire();
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        iter.seek_to_last();
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if let Some(value) = self.decode_event(key, value) {
+                return Ok(value.into_owned());
+            }
+            iter.prev();
+        }
+
+        Err(wx_error("no events found"))
+    }
+
+    /// Events ingested after the first u64 micros in the payload, up to and including the second,
+    /// for sync tooling catching up on what changed between two points in time. Only additions are
+    /// reported. Deletes and expiry aren't recorded, so removals would need a change feed of them,
+    /// which the store doesn't keep.
+    pub fn events_diff(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let (since, until): (u64, u64) = self.parse_payload(payload, "invalid interval")?;
+        let _permit = self.readers.acquire();
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        self.seek_micros(&mut iter, &[], since.saturating_add(1));
+        let mut buffer = Vec::new();
+        let mut count: u64 = 0;
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if self.key_ingest_ts(key)? > until {
+                break;
+            }
+
+            if let Some(value) = self.decode_event(key, value) {
+                buffer.extend_from_slice(&value);
+                count += 1;
+            }
+            iter.next();
+        }
+
+        Ok(envelope(count, &buffer))
+    }
+
+    /// For looking at what was happening around a point in time without guessing at a window. The
+    /// payload is a tuple of u64 target micros and u32 count. Walks outwards from the target,
+    /// taking whichever neighbor is closer by ingest_ts, and returns the events oldest first.
+    pub fn get_events_near(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let (target, count): (u64, u32) = self.parse_payload(payload, "invalid target")?;
+        let _permit = self.readers.acquire();
+        let cf = self.cf(EVENTS_CF);
+        let snapshot = self.db.snapshot();
+        let mut after = snapshot.raw_iterator_cf(cf);
+        let mut before = snapshot.raw_iterator_cf(cf);
+        self.seek_micros(&mut after, &[], target);
+        self.seek_micros(&mut before, &[], target);
+        if before.valid() {
+            before.prev();
+        } else {
+            before.seek_to_last();
+        }
+
+        let distance = |ts: u64| ts.max(target) - ts.min(target);
+        let mut next_after = self.step_near(&mut after, true);
+        let mut next_before = self.step_near(&mut before, false);
+        let mut near = Vec::new();
+
+        while near.len() < count as usize {
+            let take_after = match (&next_after, &next_before) {
+                (Some((a, _)), Some((b, _))) => distance(*a) <= distance(*b),
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+
+            if take_after {
+                near.extend(next_after.take());
+                next_after = self.step_near(&mut after, true);
+            } else {
+                near.extend(next_before.take());
+                next_before = self.step_near(&mut before, false);
+            }
+        }
+
+        near.sort_by_key(|(ingest_ts, _)| *ingest_ts);
+        let mut buffer = Vec::new();
+        for (_, value) in &near {
+            buffer.extend_from_slice(value);
+        }
+
+        Ok(envelope(near.len() as u64, &buffer))
+    }
+
+    /// Returns the ingest_ts and bytes of the event at the iterator, then steps it in the given
+    /// direction. Corrupt events are skipped.
+    fn step_near(&self, iter: &mut DBRawIterator, forward: bool) -> Option<(u64, Vec<u8>)> {
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            let event = self
+                .read_event_with_bytes(key, value)
+                .map(|(value, event)| (event.ingest_ts, value.into_owned()));
+
+            if forward {
+                iter.next();
+            } else {
+                iter.prev();
+            }
+
+            if event.is_some() {
+                return event;
+            }
+        }
+
+        None
+    }
+
+    /// Folds the keys and bytes of every event, in key order, into a single xxHash digest, so a
+    /// primary and replica can cheaply confirm they hold the same data. The payload is an optional
+    /// tuple of u64 start (inclusive) and end (exclusive) micros to limit the digest to.
+    pub fn events_digest(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let range: Option<(u64, u64)> = self.parse_payload(payload, "invalid range")?;
+        let _permit = self.readers.acquire();
+        let snapshot = self.db.snapshot();
+        let mut iter = snapshot.raw_iterator_cf(self.cf(EVENTS_CF));
+        let digest = self.digest_events(&mut iter, range)?;
+
+        Ok(serialize(&digest).unwrap())
+    }
+
+    /// Confirms a checkpoint opens and holds the same events as the live store. The payload is a
+    /// tuple of the checkpoint path and an optional range like `events_digest`, which can exclude
+    /// events written since the checkpoint was taken. The checkpoint is opened read-only as a
+    /// separate DB, so neither it nor the live one is modified. Returns whether the digests match,
+    /// then both digests.
+    pub fn verify_checkpoint(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let (path, range): (String, Option<(u64, u64)>) =
+            self.parse_payload(payload, "invalid checkpoint verification")?;
+        let opts = Options::default();
+        let cfs = DB::list_cf(&opts, &path).map_err(convert_error)?;
+        let descriptors: Vec<_> = cfs.iter().map(|name| inspection_descriptor(name)).collect();
+        let checkpoint = DB::open_cf_descriptors_read_only(&opts, &path, descriptors, false)
+            .map_err(convert_error)?;
+        let events_cf = checkpoint
+            .cf_handle(EVENTS_CF)
+            .ok_or_else(|| wx_error("checkpoint has no events"))?;
+        let mut iter = checkpoint.raw_iterator_cf(events_cf);
+        let checkpoint_digest = self.digest_events(&mut iter, range)?;
+
+        let _permit = self.readers.acquire();
+        let snapshot = self.db.snapshot();
+        let mut iter = snapshot.raw_iterator_cf(self.cf(EVENTS_CF));
+        let live_digest = self.digest_events(&mut iter, range)?;
+
+        Ok(serialize(&(
+            checkpoint_digest == live_digest,
+            checkpoint_digest,
+            live_digest,
+        ))
+        .unwrap())
+    }
+
+    fn digest_events(
+        &self,
+        iter: &mut DBRawIterator,
+        range: Option<(u64, u64)>,
+    ) -> Result<u64, Error> {
+        let (start, end) = range.unwrap_or((0, u64::MAX));
+        let mut hasher = XxHash64::default();
+        self.seek_micros(iter, &[], start);
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if self.key_ingest_ts(key)? >= end {
+                break;
+            }
+
+            // Checksums are a storage detail, so only the event itself is hashed
+            if let Some(value) = self.decode_event(key, value) {
+                hasher.write(key);
+                hasher.write(&value);
+            }
+            iter.next();
+        }
+
+        Ok(hasher.finish())
+    }
+
+    /// Distribution of stored event sizes in the window, for storage planning. On stores with more
+    /// than `MAX_SIZE_SAMPLES` events, every nth event is measured so runtime stays bounded.
+    pub fn event_size_stats(&self) -> Result<Vec<u8>, Error> {
+        let stride = (self.estimate_num_keys(EVENTS_CF)? / MAX_SIZE_SAMPLES).max(1);
+        let _permit = self.readers.acquire();
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        self.seek_window(&mut iter);
+        let mut sizes = Vec::new();
+        let mut i: u64 = 0;
+
+        while iter.valid() {
+            if i.is_multiple_of(stride) {
+                let value = iter.value().unwrap();
+                sizes.push(value.len() as u64);
+            }
+            i += 1;
+            iter.next();
+        }
+
+        sizes.sort();
+        let percentile = |p: usize| match sizes.len() {
+            0 => 0,
+            len => sizes[(len * p / 100).min(len - 1)],
+        };
+        let stats = SizeStats {
+            sampled: sizes.len() as u64,
+            min: sizes.first().cloned().unwrap_or(0),
+            mean: sizes.iter().sum::<u64>() / (sizes.len() as u64).max(1),
+            p50: percentile(50),
+            p95: percentile(95),
+            max: sizes.last().cloned().unwrap_or(0),
+        };
+
+        Ok(serialize(&stats).unwrap())
+    }
+
+    /// Recent event write rates, so operators can see ingest keeping pace without a metrics system.
+    /// Only writes since the store was opened are counted. Returns a `Throughput`.
+    pub fn write_throughput(&self) -> Result<Vec<u8>, Error> {
+        let throughput = Throughput {
+            last_10s: self.write_rate.per_sec(10),
+            last_1m: self.write_rate.per_sec(60),
+            last_5m: self.write_rate.per_sec(300),
+        };
+
+        Ok(serialize(&throughput).unwrap())
+    }
+
+    /// How far behind the pipeline is, from the gap between when events happened and when they
+    /// were ingested across the window. Events without an event_ts can't be measured, so they're
+    /// skipped.
+    pub fn ingest_lag_stats(&self) -> Result<Vec<u8>, Error> {
+        let _permit = self.readers.acquire();
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        self.seek_window(&mut iter);
+        let mut lags = Vec::new();
+        let mut skewed: u64 = 0;
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if let Some(event) = self.read_event(key, value) {
+                if event.event_ts > event.ingest_ts {
+                    skewed += 1;
+                } else if event.event_ts > 0 {
+                    lags.push(event.ingest_ts - event.event_ts);
+                }
+            }
+            iter.next();
+        }
+
+        lags.sort();
+        let stats = LagStats {
+            measured: lags.len() as u64,
+            skewed,
+            min: lags.first().cloned().unwrap_or(0),
+            mean: lags.iter().sum::<u64>() / (lags.len() as u64).max(1),
+            p95: match lags.len() {
+                0 => 0,
+                len => lags[(len * 95 / 100).min(len - 1)],
+            },
+            max: lags.last().cloned().unwrap_or(0),
+        };
+
+        Ok(serialize(&stats).unwrap())
+    }
+
+    /// Counts the events of each type ingested on a UTC day, given as a u64 of days since the
+    /// epoch, and stores the counts so trends can still be charted once the events have expired.
+    /// Any earlier rollup of the day is replaced in the same batch, so rolling up a day again is
+    /// safe, though counts only ever reflect the events still stored.
+    pub fn rollup_day(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let day: u64 = self.parse_payload(payload, "invalid day")?;
+        let start = day.saturating_mul(MICROS_PER_DAY);
+        let end = start.saturating_add(MICROS_PER_DAY);
+        let mut counts: BTreeMap<Vec<u8>, u64> = BTreeMap::new();
+        {
+            let _permit = self.readers.acquire();
+            let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+            self.seek_micros(&mut iter, &[], start);
+
+            while iter.valid() {
+                let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+                if self.key_ingest_ts(key)? >= end {
+                    break;
+                }
+                if let Some(event) = self.read_event(key, value) {
+                    *counts
+                        .entry(serialize(&event.event_type).unwrap())
+                        .or_insert(0) += 1;
+                }
+                iter.next();
+            }
+        }
+
+        let cf = self.cf(ROLLUPS_CF);
+        let mut batch = WriteBatch::default();
+        let mut iter = self.db.raw_iterator_cf(cf);
+        iter.seek(day.to_be_bytes());
+
+        while iter.valid() {
+            let key = iter.key().unwrap();
+            if !key.starts_with(&day.to_be_bytes()) {
+                break;
+            }
+            batch.delete_cf(cf, key);
+            iter.next();
+        }
+
+        let mut rollup: Vec<(EventType, u64)> = Vec::new();
+        for (event_type, count) in counts {
+            let mut key = day.to_be_bytes().to_vec();
+            key.extend_from_slice(&event_type);
+            batch.put_cf(cf, &key, serialize(&count).unwrap());
+            rollup.push((deserialize(&event_type).unwrap(), count));
+        }
+        self.db.write(batch).map_err(convert_error)?;
+
+        Ok(serialize(&rollup).unwrap())
+    }
+
+    /// Stored daily counts from the start day up to the end day, given as a tuple of u64 days since
+    /// the epoch. Days that haven't been rolled up are absent.
+    pub fn get_rollups(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let (start, end): (u64, u64) = self.parse_payload(payload, "invalid day range")?;
+        let mut iter = self.db.raw_iterator_cf(self.cf(ROLLUPS_CF));
+        let mut rollups: Vec<(u64, EventType, u64)> = Vec::new();
+        iter.seek(start.to_be_bytes());
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            let (day, event_type) = key.split_at(mem::size_of::<u64>().min(key.len()));
+            let day = day
+                .try_into()
+                .map(u64::from_be_bytes)
+                .map_err(|_| wx_error("invalid rollup"))?;
+            if day >= end {
+                break;
+            }
+            let event_type = deserialize(event_type).map_err(|_| wx_error("invalid rollup"))?;
+            let count = deserialize(value).map_err(|_| wx_error("invalid rollup"))?;
+            rollups.push((day, event_type, count));
+            iter.next();
+        }
+
+        Ok(serialize(&rollups).unwrap())
+    }
+
+    /// Tallies events in the window into buckets of the given u64 size in micros, keyed by
+    /// ingest_ts. Returns a `Vec<(u64, u64)>` of bucket start and count, oldest first, omitting
+    /// empty buckets.
+    pub fn events_histogram_by_time(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let bucket_micros: u64 = self.parse_payload(payload, "invalid bucket size")?;
+        if bucket_micros == 0 {
+            return Err(wx_error("invalid bucket size"));
+        }
+
+        let mut buckets: Vec<(u64, u64)> = Vec::new();
+        let _permit = self.readers.acquire();
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        self.seek_window(&mut iter);
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if let Some(event) = self.read_event(key, value) {
+                let bucket = event.ingest_ts / bucket_micros * bucket_micros;
+
+                // Events are iterated in order, so a bucket is only ever appended to at the end
+                match buckets.last_mut() {
+                    Some((start, count)) if *start == bucket => *count += 1,
+                    _ => buckets.push((bucket, 1)),
+                }
+            }
+            iter.next();
+        }
+
+        Ok(serialize(&buckets).unwrap())
+    }
+
+    /// Failed requests broken down by command and kind of error, as a `Vec<ErrorCount>` sorted by
+    /// command. Counts accumulate for the life of the process and are never reset, so clients
+    /// wanting rates should diff successive responses.
+    pub fn error_stats(&self) -> Result<Vec<u8>, Error> {
+        let counts: Vec<ErrorCount> = self
+            .error_counts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|((command, error), count)| ErrorCount {
+                command: command.clone(),
+                error: error.clone(),
+                count: *count,
+            })
+            .collect();
+
+        Ok(serialize(&counts).unwrap())
+    }
+
+    /// A quick operational overview. Event and fetch failure counts are RocksDB's estimates, so
+    /// they're cheap to get but may be off slightly after deletes.
+    pub fn info(&self) -> Result<Vec<u8>, Error> {
+        let info = Info {
+            uptime_micros: self.started.elapsed().as_micros() as u64,
+            requests: self.requests.load(Ordering::SeqCst),
+            errors: self.errors.load(Ordering::SeqCst),
+            events: self.estimate_num_keys(EVENTS_CF)?,
+            fetch_failures: self.estimate_num_keys(FETCH_FAILURES_CF)?,
+        };
+
+        Ok(serialize(&info).unwrap())
+    }
+
+    /// The settings the store was opened with, so clients can confirm thresholds and limits
+    /// without access to the deployment's configuration.
+    pub fn get_config(&self) -> Result<Vec<u8>, Error> {
+        let settings = Settings {
+            event_threshold_micros: self.event_threshold_micros,
+            fetch_failure_threshold_micros: self.fetch_failure_threshold_micros,
+            compression_type: self.compression_type.to_string(),
+            key_encoding: self.key_encoding().name().to_string(),
+            max_key_length: self.max_key_length as u64,
+            max_concurrent_readers: self.readers.capacity() as u64,
+            import_batch_size: self.import_batch_size as u64,
+            event_checksums: self.event_checksums,
+            ttl_expiry: self.ttl_expiry,
+            envelope_version: self.envelope_version,
+        };
+
+        Ok(serialize(&settings).unwrap())
+    }
+
+    /// Key count and SST size for each column family, including the default one holding KV pairs.
+    pub fn cf_stats(&self) -> Result<Vec<u8>, Error> {
+        let mut stats = BTreeMap::new();
+
+        for name in [DEFAULT_CF].iter().chain(COLUMN_FAMILIES.iter()) {
+            let cf_stats = CfStats {
+                keys: self.estimate_num_keys(name)?,
+                sst_bytes: self.property_u64(name, "rocksdb.total-sst-files-size")?,
+            };
+            stats.insert(name.to_string(), cf_stats);
+        }
+
+        Ok(serialize(&stats).unwrap())
+    }
+
+    /// Every live SST file with its level and key range, for seeing how compaction has laid out
+    /// the data. Memtables that haven't been flushed aren't included.
+    pub fn sst_files(&self) -> Result<Vec<u8>, Error> {
+        let files: Vec<SstFile> = self
+            .db
+            .live_files()
+            .map_err(convert_error)?
+            .into_iter()
+            .map(|file| SstFile {
+                column_family: file.column_family_name,
+                name: file.name,
+                level: file.level,
+                size: file.size as u64,
+                start_key: file.start_key,
+                end_key: file.end_key,
+                num_entries: file.num_entries,
+                num_deletions: file.num_deletions,
+            })
+            .collect();
+
+        Ok(serialize(&files).unwrap())
+    }
+
+    /// Compaction health across every column family, for detecting when writes outpace it. This is
+    /// cheap, unlike the full stats dump, so it's fine to poll.
+    pub fn compaction_backlog(&self) -> Result<Vec<u8>, Error> {
+        let mut backlog = CompactionBacklog {
+            running: self.property_u64(DEFAULT_CF, "rocksdb.num-running-compactions")?,
+            pending: false,
+            pending_bytes: 0,
+        };
+
+        for name in [DEFAULT_CF].iter().chain(COLUMN_FAMILIES.iter()) {
+            backlog.pending |= self.property_u64(name, "rocksdb.compaction-pending")? > 0;
+            backlog.pending_bytes +=
+                self.property_u64(name, "rocksdb.estimate-pending-compaction-bytes")?;
+        }
+
+        Ok(serialize(&backlog).unwrap())
+    }
+
+    /// How well data compresses and how much compaction rewrites it, for weighing compression and
+    /// compaction settings. Logical sizes come from the table properties RocksDB aggregates over
+    /// live SST files, and write volumes from its statistics.
+    pub fn storage_efficiency(&self) -> Result<Vec<u8>, Error> {
+        let mut logical_bytes = 0;
+        let mut sst_bytes = 0;
+
+        for name in [DEFAULT_CF].iter().chain(COLUMN_FAMILIES.iter()) {
+            let properties = match *name {
+                DEFAULT_CF => self
+                    .db
+                    .property_value("rocksdb.aggregated-table-properties"),
+                _ => self
+                    .db
+                    .property_value_cf(self.cf(name), "rocksdb.aggregated-table-properties"),
+            }
+            .map_err(convert_error)?
+            .unwrap_or_default();
+            logical_bytes += table_property(&properties, "raw key size")
+                + table_property(&properties, "raw value size");
+            sst_bytes += self.property_u64(name, "rocksdb.live-sst-files-size")?;
+        }
+
+        let statistics = self.opts.get_statistics().unwrap_or_default();
+        let bytes_written = ticker(&statistics, "rocksdb.bytes.written");
+        let flush_bytes = ticker(&statistics, "rocksdb.flush.write.bytes");
+        let compaction_bytes = ticker(&statistics, "rocksdb.compact.write.bytes");
+        let ratio = |numerator: u64, denominator: u64| match denominator {
+            0 => 0.0,
+            _ => numerator as f64 / denominator as f64,
+        };
+        let efficiency = StorageEfficiency {
+            logical_bytes,
+            sst_bytes,
+            compression_ratio: ratio(logical_bytes, sst_bytes),
+            bytes_written,
+            flush_bytes,
+            compaction_bytes,
+            write_amplification: ratio(flush_bytes + compaction_bytes, bytes_written),
+        };
+
+        Ok(serialize(&efficiency).unwrap())
+    }
+
+    fn estimate_num_keys(&self, cf_name: &str) -> Result<u64, Error> {
+        self.property_u64(cf_name, "rocksdb.estimate-num-keys")
+    }
+
+    /// Reads a numeric DB property, treating a missing value as 0.
+    fn property_u64(&self, cf_name: &str, property: &str) -> Result<u64, Error> {
+        let value = match cf_name {
+            DEFAULT_CF => self.db.property_value(property),
+            _ => self.db.property_value_cf(self.cf(cf_name), property),
+        }
+        .map_err(convert_error)?;
+
+        Ok(value.and_then(|value| value.parse().ok()).unwrap_or(0))
+    }
+
+    /// Deletes every event, the whole type index, and the record of where `rekey_events` moved
+    /// events, leaving other keys untouched. Returns the number of events deleted.
+    pub fn clear_events(&self) -> Result<Vec<u8>, rocksdb::Error> {
+        let cf = self.cf(EVENTS_CF);
+        let types_cf = self.cf(EVENT_TYPES_CF);
+        let _sequence = self.sequence.lock().unwrap();
+        let mut batch = WriteBatch::default();
+        let mut count: u64 = 0;
+        let mut iter = self.db.raw_iterator_cf(cf);
+        iter.seek_to_first();
+
+        while iter.valid() {
+            let key = iter.key().unwrap();
+            batch.delete_cf(cf, key);
+            count += 1;
+            iter.next();
+        }
+
+        for index_cf in [
+            types_cf,
+            self.cf(INGEST_TS_KEYS_CF),
+            self.cf(KEY_INGEST_TS_CF),
+        ]
+        .iter()
+        {
+            let mut iter = self.db.raw_iterator_cf(*index_cf);
+            iter.seek_to_first();
+            while iter.valid() {
+                batch.delete_cf(*index_cf, iter.key().unwrap());
+                iter.next();
+            }
+        }
+
+        self.db.write(batch)?;
+        self.rekeyed_through.store(0, Ordering::SeqCst);
+
+        Ok(serialize(&count).unwrap())
+    }
+
+    pub fn compact(&self) -> Result<Vec<u8>, Error> {
+        self.db.compact_range(None::<&[u8]>, None::<&[u8]>);
+
+        for name in COLUMN_FAMILIES.iter() {
+            self.db
+                .compact_range_cf(self.cf(name), None::<&[u8]>, None::<&[u8]>);
+        }
+
+        Ok(vec![])
+    }
+
+    /// Compacts just the events column family, which churns from expiry and deletes, leaving KV
+    /// pairs and everything else alone. Returns the SST bytes reclaimed.
+    pub fn compact_events(&self) -> Result<Vec<u8>, Error> {
+        let before = self.property_u64(EVENTS_CF, "rocksdb.total-sst-files-size")?;
+        self.db
+            .compact_range_cf(self.cf(EVENTS_CF), None::<&[u8]>, None::<&[u8]>);
+        let after = self.property_u64(EVENTS_CF, "rocksdb.total-sst-files-size")?;
+
+        Ok(serialize(&before.saturating_sub(after)).unwrap())
+    }
+
+    /// Flushes memtables for every column family to SST files.
+    pub fn flush(&self) -> Result<Vec<u8>, rocksdb::Error> {
+        flush_all(&self.db)?;
+
+        Ok(vec![])
+    }
+
+    /// Rejects client writes until `resume`, so operators can quiesce ingest before a backup or
+    /// migration while reads carry on. Every write the data socket accepts is rejected, including
+    /// deletes, offset commits, polls and claims, as is DeleteEvent. Bulk admin writes such as
+    /// imports aren't affected. A write already past its check when paused still lands. Returns
+    /// whether it was already paused.
+    pub fn pause(&self) -> Result<Vec<u8>, Error> {
+        let was_paused = self.paused.swap(true, Ordering::SeqCst);
+        info!(self.logger, "pause"; "was_paused" => was_paused);
+
+        Ok(serialize(&was_paused).unwrap())
+    }
+
+    /// Accepts client writes again, returning whether they were paused.
+    pub fn resume(&self) -> Result<Vec<u8>, Error> {
+        let was_paused = self.paused.swap(false, Ordering::SeqCst);
+        info!(self.logger, "resume"; "was_paused" => was_paused);
+
+        Ok(serialize(&was_paused).unwrap())
+    }
+
+    /// Times a synthetic write through the same path as client puts, for latency monitoring. It
+    /// isn't retried, so write stalls show up in the result. The scratch key is deleted afterwards.
+    /// Returns the elapsed micros, rounded up so a completed probe never reports zero.
+    pub fn write_probe(&self) -> Result<Vec<u8>, Error> {
+        self.check_not_paused()?;
+        let _lock = self.kv_lock(WRITE_PROBE_KEY);
+        let start = Instant::now();
+        self.db
+            .put(WRITE_PROBE_KEY, WRITE_PROBE_VALUE)
+            .map_err(convert_error)?;
+        let elapsed = start.elapsed().as_nanos() as u64;
+        self.db.delete(WRITE_PROBE_KEY).map_err(convert_error)?;
+
+        Ok(serialize(&elapsed.div_ceil(1000)).unwrap())
+    }
+
+    /// Creates a point-in-time copy of the store at the given path, which must not exist yet.
+    /// Memtables are flushed first so the checkpoint is guaranteed to contain every write that has
+    /// been acknowledged.
+    pub fn checkpoint(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let path: String = self.parse_payload(payload, "invalid checkpoint path")?;
+        self.flush().map_err(convert_error)?;
+        let checkpoint = Checkpoint::new(&self.db).map_err(convert_error)?;
+        checkpoint.create_checkpoint(&path).map_err(convert_error)?;
+
+        Ok(vec![])
+    }
+
+    /// Accepts a staging store to replace this one with, for blue/green refreshes such as a nightly
+    /// rebuild. The payload is the staging store's path, whose column families are listed, without
+    /// opening it, to confirm it holds events. A store can't reopen itself, so the swap is left to
+    /// the host, which sees it with `pending_swap`, drops this store once in-flight requests
+    /// finish, moves the directories with `swap_directories`, and opens the new one, or calls
+    /// `cancel_swap` if it can't. Writes are paused meanwhile, since anything written here from now
+    /// on would be thrown away with this store. See `SharedStore`, which does all of this for the
+    /// listeners.
+    pub fn swap_store(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let path: String = self.parse_payload(payload, "invalid staging path")?;
+        let cfs = DB::list_cf(&Options::default(), &path).map_err(convert_error)?;
+        if !cfs.iter().any(|cf| cf == EVENTS_CF) {
+            return Err(wx_error("staging store has no events"));
+        }
+
+        let mut pending = self.pending_swap.lock().unwrap();
+        if pending.is_some() {
+            return Err(wx_error("swap already pending"));
+        }
+        let was_paused = self.paused.swap(true, Ordering::SeqCst);
+        info!(self.logger, "swap_store"; "msg" => "swap pending", "staging_path" => &path);
+        *pending = Some((path, was_paused));
+
+        Ok(vec![])
+    }
+
+    /// The staging store path accepted by `swap_store`, if the host has yet to swap it in.
+    pub fn pending_swap(&self) -> Option<String> {
+        self.pending_swap
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|(path, _)| path.clone())
+    }
+
+    /// Drops the pending swap, if there is one, and resumes writes unless they were already paused
+    /// when it was accepted.
+    pub fn cancel_swap(&self) {
+        if let Some((path, was_paused)) = self.pending_swap.lock().unwrap().take() {
+            self.paused.store(was_paused, Ordering::SeqCst);
+            warn!(self.logger, "swap_store"; "msg" => "swap cancelled", "staging_path" => path);
+        }
+    }
+
+    /// Moves the store at `path` aside to `<path>.old` and renames the staging store into its
+    /// place. The store at `path` must be closed. Fails without moving anything if `<path>.old`
+    /// exists, since that's the store an earlier swap replaced, which only an operator should
+    /// remove. If the staging store can't be moved, the original is moved back, so `path` always
+    /// holds a store.
+    pub fn swap_directories(path: &str, staging_path: &str) -> io::Result<()> {
+        let old_path = format!("{}.old", path);
+        if fs::metadata(&old_path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", old_path),
+            ));
+        }
+
+        fs::rename(path, &old_path)?;
+        if let Err(e) = fs::rename(staging_path, path) {
+            fs::rename(&old_path, path)?;
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Tunes a hot store without a restart. The payload is a tuple of option name and value, and
+    /// the name must be in `SETTABLE_DB_OPTIONS`. RocksDB applies it to the default column
+    /// family. Changes don't persist across restarts.
+    pub fn set_db_option(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let (name, value): (&str, &str) = self.parse_payload(payload, "invalid option")?;
+        if !SETTABLE_DB_OPTIONS.contains(&name) {
+            return Err(wx_error("option can't be set at runtime"));
+        }
+
+        self.db
+            .set_options(&[(name, value)])
+            .map_err(convert_error)?;
+        info!(self.logger, "set_db_option"; "name" => name, "value" => value);
+
+        Ok(vec![])
+    }
+
+    /// Deletes a single event by the u64 micros returned from `put_event`, along with its type
+    /// index entry. Returns whether the event existed.
+    pub fn delete_event(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        self.check_not_paused()?;
+        let micros: u64 = self.parse_payload(payload, "invalid event key")?;
+        let cf = self.cf(EVENTS_CF);
+        let _sequence = self.sequence.lock().unwrap();
+        let mut batch = WriteBatch::default();
+        let mut existed = false;
+
+        for key in self.event_keys(micros)? {
+            if let Some(value) = self.db.get_cf(cf, &key).map_err(convert_error)? {
+                self.unindex_event(&mut batch, &key, &value);
+                existed = true;
+            }
+        }
+
+        self.db.write(batch).map_err(convert_error)?;
+
+        Ok(serialize(&existed).unwrap())
+    }
+
+    /// Bulk counterpart to `delete_event`, for archivers removing the keys they've processed.
+    /// Every deletion, type index entries included, is written in a single batch. Keys that don't
+    /// exist are skipped.
+    pub fn delete_events(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let keys: BTreeSet<u64> = self
+            .parse_payload::<Vec<u64>>(payload, "invalid event keys")?
+            .into_iter()
+            .collect();
+        let cf = self.cf(EVENTS_CF);
+        let _sequence = self.sequence.lock().unwrap();
+        let mut batch = WriteBatch::default();
+        let mut deleted: u64 = 0;
+
+        for micros in keys {
+            for key in self.event_keys(micros)? {
+                if let Some(value) = self.db.get_cf(cf, &key).map_err(convert_error)? {
+                    self.unindex_event(&mut batch, &key, &value);
+                    deleted += 1;
+                }
+            }
+        }
+
+        self.db.write(batch).map_err(convert_error)?;
+
+        Ok(serialize(&deleted).unwrap())
+    }
+
+    /// For reprocessing pipelines regenerating a window of events. Every event ingested in
+    /// `[start, end)` is deleted and the new events written in the same batch, so readers see
+    /// either the old window or the new one, never a mix. Events keep their ingest_ts if asked,
+    /// which must then fall inside the window, and are otherwise assigned one like `put_event`.
+    /// Returns the number of events deleted and inserted.
+    pub fn replace_window(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let (start, end, events, keep_ingest_ts): (u64, u64, Vec<Event>, bool) =
+            self.parse_payload(payload, "invalid window replacement")?;
+        if keep_ingest_ts
+            && events
+                .iter()
+                .any(|event| event.ingest_ts < start || event.ingest_ts >= end)
+        {
+            return Err(wx_error("event outside window"));
+        }
+
+        let cf = self.cf(EVENTS_CF);
+        let sequence_cf = self.cf(SEQUENCE_CF);
+        let mut sequence = self.sequence.lock().unwrap();
+        let snapshot = self.db.snapshot();
+        let mut iter = snapshot.raw_iterator_cf(cf);
+        let mut batch = WriteBatch::default();
+        let mut deleted: u64 = 0;
+        self.seek_micros(&mut iter, &[], start);
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if self.key_ingest_ts(key)? >= end {
+                break;
+            }
+
+            self.unindex_event(&mut batch, key, value);
+            deleted += 1;
+            iter.next();
+        }
+
+        let inserted = events.len() as u64;
+        let mut next = *sequence;
+        let mut last_micros = self.last_micros();
+        let mut totals = Vec::new();
+        for mut event in events {
+            let key = if keep_ingest_ts {
+                self.kept_key(event.ingest_ts)?
+            } else {
+                // Assigned keys must stay unique even when several land in the same micro
+                let micros = wx::util::get_system_micros().max(last_micros + 1);
+                event.ingest_ts = micros;
+                last_micros = micros;
+                self.key_encoding().encode(micros)
+            };
+            batch.put_cf(cf, &key, self.encode_event(&event));
+            next += 1;
+            batch.put_cf(sequence_cf, next.to_be_bytes(), &key);
+            self.index_event(&mut batch, &event.event_type, &key);
+            self.count_event(&mut batch, &mut totals, &event.event_type)
+                .map_err(convert_error)?;
+        }
+
+        self.db.write(batch).map_err(convert_error)?;
+        *sequence = next;
+        self.last_micros.store(last_micros, Ordering::SeqCst);
+        drop(sequence);
+        self.notify_event_writes(inserted);
+
+        Ok(serialize(&(deleted, inserted)).unwrap())
+    }
+
+    /// Fetches and deletes every event ingested before the u64 micros in the payload, for
+    /// archivers moving cold data out. Only what was read from the snapshot is deleted, so events
+    /// written in the meantime are never lost. Events failing their checksum are left in place.
+    pub fn drain_events_before(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let before: u64 = self.parse_payload(payload, "invalid timestamp")?;
+        let cf = self.cf(EVENTS_CF);
+        let _sequence = self.sequence.lock().unwrap();
+        let snapshot = self.db.snapshot();
+        let mut iter = snapshot.raw_iterator_cf(cf);
+        let mut batch = WriteBatch::default();
+        let mut buffer = Vec::new();
+        let mut count: u64 = 0;
+        iter.seek_to_first();
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if self.key_ingest_ts(key)? >= before {
+                break;
+            }
+
+            if let Some(decoded) = self.decode_event(key, value) {
+                buffer.extend_from_slice(&decoded);
+                self.unindex_event(&mut batch, key, value);
+                count += 1;
+            }
+            iter.next();
+        }
+
+        self.db.write(batch).map_err(convert_error)?;
+
+        Ok(envelope(count, &buffer))
+    }
+
+    /// Moves every event to contiguous keys ending at the newest key, closing the gaps left by
+    /// deletes. Events keep their ingest_ts, so where each moved is recorded both ways, from
+    /// ingest_ts to key for finding an event by its ingest_ts, and from key to ingest_ts for
+    /// cursors and ranges, which are by ingest_ts. Keys only move forward and stay in ingest order,
+    /// so every cursor and range works as before. Type index entries, claims, and sequence numbers
+    /// move with their events, all in one batch with event writes and deletes held off. Notes are
+    /// kept by ingest_ts, so they stay put.
+    ///
+    /// Moved events expire by their key, so a little later than they would have. New events that
+    /// keep their ingest_ts can't be written at or before the newest moved key, since no key there
+    /// would keep them in ingest order. Fails without writing anything during a key migration, or
+    /// if any event can't be read. Returns the new min and max keys, or None if there are no
+    /// events.
+    pub fn rekey_events(&self) -> Result<Vec<u8>, Error> {
+        if self.migrating.load(Ordering::SeqCst) {
+            return Err(wx_error("key migration is underway"));
+        }
+
+        let cf = self.cf(EVENTS_CF);
+        let types_cf = self.cf(EVENT_TYPES_CF);
+        let claims_cf = self.cf(CLAIMS_CF);
+        let ingest_ts_keys_cf = self.cf(INGEST_TS_KEYS_CF);
+        let key_ingest_ts_cf = self.cf(KEY_INGEST_TS_CF);
+        let _sequence = self.sequence.lock().unwrap();
+        let _claims = self.claims.lock().unwrap();
+        let snapshot = self.db.snapshot();
+        let mut iter = snapshot.raw_iterator_cf(cf);
+        let mut events = Vec::new();
+        iter.seek_to_first();
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            let event = self
+                .read_event(key, value)
+                .ok_or_else(|| wx_error("unreadable event"))?;
+            events.push((self.decode_key(key)?, key.to_vec(), value.to_vec(), event));
+            iter.next();
+        }
+
+        let max = match events.last() {
+            Some((micros, _, _, _)) => *micros,
+            None => return Ok(serialize(&None::<(u64, u64)>).unwrap()),
+        };
+        let min = max - (events.len() as u64 - 1);
+        let mut batch = WriteBatch::default();
+        let mut moved = HashMap::new();
+        let mut moved_through = 0;
+
+        // Working backwards guarantees a moved event is never clobbered by the delete of the key
+        // it moved into.
+        for (i, (micros, key, value, event)) in events.into_iter().enumerate().rev() {
+            let new_micros = min + i as u64;
+            if new_micros == micros {
+                continue;
+            }
+
+            let new_key = self.key_encoding().encode(new_micros);
+            batch.delete_cf(cf, &key);
+            batch.put_cf(cf, &new_key, &value);
+            batch.delete_cf(types_cf, type_key(&event.event_type, &key));
+            self.index_event(&mut batch, &event.event_type, &new_key);
+            if let Some(claim) = snapshot.get_cf(claims_cf, &key).map_err(convert_error)? {
+                batch.delete_cf(claims_cf, &key);
+                batch.put_cf(claims_cf, &new_key, &claim[..]);
+            }
+
+            if micros != event.ingest_ts {
+                batch.delete_cf(key_ingest_ts_cf, micros.to_be_bytes());
+            }
+            batch.put_cf(
+                key_ingest_ts_cf,
+                new_micros.to_be_bytes(),
+                event.ingest_ts.to_be_bytes(),
+            );
+            batch.put_cf(
+                ingest_ts_keys_cf,
+                event.ingest_ts.to_be_bytes(),
+                new_micros.to_be_bytes(),
+            );
+            moved.insert(key, new_key);
+            moved_through = moved_through.max(new_micros);
+        }
+
+        // Sequence numbers must follow their events to the new keys
+        let sequence_cf = self.cf(SEQUENCE_CF);
+        let mut iter = snapshot.raw_iterator_cf(sequence_cf);
+        iter.seek_to_first();
+
+        while iter.valid() {
+            let (sequence, key) = (iter.key().unwrap(), iter.value().unwrap());
+            if let Some(new_key) = moved.get(key) {
+                batch.put_cf(sequence_cf, sequence, new_key);
+            }
+            iter.next();
+        }
+
+        // Raised before the write lands, so readers look moved keys up as soon as they exist
+        self.rekeyed_through
+            .fetch_max(moved_through, Ordering::SeqCst);
+        self.db.write(batch).map_err(convert_error)?;
+
+        Ok(serialize(&Some((min, max))).unwrap())
+    }
+
+    /// Moves events to another key encoding without a restart, so live stores can adopt
+    /// big-endian keys. The payload is a tuple of the target `KeyEncoding` name and a u32 batch
+    /// size. Each call moves up to that many events, read from a snapshot and written as one batch
+    /// with event writes held off, and records its progress, so it's repeated until finished and
+    /// picks up where it left off after a restart. In the meantime events are read under either
+    /// encoding and new ones are written under decimal keys. Events move oldest first to
+    /// big-endian keys and newest first to decimal keys, so big-endian keys always belong to the
+    /// older events, just as they sort first.
+    ///
+    /// Once finished, the target is recorded as the store's format and as its chosen encoding,
+    /// which later opens use whatever `Config::key_encoding` says, so a stale config never moves
+    /// the events back. Returns a tuple of the u64 events moved so far, the u64 events there were
+    /// to move when it started, and the u8 percent complete, which stays below 100 until every
+    /// event has moved.
+    pub fn migrate_key_encoding(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let (name, batch_size): (&str, u32) =
+            self.parse_payload(payload, "invalid key migration")?;
+        let target =
+            KeyEncoding::from_name(name).ok_or_else(|| wx_error("unknown key encoding"))?;
+        if batch_size == 0 {
+            return Err(wx_error("invalid batch size"));
+        }
+
+        let source = target.other();
+        let cf = self.cf(EVENTS_CF);
+        let claims_cf = self.cf(CLAIMS_CF);
+        let _sequence = self.sequence.lock().unwrap();
+        let _claims = self.claims.lock().unwrap();
+        let snapshot = self.db.snapshot();
+        let mut iter = snapshot.raw_iterator_cf(cf);
+
+        let (total, mut migrated) = match self.metadata(MIGRATION_KEY)? {
+            Some(value) => {
+                let (started, total, migrated): (String, u64, u64) =
+                    deserialize(&value).map_err(|_| wx_error("invalid stored migration"))?;
+                if started != target.name() {
+                    return Err(wx_error("another key migration is underway"));
+                }
+                (total, migrated)
+            }
+            None if self.key_encoding() == target => {
+                return Ok(serialize(&(0u64, 0u64, 100u8)).unwrap());
+            }
+            None => {
+                let mut total: u64 = 0;
+                iter.seek_to_first();
+                while iter.valid() {
+                    if source.matches(iter.key().unwrap()) {
+                        total += 1;
+                    }
+                    iter.next();
+                }
+                self.migrating.store(true, Ordering::SeqCst);
+                self.set_key_encoding(KeyEncoding::Decimal);
+                info!(self.logger, "migrate_key_encoding";
+                    "msg" => "started", "key_encoding" => target.name(), "events" => total);
+                (total, 0)
+            }
+        };
+
+        // Big-endian keys for any realistic micros sort before "/", and decimal keys from "0"
+        match target {
+            KeyEncoding::BigEndian => iter.seek(b"0"),
+            KeyEncoding::Decimal => iter.seek_for_prev(b"/"),
+        }
+        let mut batch = WriteBatch::default();
+        let mut moved: u32 = 0;
+
+        while iter.valid() && moved < batch_size {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if !source.matches(key) {
+                break;
+            }
+
+            let new_key = target.encode(source.decode(key)?);
+            batch.delete_cf(cf, key);
+            batch.put_cf(cf, &new_key, value);
+            if let Some(event) = self.read_event(key, value) {
+                batch.delete_cf(self.cf(EVENT_TYPES_CF), type_key(&event.event_type, key));
+                self.index_event(&mut batch, &event.event_type, &new_key);
+            }
+            if let Some(claim) = snapshot.get_cf(claims_cf, key).map_err(convert_error)? {
+                batch.delete_cf(claims_cf, key);
+                batch.put_cf(claims_cf, &new_key, &claim[..]);
+            }
+            moved += 1;
+            match target {
+                KeyEncoding::BigEndian => iter.next(),
+                KeyEncoding::Decimal => iter.prev(),
+            }
+        }
+
+        let done = match iter.key() {
+            Some(key) => !source.matches(key),
+            None => true,
+        };
+        migrated += u64::from(moved);
+
+        if done {
+            self.reencode_sequence(&mut batch, source)?;
+            let format = serialize(&(SCHEMA_VERSION, target.name())).unwrap();
+            batch.put_cf(self.cf(METADATA_CF), FORMAT_KEY, format);
+            batch.put_cf(
+                self.cf(METADATA_CF),
+                KEY_ENCODING_KEY,
+                serialize(target.name()).unwrap(),
+            );
+            batch.delete_cf(self.cf(METADATA_CF), MIGRATION_KEY);
+        } else {
+            let progress = serialize(&(target.name(), total, migrated)).unwrap();
+            batch.put_cf(self.cf(METADATA_CF), MIGRATION_KEY, progress);
+        }
+        self.db.write(batch).map_err(convert_error)?;
+
+        let percent = if done {
+            100
+        } else {
+            (migrated * 100 / total.max(1)).min(99) as u8
+        };
+        if done {
+            self.set_key_encoding(target);
+            self.migrating.store(false, Ordering::SeqCst);
+            info!(self.logger, "migrate_key_encoding";
+                "msg" => "finished", "key_encoding" => target.name(), "events" => migrated);
+        }
+
+        Ok(serialize(&(migrated, total, percent)).unwrap())
+    }
+
+    /// Writes many events in a single batch, for bulk loaders and replication. The payload is a
+    /// tuple of a `Vec<Event>` and a bool of whether each event keeps its ingest_ts as its key, as
+    /// imported events do, rather than being assigned one like `put_event`. Kept keys must be new,
+    /// so a batch holding an event already stored, or the same ingest_ts twice, is rejected without
+    /// writing anything. Returns the u64 number of events written.
+    pub fn put_event_batch(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        self.check_not_paused()?;
+        let (events, keep_ingest_ts): (Vec<Event>, bool) =
+            self.parse_payload(payload, "invalid event batch")?;
+        let cf = self.cf(EVENTS_CF);
+        let sequence_cf = self.cf(SEQUENCE_CF);
+        let mut sequence = self.sequence.lock().unwrap();
+        let mut batch = WriteBatch::default();
+        let mut next = *sequence;
+        let mut last_micros = self.last_micros();
+        let mut totals = Vec::new();
+        let mut kept = BTreeSet::new();
+
+        for mut event in events {
+            let key = if keep_ingest_ts {
+                // Checked under the sequence lock, so no other event write can land in between
+                if !kept.insert(event.ingest_ts) || self.event_exists(event.ingest_ts)? {
+                    return Err(wx_error("event already exists"));
+                }
+                self.kept_key(event.ingest_ts)?
+            } else {
+                // Assigned keys must stay unique even when several land in the same micro
+                let micros = wx::util::get_system_micros().max(last_micros + 1);
+                event.ingest_ts = micros;
+                last_micros = micros;
+                self.key_encoding().encode(micros)
+            };
+            batch.put_cf(cf, &key, self.encode_event(&event));
+            next += 1;
+            batch.put_cf(sequence_cf, next.to_be_bytes(), &key);
+            self.index_event(&mut batch, &event.event_type, &key);
+            self.count_event(&mut batch, &mut totals, &event.event_type)
+                .map_err(convert_error)?;
+        }
+
+        self.db.write(batch).map_err(convert_error)?;
+        let written = next - *sequence;
+        *sequence = next;
+        self.last_micros.store(last_micros, Ordering::SeqCst);
+        drop(sequence);
+        self.notify_event_writes(written);
+
+        Ok(serialize(&written).unwrap())
+    }
+
+    /// Whether an event with this ingest_ts is stored, under either encoding of its key, since
+    /// both may be present while keys are being migrated.
+    fn event_exists(&self, ingest_ts: u64) -> Result<bool, Error> {
+        let micros = match self.ingest_ts_key(ingest_ts)? {
+            Some(micros) => micros,
+            None => return Ok(false),
+        };
+        let cf = self.cf(EVENTS_CF);
+        for encoding in [KeyEncoding::Decimal, KeyEncoding::BigEndian].iter() {
+            if self
+                .db
+                .get_cf(cf, encoding.encode(micros))
+                .map_err(convert_error)?
+                .is_some()
+            {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Clones every event into another store over the usual protocol, for seeding a new instance.
+    /// The payload is the address of the target's data socket, which is sent `PutEventBatch`
+    /// requests of up to `import_batch_size` events read from a snapshot, each keeping its
+    /// ingest_ts. Returns the u64 number of events replicated.
+    pub fn replicate_to(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let address: String = self.parse_payload(payload, "invalid address")?;
+        let _stream = self.open_stream(Command::ReplicateTo)?;
+        let ctx = zmq::Context::new();
+        let sock = ctx.socket(zmq::REQ).map_err(|e| wx_error(&e.to_string()))?;
+        sock.set_linger(0)
+            .and_then(|_| sock.set_sndtimeo(REPLICATION_TIMEOUT_MILLIS))
+            .and_then(|_| sock.set_rcvtimeo(REPLICATION_TIMEOUT_MILLIS))
+            .and_then(|_| sock.connect(&address))
+            .map_err(|e| wx_error(&e.to_string()))?;
+
+        let snapshot = self.db.snapshot();
+        let mut iter = snapshot.raw_iterator_cf(self.cf(EVENTS_CF));
+        let mut events = Vec::new();
+        let mut replicated: u64 = 0;
+        iter.seek_to_first();
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if let Some(event) = self.read_event(key, value) {
+                events.push(event);
+            }
+            if events.len() == self.import_batch_size {
+                send_event_batch(&sock, &events)?;
+                replicated += events.len() as u64;
+                events.clear();
+            }
+            iter.next();
+        }
+
+        if !events.is_empty() {
+            send_event_batch(&sock, &events)?;
+            replicated += events.len() as u64;
+        }
+
+        Ok(serialize(&replicated).unwrap())
+    }
+
+    /// Imports events from a newline-delimited JSON file on the server, given its path. Events that
+    /// already have an ingest_ts keep it as their key, otherwise one is assigned. Like
+    /// `put_event_batch`, a kept ingest_ts must be new, so the import stops at an event already
+    /// stored or repeated in the file. Records are written every `import_batch_size` records, so
+    /// memory stays bounded for huge files, and batches written before a failing line are kept.
+    /// Returns the number of events imported and the number of batches written.
+    pub fn import_events(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let path: String = self.parse_payload(payload, "invalid import path")?;
+        let file = File::open(&path).map_err(|e| wx_error(&e.to_string()))?;
+        let cf = self.cf(EVENTS_CF);
+        let sequence_cf = self.cf(SEQUENCE_CF);
+        let mut sequence = self.sequence.lock().unwrap();
+        let mut batch = WriteBatch::default();
+        let mut batch_len = 0;
+        let mut imported: u64 = 0;
+        let mut flushes: u64 = 0;
+        let mut next = *sequence;
+        let mut last_micros = self.last_micros();
+        let mut totals = Vec::new();
+        let mut kept = BTreeSet::new();
+
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| wx_error(&e.to_string()))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let mut event: Event = serde_json::from_str(&line)
+                .map_err(|_| wx_error(&format!("invalid event on line {}", i + 1)))?;
+
+            let key = if event.ingest_ts == 0 {
+                // Assigned keys must stay unique even when several land in the same micro
+                let micros = wx::util::get_system_micros().max(last_micros + 1);
+                event.ingest_ts = micros;
+                last_micros = micros;
+                self.key_encoding().encode(micros)
+            } else {
+                // Earlier batches are already written, so only this one's keys need tracking
+                if !kept.insert(event.ingest_ts) || self.event_exists(event.ingest_ts)? {
+                    return Err(wx_error(&format!("event on line {} already exists", i + 1)));
+                }
+                self.kept_key(event.ingest_ts)?
+            };
+            let value = self.encode_event(&event);
+            batch.put_cf(cf, &key, &value);
+            next += 1;
+            batch.put_cf(sequence_cf, next.to_be_bytes(), &key);
+            self.index_event(&mut batch, &event.event_type, &key);
+            self.count_event(&mut batch, &mut totals, &event.event_type)
+                .map_err(convert_error)?;
+            batch_len += 1;
+
+            if batch_len == self.import_batch_size {
+                self.db.write(batch).map_err(convert_error)?;
+                *sequence = next;
+                self.last_micros.store(last_micros, Ordering::SeqCst);
+                imported += batch_len as u64;
+                batch = WriteBatch::default();
+                batch_len = 0;
+                kept.clear();
+                flushes += 1;
+            }
+        }
+
+        if batch_len > 0 {
+            self.db.write(batch).map_err(convert_error)?;
+            *sequence = next;
+            self.last_micros.store(last_micros, Ordering::SeqCst);
+            imported += batch_len as u64;
+            flushes += 1;
+        }
+
+        if imported > 0 {
+            self.notify_event_writes(imported);
+        }
+
+        Ok(serialize(&(imported, flushes)).unwrap())
+    }
+
+    /// Writes every event of the given type to a file on the server as newline-delimited JSON, the
+    /// same format `import_events` reads. The payload is a tuple of event type and path. Events are
+    /// read from a snapshot and written as they're read, so memory stays bounded. Returns the
+    /// number of events exported.
+    pub fn export_events_by_type(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let (event_type, path): (EventType, String) =
+            self.parse_payload(payload, "invalid export")?;
+        let _stream = self.open_stream(Command::ExportEventsByType)?;
+        let file = File::create(&path).map_err(|e| wx_error(&e.to_string()))?;
+        let mut writer = BufWriter::new(file);
+        let snapshot = self.db.snapshot();
+        let mut iter = snapshot.raw_iterator_cf(self.cf(EVENTS_CF));
+        let mut exported: u64 = 0;
+        iter.seek_to_first();
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if let Some(event) = self.read_event(key, value) {
+                if event.event_type == event_type {
+                    serde_json::to_writer(&mut writer, &event)
+                        .map_err(|e| wx_error(&e.to_string()))?;
+                    writeln!(writer).map_err(|e| wx_error(&e.to_string()))?;
+                    exported += 1;
+                }
+            }
+            iter.next();
+        }
+
+        writer.flush().map_err(|e| wx_error(&e.to_string()))?;
+
+        Ok(serialize(&exported).unwrap())
+    }
+
+    /// Records a consumer's position in the event stream, so independent consumers can each resume
+    /// where they left off. The payload is a tuple of consumer id and the u64 ingest_ts of the last
+    /// event it processed.
+    pub fn commit_offset(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        self.check_not_paused()?;
+        let (consumer, offset): (&str, u64) = self.parse_payload(payload, "invalid offset")?;
+        if consumer.is_empty() {
+            return Err(wx_error("invalid consumer id"));
+        }
+
+        self.db
+            .put_cf(
+                self.cf(OFFSETS_CF),
+                consumer.as_bytes(),
+                serialize(&offset).unwrap(),
+            )
+            .map_err(convert_error)?;
+
+        Ok(vec![])
+    }
+
+    /// Hands out events one at a time to competing workers, oldest first. The u64 payload is how
+    /// long in millis the claim lasts, after which the event can be claimed again, so events held
+    /// by a worker that died aren't lost. Claims are made one at a time, so each event has at most
+    /// one live claim. Nothing marks an event done, so a queue drains as its events are deleted or
+    /// expire. Returns an `Option<Event>`, None when every event is claimed.
+    pub fn claim_next_event(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        self.check_not_paused()?;
+        let lease_millis: u64 = self.parse_payload(payload, "invalid lease")?;
+        let claims_cf = self.cf(CLAIMS_CF);
+        let _claim = self.claims.lock().unwrap();
+        let now = wx::util::get_system_micros();
+        let _permit = self.readers.acquire();
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        self.seek_window(&mut iter);
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            let claimed_until = self
+                .db
+                .get_cf(claims_cf, key)
+                .map_err(convert_error)?
+                .and_then(|value| deserialize::<u64>(&value).ok());
+            if claimed_until.is_none_or(|claimed_until| claimed_until <= now) {
+                if let Some(event) = self.read_event(key, value) {
+                    let expiry = now.saturating_add(lease_millis.saturating_mul(1000));
+                    self.db
+                        .put_cf(claims_cf, key, serialize(&expiry).unwrap())
+                        .map_err(convert_error)?;
+                    return Ok(serialize(&Some(event)).unwrap());
+                }
+            }
+            iter.next();
+        }
+
+        Ok(serialize(&None::<Event>).unwrap())
+    }
+
+    /// Cursor-free consumption for simple clients. Returns every event since the consumer's
+    /// offset, or from the start of the window if it has none, and commits the newest one returned
+    /// as its new offset. Polls are serialized, so consecutive polls always return disjoint sets.
+    pub fn poll(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        self.check_not_paused()?;
+        let consumer: &str = self.parse_payload(payload, "invalid consumer id")?;
+        if consumer.is_empty() {
+            return Err(wx_error("invalid consumer id"));
+        }
+
+        let _poll = self.polls.lock().unwrap();
+        let offsets_cf = self.cf(OFFSETS_CF);
+        let offset = self
+            .db
+            .get_cf(offsets_cf, consumer.as_bytes())
+            .map_err(convert_error)?
+            .map(|value| deserialize::<u64>(&value))
+            .transpose()
+            .map_err(|_| wx_error("invalid stored offset"))?;
+
+        let _permit = self.readers.acquire();
+        let mut iter = self.db.raw_iterator_cf(self.cf(EVENTS_CF));
+        match offset {
+            Some(offset) => self.seek_after(&mut iter, offset),
+            None => self.seek_window(&mut iter),
+        }
+        let mut buffer = Vec::new();
+        let mut count: u64 = 0;
+        let mut newest = offset;
+
+        while iter.valid() {
+            let (key, value) = (iter.key().unwrap(), iter.value().unwrap());
+            if let Some((value, event)) = self.read_event_with_bytes(key, value) {
+                newest = newest.max(Some(event.ingest_ts));
+                buffer.extend_from_slice(&value);
+                count += 1;
+            }
+            iter.next();
+        }
+
+        if newest != offset {
+            self.db
+                .put_cf(
+                    offsets_cf,
+                    consumer.as_bytes(),
+                    serialize(&newest.unwrap()).unwrap(),
+                )
+                .map_err(convert_error)?;
+        }
+
+        Ok(envelope(count, &buffer))
+    }
+
+    /// Returns the consumer's last committed offset, or None if it has never committed one.
+    pub fn get_offset(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let consumer: &str = self.parse_payload(payload, "invalid consumer id")?;
+        let offset = self
+            .db
+            .get_cf(self.cf(OFFSETS_CF), consumer.as_bytes())
+            .map_err(convert_error)?
+            .map(|value| deserialize::<u64>(&value))
+            .transpose()
+            .map_err(|_| wx_error("invalid stored offset"))?;
+
+        Ok(serialize(&offset).unwrap())
+    }
+
+    /// Fetch failures are stored as the serialized bytes the client sends, keyed by big-endian
+    /// micros so they sort by time whatever the number of digits. Returns the u64 micros used as
+    /// the key.
+    pub fn put_fetch_failure(&self, value: &[u8]) -> Result<Vec<u8>, Error> {
+        self.check_not_paused()?;
+        let micros = wx::util::get_system_micros();
+        let key = KeyEncoding::BigEndian.encode(micros);
+        let cf = self.cf(FETCH_FAILURES_CF);
+        self.retry
+            .run(|| self.db.put_cf(cf, &key, value))
+            .map_err(convert_error)?;
+
+        Ok(serialize(&micros).unwrap())
+    }
+
+    /// Returns fetch failures newer than the configured threshold.
+    pub fn get_fetch_failures(&self) -> Result<Vec<u8>, Error> {
+        let micros = wx::util::get_system_micros() - self.fetch_failure_threshold_micros;
+        self.fetch_failures_from(micros)
+    }
+
+    /// Returns fetch failures at or after an explicit u64 micros lower bound, ignoring the
+    /// configured threshold.
+    pub fn get_fetch_failures_since(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let micros: u64 = self.parse_payload(payload, "invalid timestamp")?;
+        self.fetch_failures_from(micros)
+    }
+
+    /// Looks up a single fetch failure by the u64 key returned from `put_fetch_failure`. Like
+    /// `get`, a missing failure is an empty response rather than an error.
+    pub fn get_fetch_failure_by_key(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let micros: u64 = self.parse_payload(payload, "invalid fetch failure key")?;
+        let key = KeyEncoding::BigEndian.encode(micros);
+        let value = self
+            .db
+            .get_cf(self.cf(FETCH_FAILURES_CF), &key)
+            .map_err(convert_error)?;
+
+        Ok(value.map_or_else(Vec::new, |value| value.to_vec()))
+    }
+
+    fn fetch_failures_from(&self, micros: u64) -> Result<Vec<u8>, Error> {
+        let mut iter = self.db.raw_iterator_cf(self.cf(FETCH_FAILURES_CF));
+        iter.seek(KeyEncoding::BigEndian.encode(micros));
+
+        Ok(read_envelope(&mut iter))
+    }
+}
+
+impl Drop for Store {
+    /// Signals background workers to stop and waits for them, so the DB lock is released by the
+    /// time the store is gone.
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+
+        for worker in self.workers.drain(..) {
+            if worker.join().is_err() {
+                error!(self.logger, "shutdown"; "msg" => "background worker panicked");
+            }
+        }
     }
 }
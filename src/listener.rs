@@ -0,0 +1,230 @@
+use crate::{process_msg, wx_error, Config, Socket, Store};
+use bincode::serialize;
+use slog::Logger;
+use std::fs;
+use std::io;
+use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use wx::error::Error;
+use wx::store::Status;
+use zmq::Message;
+
+/// Wait before retrying a socket that failed to open, doubling for each retry up to the max. A
+/// closed socket is torn down asynchronously, so rebinding its address can fail for a moment.
+const REOPEN_BACKOFF: Duration = Duration::from_millis(10);
+const MAX_REOPEN_BACKOFF: Duration = Duration::from_secs(5);
+
+/// The store shared by every listener, which SwapStore can replace while they keep serving.
+/// Requests only hold it while they're processed, so a swap waits for requests in flight, but not
+/// for listeners idling in `recv`.
+pub struct SharedStore {
+    path: String,
+    config: Box<dyn Fn() -> Config + Send + Sync>,
+    /// Only None if a failed swap couldn't reopen the original store either.
+    store: RwLock<Option<Store>>,
+    logger: Logger,
+}
+
+impl SharedStore {
+    /// Opens the store at `path` with the settings from `config`, which is called again for each
+    /// store swapped in, so it's opened just like the one it replaces.
+    pub fn open<F>(path: &str, config: F) -> Result<SharedStore, Error>
+    where
+        F: Fn() -> Config + Send + Sync + 'static,
+    {
+        let first = config();
+        let logger = first.logger.clone();
+        let store = Store::open(path, first)?;
+
+        Ok(SharedStore {
+            path: path.to_string(),
+            config: Box::new(config),
+            store: RwLock::new(Some(store)),
+            logger,
+        })
+    }
+
+    /// Runs `f` against the current store. Fails rather than panicking if there's no store, or a
+    /// panic elsewhere poisoned the lock.
+    pub fn with_store<F, R>(&self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&Store) -> R,
+    {
+        let store = self
+            .store
+            .read()
+            .map_err(|_| wx_error("store unavailable"))?;
+        let store = store
+            .as_ref()
+            .ok_or_else(|| wx_error("store unavailable"))?;
+
+        Ok(f(store))
+    }
+
+    /// Processes a request, then swaps in any staging store it accepted, so the reply to SwapStore
+    /// says whether the swap went through.
+    pub fn process(&self, msg: &Message, socket: Socket) -> Result<Vec<u8>, Error> {
+        let value = self.with_store(|store| process_msg(msg, store, socket))??;
+        self.swap_if_pending()?;
+
+        Ok(value)
+    }
+
+    /// Swaps in the staging store accepted by SwapStore, if there is one. The staging store is
+    /// opened first, which runs any migrations it needs and turns it away if it can't be opened,
+    /// before the live store is touched. Only then is the write lock taken, holding off new
+    /// requests while the directories move and the store reopens. If that fails, the original is
+    /// put back and reopened.
+    fn swap_if_pending(&self) -> Result<(), Error> {
+        let staging_path = match self.with_store(Store::pending_swap)? {
+            Some(staging_path) => staging_path,
+            None => return Ok(()),
+        };
+        if let Err(e) = Store::open(&staging_path, (self.config)()) {
+            error!(self.logger, "swap_store";
+                "msg" => e.to_string(), "staging_path" => &staging_path);
+            self.with_store(Store::cancel_swap)?;
+            return Err(e);
+        }
+
+        let mut store = self
+            .store
+            .write()
+            .map_err(|_| wx_error("store unavailable"))?;
+        // Another listener may have swapped it while this one waited for the lock
+        if store.as_ref().and_then(Store::pending_swap) != Some(staging_path.clone()) {
+            return Ok(());
+        }
+        // The old store has to be closed before its directory can move
+        drop(store.take());
+        let swapped = match Store::swap_directories(&self.path, &staging_path) {
+            Ok(()) => Store::open(&self.path, (self.config)()).inspect_err(|_| {
+                if let Err(undo) = unswap_directories(&self.path, &staging_path) {
+                    error!(self.logger, "swap_store"; "msg" => undo.to_string());
+                }
+            }),
+            Err(e) => Err(wx_error(&e.to_string())),
+        };
+
+        match swapped {
+            Ok(swapped) => {
+                info!(self.logger, "swap_store";
+                    "msg" => "swapped", "staging_path" => &staging_path);
+                *store = Some(swapped);
+                Ok(())
+            }
+            Err(e) => {
+                error!(self.logger, "swap_store";
+                    "msg" => e.to_string(), "staging_path" => &staging_path);
+                match Store::open(&self.path, (self.config)()) {
+                    Ok(original) => *store = Some(original),
+                    Err(e) => error!(self.logger, "swap_store";
+                        "msg" => "unable to reopen store", "error" => e.to_string()),
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Reverses `Store::swap_directories`, returning the staging store to its path and the original
+/// to its place.
+fn unswap_directories(path: &str, staging_path: &str) -> io::Result<()> {
+    fs::rename(path, staging_path)?;
+    fs::rename(format!("{}.old", path), path)
+}
+
+/// The request/reply socket a listener serves, so listeners can be driven without ZeroMQ.
+pub trait Transport {
+    fn recv(&mut self, msg: &mut Message) -> Result<(), zmq::Error>;
+    fn send(&mut self, payload: Vec<u8>) -> Result<(), zmq::Error>;
+}
+
+impl Transport for zmq::Socket {
+    fn recv(&mut self, msg: &mut Message) -> Result<(), zmq::Error> {
+        zmq::Socket::recv(self, msg, 0)
+    }
+
+    fn send(&mut self, payload: Vec<u8>) -> Result<(), zmq::Error> {
+        zmq::Socket::send(self, payload, 0)
+    }
+}
+
+/// Serves requests on a socket from `open` until the process exits. A REP socket that fails to
+/// send is stuck waiting to send and can't receive the next request, so it's closed and replaced
+/// with a fresh one rather than wedging the thread. The client whose reply was lost times out.
+/// Sockets that fail to open are retried with backoff, so the thread never dies.
+pub fn spawn_listener<T, F>(
+    open: F,
+    socket: Socket,
+    store: Arc<SharedStore>,
+    logger: Logger,
+) -> JoinHandle<()>
+where
+    T: Transport,
+    F: Fn() -> Result<T, zmq::Error> + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut sock = open_with_backoff(&open, &logger);
+        let mut msg = Message::new();
+
+        loop {
+            if let Err(e) = handle_request(&mut sock, socket, &store, &logger, &mut msg) {
+                error!(logger, "listener";
+                    "msg" => "error sending on socket, resetting", "error" => e.to_string());
+                drop(sock);
+                sock = open_with_backoff(&open, &logger);
+            }
+        }
+    })
+}
+
+fn open_with_backoff<T, F>(open: &F, logger: &Logger) -> T
+where
+    F: Fn() -> Result<T, zmq::Error>,
+{
+    let mut backoff = REOPEN_BACKOFF;
+
+    loop {
+        match open() {
+            Ok(sock) => return sock,
+            Err(e) => {
+                error!(logger, "listener"; "msg" => "error opening socket, retrying",
+                    "error" => e.to_string(), "backoff_millis" => backoff.as_millis() as u64);
+                thread::sleep(backoff);
+                backoff = (backoff * 2).min(MAX_REOPEN_BACKOFF);
+            }
+        }
+    }
+}
+
+fn handle_request<T: Transport>(
+    sock: &mut T,
+    socket: Socket,
+    store: &SharedStore,
+    logger: &Logger,
+    msg: &mut Message,
+) -> Result<(), zmq::Error> {
+    if sock.recv(msg).is_ok() {
+        match store.process(msg, socket) {
+            Ok(value) => {
+                let mut payload = [Status::OkByte.value()].to_vec();
+                payload.extend_from_slice(&value);
+                sock.send(payload)?;
+            }
+            Err(e) => {
+                let error_msg = e.to_string();
+                error!(logger, "listener"; "msg" => &error_msg);
+                let mut payload = [Status::ErrorByte.value()].to_vec();
+                let error_bytes = serialize(&error_msg).unwrap();
+                payload.extend_from_slice(&error_bytes);
+                sock.send(payload)?;
+            }
+        }
+    } else {
+        error!(logger, "listener"; "msg" => "error receiving on socket");
+    }
+
+    Ok(())
+}
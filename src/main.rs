@@ -1,46 +1,271 @@
 #[macro_use]
 extern crate slog;
 
-use bincode::serialize;
-use wx::store::Status;
+use slog::Logger as SlogLogger;
+use std::env;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use wx::domain::EventType;
 use wx::util::Logger;
-use wx_storage::Store;
-use zmq::{Context, Message};
+use wx_storage::{spawn_listener, Config, KeyEncoding, Memtable, SharedStore, Socket, Store};
+use zmq::Context;
 
 const APP_NAME: &str = "wx_storage";
 const STORE_PATH: &str = "wx_store";
 const EVENT_THRESHOLD_MICROS: u64 = 1000 * 1000 * 60 * 60; // 1 hr
 const ZMQ_ADDRESS: &str = "tcp://127.0.0.1:31337";
+const ADMIN_ZMQ_ADDRESS: &str = "tcp://127.0.0.1:31338";
+const WORKERS_ADDRESS: &str = "inproc://workers";
+const WORKERS: &str = "4";
+const MAX_CONCURRENT_READERS: &str = "16";
+const MAX_OPEN_ITERATORS: &str = "32";
+const LONG_ITERATOR_SECS: &str = "300"; // 5 min
+const HWM: &str = "1000"; // zmq's default
+const STATS_DUMP_INTERVAL_SECS: &str = "600"; // 10 min
+const SLOW_REQUEST_MILLIS: &str = "1000";
+const BLOCK_CACHE_BYTES: &str = "8388608"; // RocksDB's default
 
 fn main() {
     let ctx = Context::new();
-    let sock = ctx.socket(zmq::REP).unwrap();
     let logger = Logger::new(APP_NAME);
-    let store = Store::new(STORE_PATH, EVENT_THRESHOLD_MICROS);
-    let mut msg = Message::new();
-
-    info!(logger, "initializing"; "zmq_address" => ZMQ_ADDRESS, "store_path" => STORE_PATH);
-    sock.bind(ZMQ_ADDRESS).unwrap();
-
-    loop {
-        if sock.recv(&mut msg, 0).is_ok() {
-            match wx_storage::process_msg(&msg, &store) {
-                Ok(value) => {
-                    let mut payload = [Status::OkByte.value()].to_vec();
-                    payload.extend_from_slice(&value);
-                    sock.send(payload, 0).unwrap();
-                }
-                Err(e) => {
-                    let error_msg = e.to_string();
-                    error!(logger, "listener"; "msg" => &error_msg);
-                    let mut payload = [Status::ErrorByte.value()].to_vec();
-                    let error_bytes = serialize(&error_msg).unwrap();
-                    payload.extend_from_slice(&error_bytes);
-                    sock.send(payload, 0).unwrap();
-                }
-            }
-        } else {
-            error!(logger, "listener"; "msg" => "error receiving on socket");
-        }
+    let admin_address = get_config("WX_STORAGE_ADMIN_ADDRESS", ADMIN_ZMQ_ADDRESS);
+    let workers: usize = get_config("WX_STORAGE_WORKERS", WORKERS).parse().unwrap();
+    let sndhwm: i32 = get_config("WX_STORAGE_SNDHWM", HWM).parse().unwrap();
+    let rcvhwm: i32 = get_config("WX_STORAGE_RCVHWM", HWM).parse().unwrap();
+    let retention_sweep_interval = env::var("WX_STORAGE_RETENTION_SWEEP_SECS")
+        .ok()
+        .map(|secs| Duration::from_secs(secs.parse().unwrap()));
+    let config = load_config(&logger);
+
+    // Everything in force is logged in a single entry. Anything secret must be redacted here.
+    info!(logger, "initializing";
+        "version" => env!("CARGO_PKG_VERSION"),
+        "zmq_address" => ZMQ_ADDRESS,
+        "admin_zmq_address" => &admin_address,
+        "store_path" => STORE_PATH,
+        "workers" => workers,
+        "sndhwm" => sndhwm,
+        "rcvhwm" => rcvhwm,
+        "max_concurrent_readers" => config.max_concurrent_readers,
+        "max_open_iterators" => config.max_open_iterators,
+        "long_iterator_secs" => config.long_iterator_threshold.as_secs(),
+        "event_threshold_micros" => config.event_threshold_micros,
+        "fetch_failure_threshold_micros" => config.fetch_failure_threshold_micros,
+        "compression_type" => config.compression_name(),
+        "key_encoding" => config.key_encoding.name(),
+        "event_memtable" => config.event_memtable.name(),
+        "max_background_jobs" => config.max_background_jobs,
+        "rate_limit_bytes_per_sec" => config.rate_limit_bytes_per_sec,
+        "max_total_wal_size" => config.max_total_wal_size,
+        "wal_size_limit_mb" => config.wal_size_limit_mb,
+        "recycle_log_file_num" => config.recycle_log_file_num,
+        "direct_reads" => config.direct_reads,
+        "direct_io_for_flush_and_compaction" => config.direct_io_for_flush_and_compaction,
+        "block_cache_bytes" => config.block_cache_bytes,
+        "warmup" => config.warmup,
+        "event_checksums" => config.event_checksums,
+        "encryption" => config.encryption_key_path.is_some(),
+        "redacted_fields" => config.redacted_fields.join(","),
+        "type_retention" => format!("{:?}", config.type_retention),
+        "retention_sweep_secs" => retention_sweep_interval.map(|interval| interval.as_secs()),
+        "ttl_expiry" => config.ttl_expiry,
+        "expire_annotations" => config.expire_annotations,
+        "envelope_version" => config.envelope_version,
+        "stats_dump_path" => config.stats_dump_path.as_ref().map_or("none", String::as_str),
+        "stats_dump_interval_secs" => config.stats_dump_interval.as_secs(),
+        "auto_flush_interval_secs" => config.auto_flush_interval.map(|interval| interval.as_secs()),
+        "error_detail" => config.error_detail,
+        "slow_request_millis" => config.slow_request_threshold.as_millis() as u64,
+        "audit_log_path" => config.audit_log_path.as_ref().map_or("none", String::as_str));
+
+    let config_logger = logger.clone();
+    let store = SharedStore::open(STORE_PATH, move || load_config(&config_logger)).unwrap();
+    if let Ok(Some(duration)) = store.with_store(Store::warmup_duration) {
+        info!(logger, "warmup"; "duration_micros" => duration.as_micros() as u64);
+    }
+    let store = Arc::new(store);
+
+    if let Some(interval) = retention_sweep_interval {
+        spawn_retention_sweep(interval, store.clone(), logger.clone());
+    }
+
+    // Data requests are fanned out from the public socket to a pool of worker threads, each with
+    // its own REP socket. The inproc endpoint must be bound before any worker connects to it.
+    let frontend = ctx.socket(zmq::ROUTER).unwrap();
+    let backend = ctx.socket(zmq::DEALER).unwrap();
+    set_hwm(&frontend, sndhwm, rcvhwm).unwrap();
+    frontend.bind(ZMQ_ADDRESS).unwrap();
+    backend.bind(WORKERS_ADDRESS).unwrap();
+
+    for _ in 0..workers {
+        let ctx = ctx.clone();
+        let open = move || -> Result<zmq::Socket, zmq::Error> {
+            let sock = ctx.socket(zmq::REP)?;
+            sock.set_linger(0)?;
+            sock.connect(WORKERS_ADDRESS)?;
+            Ok(sock)
+        };
+        spawn_listener(open, Socket::Data, store.clone(), logger.clone());
+    }
+
+    // Admin commands are infrequent, so a single thread serves them.
+    let admin_ctx = ctx.clone();
+    let open_admin = move || -> Result<zmq::Socket, zmq::Error> {
+        let sock = admin_ctx.socket(zmq::REP)?;
+        sock.set_linger(0)?;
+        set_hwm(&sock, sndhwm, rcvhwm)?;
+        sock.bind(&admin_address)?;
+        Ok(sock)
+    };
+    spawn_listener(open_admin, Socket::Admin, store.clone(), logger.clone());
+
+    if let Err(e) = zmq::proxy(&frontend, &backend) {
+        error!(logger, "listener"; "msg" => e.to_string());
+    }
+}
+
+/// Reads the store's settings from the environment. Called again whenever a swapped store is
+/// opened, so it's opened just like the one it replaces.
+fn load_config(logger: &SlogLogger) -> Config {
+    Config {
+        event_threshold_micros: EVENT_THRESHOLD_MICROS,
+        logger: logger.clone(),
+        key_encoding: match get_config("WX_STORAGE_KEY_ENCODING", "decimal").as_str() {
+            "decimal" => KeyEncoding::Decimal,
+            "big_endian" => KeyEncoding::BigEndian,
+            other => panic!("unknown key encoding: {}", other),
+        },
+        event_memtable: match get_config("WX_STORAGE_EVENT_MEMTABLE", "skiplist").as_str() {
+            "skiplist" => Memtable::SkipList,
+            "vector" => Memtable::Vector,
+            other => panic!("unknown memtable: {}", other),
+        },
+        max_background_jobs: env::var("WX_STORAGE_MAX_BACKGROUND_JOBS")
+            .ok()
+            .map(|jobs| jobs.parse().unwrap()),
+        rate_limit_bytes_per_sec: env::var("WX_STORAGE_RATE_LIMIT_BYTES_PER_SEC")
+            .ok()
+            .map(|bytes| bytes.parse().unwrap()),
+        max_total_wal_size: env::var("WX_STORAGE_MAX_TOTAL_WAL_SIZE")
+            .ok()
+            .map(|size| size.parse().unwrap()),
+        wal_size_limit_mb: env::var("WX_STORAGE_WAL_SIZE_LIMIT_MB")
+            .ok()
+            .map(|size| size.parse().unwrap()),
+        recycle_log_file_num: env::var("WX_STORAGE_RECYCLE_LOG_FILE_NUM")
+            .ok()
+            .map(|num| num.parse().unwrap()),
+        stats_dump_path: env::var("WX_STORAGE_STATS_DUMP_PATH").ok(),
+        audit_log_path: env::var("WX_STORAGE_AUDIT_LOG_PATH").ok(),
+        encryption_key_path: env::var("WX_STORAGE_ENCRYPTION_KEY_PATH").ok(),
+        auto_flush_interval: env::var("WX_STORAGE_AUTO_FLUSH_INTERVAL_SECS")
+            .ok()
+            .map(|secs| Duration::from_secs(secs.parse().unwrap())),
+        stats_dump_interval: Duration::from_secs(
+            get_config(
+                "WX_STORAGE_STATS_DUMP_INTERVAL_SECS",
+                STATS_DUMP_INTERVAL_SECS,
+            )
+            .parse()
+            .unwrap(),
+        ),
+        slow_request_threshold: Duration::from_millis(
+            get_config("WX_STORAGE_SLOW_REQUEST_MILLIS", SLOW_REQUEST_MILLIS)
+                .parse()
+                .unwrap(),
+        ),
+        block_cache_bytes: get_config("WX_STORAGE_BLOCK_CACHE_BYTES", BLOCK_CACHE_BYTES)
+            .parse()
+            .unwrap(),
+        warmup: get_config("WX_STORAGE_WARMUP", "false").parse().unwrap(),
+        direct_reads: get_config("WX_STORAGE_DIRECT_READS", "false")
+            .parse()
+            .unwrap(),
+        direct_io_for_flush_and_compaction: get_config(
+            "WX_STORAGE_DIRECT_IO_FOR_FLUSH_AND_COMPACTION",
+            "false",
+        )
+        .parse()
+        .unwrap(),
+        event_checksums: get_config("WX_STORAGE_EVENT_CHECKSUMS", "false")
+            .parse()
+            .unwrap(),
+        error_detail: get_config("WX_STORAGE_ERROR_DETAIL", "false")
+            .parse()
+            .unwrap(),
+        redacted_fields: get_config("WX_STORAGE_REDACTED_FIELDS", "")
+            .split(',')
+            .filter(|field| !field.is_empty())
+            .map(String::from)
+            .collect(),
+        type_retention: get_config("WX_STORAGE_TYPE_RETENTION", "")
+            .split(',')
+            .filter(|rule| !rule.is_empty())
+            .map(parse_retention)
+            .collect(),
+        ttl_expiry: get_config("WX_STORAGE_TTL_EXPIRY", "false")
+            .parse()
+            .unwrap(),
+        expire_annotations: get_config("WX_STORAGE_EXPIRE_ANNOTATIONS", "false")
+            .parse()
+            .unwrap(),
+        envelope_version: get_config("WX_STORAGE_ENVELOPE_VERSION", "false")
+            .parse()
+            .unwrap(),
+        max_concurrent_readers: get_config(
+            "WX_STORAGE_MAX_CONCURRENT_READERS",
+            MAX_CONCURRENT_READERS,
+        )
+        .parse()
+        .unwrap(),
+        max_open_iterators: get_config("WX_STORAGE_MAX_OPEN_ITERATORS", MAX_OPEN_ITERATORS)
+            .parse()
+            .unwrap(),
+        long_iterator_threshold: Duration::from_secs(
+            get_config("WX_STORAGE_LONG_ITERATOR_SECS", LONG_ITERATOR_SECS)
+                .parse()
+                .unwrap(),
+        ),
+        ..Config::default()
     }
 }
+
+/// High-water marks only apply to connections made after they're set, so this must be called before
+/// binding.
+fn set_hwm(sock: &zmq::Socket, sndhwm: i32, rcvhwm: i32) -> Result<(), zmq::Error> {
+    sock.set_sndhwm(sndhwm)?;
+    sock.set_rcvhwm(rcvhwm)
+}
+
+/// Enforces the retention policy every interval, for the caps compaction can't apply.
+fn spawn_retention_sweep(interval: Duration, store: Arc<SharedStore>, logger: SlogLogger) {
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        if let Err(e) = store
+            .with_store(Store::sweep_events)
+            .and_then(|swept| swept)
+        {
+            error!(logger, "retention_sweep"; "msg" => e.to_string());
+        }
+    });
+}
+
+fn get_config(key: &str, default: &str) -> String {
+    env::var(key).unwrap_or_else(|_| default.to_string())
+}
+
+/// Parses a retention rule of an event type, named as in exported JSON, and an age in seconds,
+/// separated by a colon.
+fn parse_retention(rule: &str) -> (EventType, Duration) {
+    let mut parts = rule.splitn(2, ':');
+    let name = parts.next().unwrap();
+    let event_type = serde_json::from_value(serde_json::Value::String(name.to_string()))
+        .unwrap_or_else(|_| panic!("unknown event type: {}", name));
+    let secs = parts
+        .next()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or_else(|| panic!("invalid retention: {}", rule));
+
+    (event_type, Duration::from_secs(secs))
+}
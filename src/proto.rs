@@ -0,0 +1,193 @@
+//! Protobuf messages of the `GetEventsProto` response, matching `proto/event.proto`. They're
+//! written out by hand rather than generated, so building doesn't need `protoc`, and have to be
+//! kept in step with the schema.
+
+use prost::Message;
+use serde_json::Value;
+use std::convert::TryFrom;
+use wx::domain;
+use wx::error::Error;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Coordinates {
+    #[prost(float, tag = "1")]
+    pub lat: f32,
+    #[prost(float, tag = "2")]
+    pub lon: f32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Polygon {
+    #[prost(message, repeated, tag = "1")]
+    pub points: Vec<Coordinates>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Location {
+    #[prost(string, optional, tag = "1")]
+    pub wfo: Option<String>,
+    #[prost(message, optional, tag = "2")]
+    pub point: Option<Coordinates>,
+    #[prost(message, optional, tag = "3")]
+    pub poly: Option<Polygon>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Event {
+    #[prost(uint64, tag = "1")]
+    pub event_ts: u64,
+    #[prost(string, tag = "2")]
+    pub event_type: String,
+    #[prost(uint64, optional, tag = "3")]
+    pub expires_ts: Option<u64>,
+    #[prost(string, optional, tag = "4")]
+    pub ext_uri: Option<String>,
+    #[prost(uint64, tag = "5")]
+    pub ingest_ts: u64,
+    #[prost(message, optional, tag = "6")]
+    pub location: Option<Location>,
+    #[prost(string, optional, tag = "7")]
+    pub md: Option<String>,
+    #[prost(string, optional, tag = "8")]
+    pub outlook: Option<String>,
+    #[prost(string, optional, tag = "9")]
+    pub report: Option<String>,
+    #[prost(string, optional, tag = "10")]
+    pub text: Option<String>,
+    #[prost(string, tag = "11")]
+    pub title: String,
+    #[prost(uint64, optional, tag = "12")]
+    pub valid_ts: Option<u64>,
+    #[prost(string, optional, tag = "13")]
+    pub warning: Option<String>,
+    #[prost(string, optional, tag = "14")]
+    pub watch: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Events {
+    #[prost(message, repeated, tag = "1")]
+    pub events: Vec<Event>,
+}
+
+/// Decodes a `GetEventsProto` response back into events.
+pub fn decode_events(bytes: &[u8]) -> Result<Vec<domain::Event>, Error> {
+    let events = Events::decode(bytes).map_err(|e| crate::wx_error(&e.to_string()))?;
+    events
+        .events
+        .into_iter()
+        .map(domain::Event::try_from)
+        .collect()
+}
+
+impl From<&domain::Coordinates> for Coordinates {
+    fn from(coordinates: &domain::Coordinates) -> Coordinates {
+        Coordinates {
+            lat: coordinates.lat,
+            lon: coordinates.lon,
+        }
+    }
+}
+
+impl From<Coordinates> for domain::Coordinates {
+    fn from(coordinates: Coordinates) -> domain::Coordinates {
+        domain::Coordinates {
+            lat: coordinates.lat,
+            lon: coordinates.lon,
+        }
+    }
+}
+
+impl From<&domain::Location> for Location {
+    fn from(location: &domain::Location) -> Location {
+        Location {
+            wfo: location.wfo.clone(),
+            point: location.point.as_ref().map(Coordinates::from),
+            poly: location.poly.as_ref().map(|points| Polygon {
+                points: points.iter().map(Coordinates::from).collect(),
+            }),
+        }
+    }
+}
+
+impl From<Location> for domain::Location {
+    fn from(location: Location) -> domain::Location {
+        domain::Location {
+            wfo: location.wfo,
+            point: location.point.map(domain::Coordinates::from),
+            poly: location.poly.map(|poly| {
+                poly.points
+                    .into_iter()
+                    .map(domain::Coordinates::from)
+                    .collect()
+            }),
+        }
+    }
+}
+
+impl TryFrom<&domain::Event> for Event {
+    type Error = Error;
+
+    fn try_from(event: &domain::Event) -> Result<Event, Error> {
+        let event_type = match serde_json::to_value(event.event_type) {
+            Ok(Value::String(name)) => name,
+            _ => return Err(crate::wx_error("invalid event type")),
+        };
+
+        Ok(Event {
+            event_ts: event.event_ts,
+            event_type,
+            expires_ts: event.expires_ts,
+            ext_uri: event.ext_uri.clone(),
+            ingest_ts: event.ingest_ts,
+            location: event.location.as_ref().map(Location::from),
+            md: to_json(&event.md)?,
+            outlook: to_json(&event.outlook)?,
+            report: to_json(&event.report)?,
+            text: event.text.clone(),
+            title: event.title.clone(),
+            valid_ts: event.valid_ts,
+            warning: to_json(&event.warning)?,
+            watch: to_json(&event.watch)?,
+        })
+    }
+}
+
+impl TryFrom<Event> for domain::Event {
+    type Error = Error;
+
+    fn try_from(event: Event) -> Result<domain::Event, Error> {
+        let event_type = serde_json::from_value(Value::String(event.event_type))
+            .map_err(|_| crate::wx_error("invalid event type"))?;
+
+        Ok(domain::Event {
+            event_ts: event.event_ts,
+            event_type,
+            expires_ts: event.expires_ts,
+            ext_uri: event.ext_uri,
+            ingest_ts: event.ingest_ts,
+            location: event.location.map(domain::Location::from),
+            md: from_json(event.md)?,
+            outlook: from_json(event.outlook)?,
+            report: from_json(event.report)?,
+            text: event.text,
+            title: event.title,
+            valid_ts: event.valid_ts,
+            warning: from_json(event.warning)?,
+            watch: from_json(event.watch)?,
+        })
+    }
+}
+
+fn to_json<T: serde::Serialize>(detail: &Option<T>) -> Result<Option<String>, Error> {
+    detail
+        .as_ref()
+        .map(|detail| serde_json::to_string(detail).map_err(|e| crate::wx_error(&e.to_string())))
+        .transpose()
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(detail: Option<String>) -> Result<Option<T>, Error> {
+    detail
+        .map(|detail| serde_json::from_str(&detail).map_err(|e| crate::wx_error(&e.to_string())))
+        .transpose()
+}
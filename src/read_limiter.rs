@@ -0,0 +1,77 @@
+use std::sync::{Condvar, Mutex};
+
+/// Bounds how many reads iterate the store at once. Under heavy read concurrency, each read creates
+/// its own iterator and implicit snapshot, so rather than allocating them unboundedly, requests
+/// block briefly until a permit is free.
+///
+/// The snapshots and iterators themselves aren't pooled for reuse. Each one pins the version of
+/// the store it was created at, so a reused one would serve reads that miss later writes, and the
+/// binding ties both to a borrow of the DB, so they can't be kept in the store itself. Creating
+/// them is cheap next to iterating; holding too many at once is what costs memory.
+pub(crate) struct ReadLimiter {
+    capacity: usize,
+    state: Mutex<LimiterState>,
+    available: Condvar,
+}
+
+struct LimiterState {
+    in_use: usize,
+    peak: usize,
+}
+
+/// Returns its permit to the limiter when dropped.
+pub(crate) struct ReadPermit<'a> {
+    limiter: &'a ReadLimiter,
+}
+
+impl ReadLimiter {
+    pub(crate) fn new(capacity: usize) -> ReadLimiter {
+        ReadLimiter {
+            capacity: capacity.max(1),
+            state: Mutex::new(LimiterState { in_use: 0, peak: 0 }),
+            available: Condvar::new(),
+        }
+    }
+
+    pub(crate) fn acquire(&self) -> ReadPermit<'_> {
+        let mut state = self.state.lock().unwrap();
+        while state.in_use >= self.capacity {
+            state = self.available.wait(state).unwrap();
+        }
+
+        state.in_use += 1;
+        state.peak = state.peak.max(state.in_use);
+
+        ReadPermit { limiter: self }
+    }
+
+    /// Like `acquire`, but returns None rather than waiting when every permit is held.
+    pub(crate) fn try_acquire(&self) -> Option<ReadPermit<'_>> {
+        let mut state = self.state.lock().unwrap();
+        if state.in_use >= self.capacity {
+            return None;
+        }
+
+        state.in_use += 1;
+        state.peak = state.peak.max(state.in_use);
+
+        Some(ReadPermit { limiter: self })
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The most permits that have been held at once.
+    pub(crate) fn peak(&self) -> usize {
+        self.state.lock().unwrap().peak
+    }
+}
+
+impl<'a> Drop for ReadPermit<'a> {
+    fn drop(&mut self) {
+        let mut state = self.limiter.state.lock().unwrap();
+        state.in_use -= 1;
+        self.limiter.available.notify_one();
+    }
+}
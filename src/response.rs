@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+use wx::domain::EventType;
+
+/// Response to `Command::Info`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Info {
+    pub uptime_micros: u64,
+    /// Total requests received, including the one asking for this.
+    pub requests: u64,
+    pub errors: u64,
+    pub events: u64,
+    pub fetch_failures: u64,
+}
+
+/// Entry in the response to `Command::ErrorStats`. The error is the message with numbers replaced
+/// by `N`, so failures differing only in keys or sizes are counted together.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ErrorCount {
+    /// The command's name, or "unknown" if the command byte wasn't recognized or readable.
+    pub command: String,
+    pub error: String,
+    pub count: u64,
+}
+
+/// Per column family entry in the response to `Command::CfStats`. Both values are RocksDB's
+/// estimates.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CfStats {
+    pub keys: u64,
+    pub sst_bytes: u64,
+}
+
+/// Response to `Command::GetConfig`, the effective settings of the running store.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Settings {
+    pub event_threshold_micros: u64,
+    pub fetch_failure_threshold_micros: u64,
+    pub compression_type: String,
+    pub key_encoding: String,
+    pub max_key_length: u64,
+    pub max_concurrent_readers: u64,
+    pub import_batch_size: u64,
+    pub event_checksums: bool,
+    pub ttl_expiry: bool,
+    pub envelope_version: bool,
+}
+
+/// Entry in the response to `Command::SstFiles`, describing one live SST file.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SstFile {
+    pub column_family: String,
+    /// File name relative to the store's directory.
+    pub name: String,
+    pub level: i32,
+    pub size: u64,
+    /// The smallest and largest keys in the file, in their stored encoding.
+    pub start_key: Option<Vec<u8>>,
+    pub end_key: Option<Vec<u8>>,
+    pub num_entries: u64,
+    pub num_deletions: u64,
+}
+
+/// Response to `Command::CompactionBacklog`. A steadily growing `pending_bytes` means compaction
+/// is falling behind writes, and reads will slow down until it catches up.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CompactionBacklog {
+    pub running: u64,
+    /// Whether any column family has files waiting to be compacted.
+    pub pending: bool,
+    /// RocksDB's estimate of the bytes to rewrite to bring every column family back into shape.
+    pub pending_bytes: u64,
+}
+
+/// Response to `Command::StorageEfficiency`. SST figures cover live files in every column family,
+/// and write figures cover everything since the store was opened.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StorageEfficiency {
+    /// Uncompressed key and value bytes in SST files.
+    pub logical_bytes: u64,
+    pub sst_bytes: u64,
+    /// Logical over SST bytes, or 0 if nothing has been flushed.
+    pub compression_ratio: f64,
+    /// Bytes written by clients, before any flush or compaction.
+    pub bytes_written: u64,
+    pub flush_bytes: u64,
+    pub compaction_bytes: u64,
+    /// Flushed and compacted over written bytes, or 0 if nothing has been written.
+    pub write_amplification: f64,
+}
+
+/// Response to `Command::EventSizeStats`. Sizes are stored bytes, all zero if there are no events.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SizeStats {
+    /// Number of events measured, which is a sample of the window on large stores.
+    pub sampled: u64,
+    pub min: u64,
+    pub mean: u64,
+    pub p50: u64,
+    pub p95: u64,
+    pub max: u64,
+}
+
+/// Response to `Command::IngestLagStats`. Lags are micros from event_ts to ingest_ts, all zero if
+/// no events were measured.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LagStats {
+    pub measured: u64,
+    /// Events stamped after they were ingested, which means clock skew upstream. These are left
+    /// out of the lag figures rather than clamped to zero, so they don't hide real lag.
+    pub skewed: u64,
+    pub min: u64,
+    pub mean: u64,
+    pub p95: u64,
+    pub max: u64,
+}
+
+/// A note attached to an event by `Command::AnnotateEvent`, as returned by
+/// `Command::GetAnnotations`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Annotation {
+    pub ingest_ts: u64,
+    /// When the note was added, in micros.
+    pub annotated_ts: u64,
+    pub note: String,
+}
+
+/// Response to `Command::VerifyIndexes`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct IndexReport {
+    /// Events checked, not counting any that fail their checksum or can't be decrypted.
+    pub events: u64,
+    /// Events missing from the type index.
+    pub missing: u64,
+    /// Type index entries whose event is gone or holds another type, not counting events that
+    /// compaction expired.
+    pub orphaned: u64,
+}
+
+/// Sent with `Command::SetRetentionPolicy` and returned by `Command::GetRetentionPolicy`.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct RetentionPolicy {
+    /// Events of these types are dropped once older than the paired micros.
+    pub type_retention: Vec<(EventType, u64)>,
+    /// Sweeps delete the oldest events until no more than this many remain.
+    pub max_events: Option<u64>,
+    /// Sweeps delete the oldest events until the rest take up no more than this many bytes,
+    /// counted as stored keys and values, before compression.
+    pub max_bytes: Option<u64>,
+}
+
+/// Response to `Command::WriteThroughput`, in event writes per second over each interval.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Throughput {
+    pub last_10s: f64,
+    pub last_1m: f64,
+    pub last_5m: f64,
+}
+
+/// Lightweight projection of an `Event` returned by `Command::GetEventHeaders`, leaving out the
+/// text and structured bodies that dominate its size.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EventHeader {
+    pub ingest_ts: u64,
+    pub event_type: EventType,
+    pub title: String,
+    /// The first line of the event's text, truncated to `HEADER_SUMMARY_CHARS`.
+    pub summary: Option<String>,
+    pub expires_ts: Option<u64>,
+}
@@ -0,0 +1,52 @@
+use std::fmt::Display;
+use std::thread;
+use std::time::Duration;
+
+/// RocksDB reports transient conditions, such as writes stalled behind compaction, with these
+/// status prefixes. Anything else is a hard failure that retrying won't fix.
+const TRANSIENT_ERRORS: [&str; 3] = ["Resource busy", "Operation timed out", "Try again"];
+
+/// Bounded retry with exponential backoff for operations that can fail transiently.
+#[derive(Clone, Copy, Debug)]
+pub struct Retry {
+    /// Retries after the first attempt. Zero disables retrying.
+    pub retries: u32,
+    /// Wait before the first retry, doubling for each one after.
+    pub backoff: Duration,
+}
+
+impl Retry {
+    /// Runs the operation, retrying transient failures until they succeed or retries run out.
+    /// Hard failures are returned immediately.
+    pub fn run<T, E: Display>(&self, op: impl FnMut() -> Result<T, E>) -> Result<T, E> {
+        self.run_if(op, is_transient)
+    }
+
+    /// Same as `run`, but with the caller deciding which failures are transient.
+    pub fn run_if<T, E>(
+        &self,
+        mut op: impl FnMut() -> Result<T, E>,
+        is_transient: impl Fn(&E) -> bool,
+    ) -> Result<T, E> {
+        let mut backoff = self.backoff;
+        let mut retries = 0;
+
+        loop {
+            match op() {
+                Err(ref e) if retries < self.retries && is_transient(e) => {
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                    retries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+fn is_transient<E: Display>(e: &E) -> bool {
+    let message = e.to_string();
+    TRANSIENT_ERRORS
+        .iter()
+        .any(|prefix| message.starts_with(prefix))
+}
@@ -0,0 +1,47 @@
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Seconds of history kept, which bounds the longest interval a rate can be asked for.
+pub(crate) const WINDOW_SECS: usize = 300;
+
+/// Counts event writes in one-second buckets over the last `WINDOW_SECS`, reusing each bucket as
+/// the window wraps around, so recording a write only touches a single bucket.
+pub(crate) struct WriteRate {
+    started: Instant,
+    /// Pairs of the second since `started` each bucket was last used for, and its count.
+    buckets: Mutex<Vec<(u64, u64)>>,
+}
+
+impl WriteRate {
+    pub(crate) fn new() -> WriteRate {
+        WriteRate {
+            started: Instant::now(),
+            buckets: Mutex::new(vec![(u64::MAX, 0); WINDOW_SECS]),
+        }
+    }
+
+    pub(crate) fn record(&self, writes: u64) {
+        let now = self.started.elapsed().as_secs();
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = &mut buckets[now as usize % WINDOW_SECS];
+        if bucket.0 != now {
+            *bucket = (now, 0);
+        }
+        bucket.1 += writes;
+    }
+
+    /// Mean writes per second over the last `secs` seconds, including the current one. Seconds
+    /// before the store was opened count as having no writes.
+    pub(crate) fn per_sec(&self, secs: u64) -> f64 {
+        let secs = secs.max(1).min(WINDOW_SECS as u64);
+        let now = self.started.elapsed().as_secs();
+        let buckets = self.buckets.lock().unwrap();
+        let writes: u64 = buckets
+            .iter()
+            .filter(|(second, _)| *second <= now && now - *second < secs)
+            .map(|(_, count)| count)
+            .sum();
+
+        writes as f64 / secs as f64
+    }
+}
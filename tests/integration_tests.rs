@@ -1,10 +1,23 @@
 extern crate wx_storage;
+#[macro_use]
+extern crate slog;
 
 use bincode::{deserialize, serialize};
+use flate2::read::GzDecoder;
 use rocksdb::{Options, DB};
-use std::{str, thread, time};
-use wx::domain::{Event, EventType};
-use wx_storage::{process_msg, Store};
+use slog::{Discard, Drain, Key, Logger, OwnedKVList, Record, Serializer, KV};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Read;
+use std::sync::{mpsc, Arc, Mutex};
+use std::{fmt, fs, str, thread, time};
+use wx::domain::{Coordinates, Event, EventType, Location};
+use wx::store::Status;
+use wx_storage::{
+    process_msg, proto, spawn_listener, Annotation, CfStats, Command, CompactionBacklog, Config,
+    ErrorCount, EventHeader, IndexReport, Info, KeyEncoding, LagStats, Memtable, RetentionPolicy,
+    Retry, Settings, SharedStore, SizeStats, Socket, SstFile, StorageEfficiency, Store, Throughput,
+    Transport, ENVELOPE_VERSION, GZIP_FLAG, SCHEMA_VERSION, TRACE_FLAG, ZSTD_FLAG,
+};
 use zmq::Message;
 
 const EVENT_THRESHOLD_MICROS: u64 = 1000 * 1000 * 60 * 60; // 1 hr
@@ -15,6 +28,44 @@ fn destroy_store() {
     DB::destroy(&opts, TEST_STORE_PATH).unwrap();
 }
 
+/// Captures each log record as a line of `msg key=value ...` so tests can assert on it.
+struct CaptureDrain(Arc<Mutex<Vec<String>>>);
+
+struct CaptureSerializer(String);
+
+impl Serializer for CaptureSerializer {
+    fn emit_arguments(&mut self, key: Key, val: &fmt::Arguments) -> slog::Result {
+        self.0.push_str(&format!(" {}={}", key, val));
+        Ok(())
+    }
+}
+
+impl Drain for CaptureDrain {
+    type Ok = ();
+    type Err = slog::Never;
+
+    fn log(&self, record: &Record, _: &OwnedKVList) -> Result<(), slog::Never> {
+        let mut serializer = CaptureSerializer(record.msg().to_string());
+        record.kv().serialize(record, &mut serializer).unwrap();
+        self.0.lock().unwrap().push(serializer.0);
+        Ok(())
+    }
+}
+
+fn get_capture_logger() -> (Logger, Arc<Mutex<Vec<String>>>) {
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let logger = Logger::root(CaptureDrain(lines.clone()), o!());
+    (logger, lines)
+}
+
+/// Any event type other than the one used by `get_test_event`.
+fn get_other_event_type() -> EventType {
+    (0u32..)
+        .map(|i| deserialize::<EventType>(&serialize(&i).unwrap()).unwrap())
+        .find(|event_type| *event_type != EventType::NwsLsr)
+        .unwrap()
+}
+
 fn get_test_event() -> Event {
     Event {
         event_ts: 1548378900711570,
@@ -34,20 +85,51 @@ fn get_test_event() -> Event {
     }
 }
 
+/// Puts `count` test events at least a millisecond apart, returning their keys.
+fn put_spaced_events(store: &Store, count: usize) -> Vec<u64> {
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    let msg = Message::from_slice(&payload);
+
+    (0..count)
+        .map(|_| {
+            let key = deserialize(&process_msg(&msg, store, Socket::Data).unwrap()).unwrap();
+            thread::sleep(time::Duration::from_millis(1));
+            key
+        })
+        .collect()
+}
+
+/// Deletes the middle of three events and rekeys, returning the ingest_ts of the other two and
+/// the key the older one moved to.
+fn rekey_with_gap(store: &Store) -> (u64, u64, u64) {
+    let keys = put_spaced_events(store, 3);
+    let mut payload = [Command::DeleteEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&keys[1]).unwrap());
+    process_msg(&Message::from_slice(&payload), store, Socket::Admin).unwrap();
+
+    let msg = Message::from_slice(&[Command::RekeyEvents.value()]);
+    let result = process_msg(&msg, store, Socket::Admin).unwrap();
+    let (min, _): (u64, u64) = deserialize::<Option<(u64, u64)>>(&result).unwrap().unwrap();
+    assert!(min > keys[0]);
+
+    (keys[0], keys[2], min)
+}
+
 #[test]
 fn zero_message_length_should_error() {
     let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
     let msg = Message::new();
-    let result = process_msg(&msg, &store);
+    let result = process_msg(&msg, &store, Socket::Data);
     assert!(result.is_err())
 }
 
 #[test]
 fn unknown_command_should_error() {
     let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
-    let payload = b"4";
-    let msg = Message::from_slice(payload);
-    let result = process_msg(&msg, &store);
+    // The highest command byte without the trace flag set
+    let msg = Message::from_slice(&[0x7f]);
+    let result = process_msg(&msg, &store, Socket::Data);
     assert!(result.is_err())
 }
 
@@ -63,14 +145,14 @@ fn put_and_get_should_work() {
     let mut payload = [0u8].to_vec();
     payload.extend_from_slice(&kv);
     let msg = Message::from_slice(&payload);
-    let result = process_msg(&msg, &store);
+    let result = process_msg(&msg, &store, Socket::Data);
     assert!(result.is_ok());
 
     // get
     let mut payload = [1u8].to_vec();
     payload.extend_from_slice(key.as_bytes());
     let msg = Message::from_slice(&payload);
-    let result = process_msg(&msg, &store);
+    let result = process_msg(&msg, &store, Socket::Data);
     assert!(result.is_ok());
     assert!(result.unwrap() == value);
 }
@@ -83,7 +165,7 @@ fn get_should_return_nothing_if_key_not_found() {
     let mut payload = [1u8].to_vec();
     payload.extend_from_slice(key.as_bytes());
     let msg = Message::from_slice(&payload);
-    let result = process_msg(&msg, &store);
+    let result = process_msg(&msg, &store, Socket::Data);
     let expected: Vec<u8> = vec![];
     assert!(result.unwrap() == expected);
 }
@@ -96,7 +178,7 @@ fn put_event_should_return_a_u64() {
     let mut payload = [2u8].to_vec();
     payload.extend_from_slice(&serialize(&event).unwrap());
     let msg = Message::from_slice(&payload);
-    let result = process_msg(&msg, &store);
+    let result = process_msg(&msg, &store, Socket::Data);
     assert!(result.unwrap().len() == 8)
 }
 
@@ -109,12 +191,12 @@ fn put_event_and_get_events_should_persist_an_event_and_populate_ingest_ts() {
     let mut payload = [2u8].to_vec();
     payload.extend_from_slice(&serialize(&event).unwrap());
     let msg = Message::from_slice(&payload);
-    let key = process_msg(&msg, &store).unwrap();
+    let key = process_msg(&msg, &store, Socket::Data).unwrap();
     let expected_key: u64 = deserialize(&key).unwrap();
 
     let payload = [3u8].to_vec();
     let msg = Message::from_slice(&payload);
-    let result = process_msg(&msg, &store).unwrap();
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
     let result: Vec<Event> = deserialize(&result).unwrap();
     let result = &result[0];
     assert_eq!(result.event_ts, event.event_ts);
@@ -123,6 +205,38 @@ fn put_event_and_get_events_should_persist_an_event_and_populate_ingest_ts() {
     assert_eq!(result.event_type, event.event_type);
 }
 
+#[test]
+fn concurrent_put_events_should_each_get_their_own_key() {
+    destroy_store();
+    let store = Arc::new(Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS));
+    let writers: Vec<_> = (0..8)
+        .map(|_| {
+            let store = store.clone();
+            thread::spawn(move || {
+                let mut payload = [Command::PutEvent.value()].to_vec();
+                payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+                (0..50)
+                    .map(|_| {
+                        let msg = Message::from_slice(&payload);
+                        let key = process_msg(&msg, &store, Socket::Data).unwrap();
+                        deserialize::<u64>(&key).unwrap()
+                    })
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+    let keys: BTreeSet<u64> = writers
+        .into_iter()
+        .flat_map(|writer| writer.join().unwrap())
+        .collect();
+    assert_eq!(keys.len(), 400);
+
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let events: Vec<Event> = deserialize(&result).unwrap();
+    assert_eq!(events.len(), 400);
+}
+
 #[test]
 fn get_event_should_return_events_newer_than_threshold() {
     destroy_store();
@@ -137,16 +251,16 @@ fn get_event_should_return_events_newer_than_threshold() {
     let msg = Message::from_slice(&payload);
 
     // put 1 "old" message and sleep
-    process_msg(&msg, &store).unwrap();
+    process_msg(&msg, &store, Socket::Data).unwrap();
     thread::sleep(sleep_duration);
 
     // put 1 "new" messages and capture the ingest_ts
-    let value = process_msg(&msg, &store).unwrap();
+    let value = process_msg(&msg, &store, Socket::Data).unwrap();
     let expected: u64 = deserialize(&value).unwrap();
 
     let payload = [3u8].to_vec();
     let msg = Message::from_slice(&payload);
-    let result = process_msg(&msg, &store).unwrap();
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
     let result: Vec<Event> = deserialize(&result).unwrap();
     assert!(result.len() == 1);
     assert!(result[0].ingest_ts == expected);
@@ -163,13 +277,13 @@ fn get_events_should_seek_correctly() {
     let msg = Message::from_slice(&payload);
 
     // put 1 "old" message
-    process_msg(&msg, &store).unwrap();
+    process_msg(&msg, &store, Socket::Data).unwrap();
 
     // put 1 message and capture the ingest_ts
-    let value: u64 = deserialize(&process_msg(&msg, &store).unwrap()).unwrap();
+    let value: u64 = deserialize(&process_msg(&msg, &store, Socket::Data).unwrap()).unwrap();
 
     // put 1 "new" message
-    let expected = process_msg(&msg, &store).unwrap();
+    let expected = process_msg(&msg, &store, Socket::Data).unwrap();
     let expected: u64 = deserialize(&expected).unwrap();
 
     // get events, passing the last seen ts
@@ -178,7 +292,7 @@ fn get_events_should_seek_correctly() {
     payload.extend_from_slice(&ingest_string_bytes);
 
     let msg = Message::from_slice(&payload);
-    let result = process_msg(&msg, &store).unwrap();
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
     let result: Vec<Event> = deserialize(&result).unwrap();
     assert!(result.len() == 1);
     assert!(result[0].ingest_ts == expected);
@@ -192,7 +306,4267 @@ fn get_events_handles_zero_events() {
     let value: Vec<u8> = vec![];
     payload.extend_from_slice(&value);
     let msg = Message::from_slice(&payload);
-    let result = process_msg(&msg, &store).unwrap();
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let result: Vec<Event> = deserialize(&result).unwrap();
+    assert!(result.is_empty());
+}
+
+#[test]
+fn admin_commands_should_be_rejected_on_data_socket() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let admin_commands = [
+        Command::ClearEvents,
+        Command::Compact,
+        Command::Checkpoint,
+        Command::DeleteEvent,
+        Command::Flush,
+    ];
+
+    for command in admin_commands.iter() {
+        let msg = Message::from_slice(&[command.value()]);
+        let result = process_msg(&msg, &store, Socket::Data);
+        assert!(result.is_err());
+    }
+}
+
+#[test]
+fn data_commands_should_be_accepted_on_admin_socket() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let msg = Message::from_slice(&[Command::GetEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Admin);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn clear_events_should_only_delete_events() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let event = get_test_event();
+
+    let kv = serialize(&("test", "testval".as_bytes())).unwrap();
+    let mut payload = [Command::Put.value()].to_vec();
+    payload.extend_from_slice(&kv);
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&event).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+
+    let msg = Message::from_slice(&[Command::ClearEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Admin).unwrap();
+    let count: u64 = deserialize(&result).unwrap();
+    assert_eq!(count, 1);
+
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let result: Vec<Event> = deserialize(&result).unwrap();
+    assert!(result.is_empty());
+
+    let mut payload = [Command::Get.value()].to_vec();
+    payload.extend_from_slice(b"test");
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    assert_eq!(result, b"testval");
+}
+
+#[test]
+fn delete_event_should_remove_the_event() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let event = get_test_event();
+
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&event).unwrap());
+    let key = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+
+    let mut payload = [Command::DeleteEvent.value()].to_vec();
+    payload.extend_from_slice(&key);
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    let existed: bool = deserialize(&result).unwrap();
+    assert!(existed);
+
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let result: Vec<Event> = deserialize(&result).unwrap();
+    assert!(result.is_empty());
+}
+
+#[test]
+fn stats_dump_should_write_to_file_until_shutdown() {
+    destroy_store();
+    let stats_path = "wx_test_stats.log";
+    let _ = fs::remove_file(stats_path);
+    let config = Config {
+        stats_dump_path: Some(String::from(stats_path)),
+        stats_dump_interval: time::Duration::from_millis(100),
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+    thread::sleep(time::Duration::from_millis(350));
+    drop(store);
+
+    let dumped = fs::read_to_string(stats_path).unwrap();
+    assert!(dumped.contains("rocksdb"));
+
+    // the dump thread has exited, so nothing else should be written
+    let len = fs::metadata(stats_path).unwrap().len();
+    thread::sleep(time::Duration::from_millis(250));
+    assert_eq!(fs::metadata(stats_path).unwrap().len(), len);
+    fs::remove_file(stats_path).unwrap();
+}
+
+#[test]
+fn get_fetch_failures_since_should_override_the_default_threshold() {
+    destroy_store();
+    let config = Config {
+        fetch_failure_threshold_micros: 1000 * 1000, // 1s
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+    let failure = String::from("failure");
+
+    let mut payload = [Command::PutFetchFailure.value()].to_vec();
+    payload.extend_from_slice(&serialize(&failure).unwrap());
+    let msg = Message::from_slice(&payload);
+    let key = process_msg(&msg, &store, Socket::Data).unwrap();
+    thread::sleep(time::Duration::from_millis(1100));
+
+    // the failure has aged out of the default window
+    let msg = Message::from_slice(&[Command::GetFetchFailures.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let result: Vec<String> = deserialize(&result).unwrap();
+    assert!(result.is_empty());
+
+    let mut payload = [Command::GetFetchFailuresSince.value()].to_vec();
+    payload.extend_from_slice(&key);
+    let msg = Message::from_slice(&payload);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let result: Vec<String> = deserialize(&result).unwrap();
+    assert_eq!(result, vec![failure]);
+}
+
+#[test]
+fn get_fetch_failures_since_should_accept_a_bound_with_fewer_digits() {
+    destroy_store();
+    let legacy_micros = wx::util::get_system_micros() - 1000;
+
+    // a failure stored by an older build, under a decimal key
+    {
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        let db = DB::open_cf(&opts, TEST_STORE_PATH, ["fetch_failures"]).unwrap();
+        let cf = db.cf_handle("fetch_failures").unwrap();
+        let key = legacy_micros.to_string();
+        db.put_cf(cf, key.as_bytes(), serialize(&"legacy").unwrap())
+            .unwrap();
+    }
+
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut payload = [Command::PutFetchFailure.value()].to_vec();
+    payload.extend_from_slice(&serialize(&"failure").unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+
+    let mut payload = [Command::GetFetchFailuresSince.value()].to_vec();
+    payload.extend_from_slice(&serialize(&5u64).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let result: Vec<String> = deserialize(&result).unwrap();
+    assert_eq!(result, vec!["legacy", "failure"]);
+
+    let mut payload = [Command::GetFetchFailureByKey.value()].to_vec();
+    payload.extend_from_slice(&serialize(&legacy_micros).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    assert_eq!(deserialize::<String>(&result).unwrap(), "legacy");
+}
+
+#[test]
+fn namespaced_keys_should_not_clobber_each_other() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+
+    for (app, value) in [("app1", "value1"), ("app2", "value2")].iter() {
+        let mut payload = [Command::PutNamespaced.value()].to_vec();
+        payload.extend_from_slice(&serialize(&(app, "key", value.as_bytes())).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        assert_eq!(result, b"key");
+    }
+
+    for (app, value) in [("app1", "value1"), ("app2", "value2")].iter() {
+        let mut payload = [Command::GetNamespaced.value()].to_vec();
+        payload.extend_from_slice(&serialize(&(app, "key")).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        assert_eq!(result, value.as_bytes());
+    }
+
+    let mut payload = [Command::DeleteNamespaced.value()].to_vec();
+    payload.extend_from_slice(&serialize(&("app1", "key")).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+
+    let mut payload = [Command::GetNamespaced.value()].to_vec();
+    payload.extend_from_slice(&serialize(&("app2", "key")).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    assert_eq!(result, b"value2");
+}
+
+#[test]
+fn checkpoint_should_contain_recently_put_events() {
+    destroy_store();
+    let checkpoint_path = "wx_test_checkpoint";
+    let _ = fs::remove_dir_all(checkpoint_path);
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let event = get_test_event();
+
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&event).unwrap());
+    let msg = Message::from_slice(&payload);
+    process_msg(&msg, &store, Socket::Data).unwrap();
+    process_msg(&msg, &store, Socket::Data).unwrap();
+
+    let mut payload = [Command::Checkpoint.value()].to_vec();
+    payload.extend_from_slice(&serialize(&checkpoint_path).unwrap());
+    let msg = Message::from_slice(&payload);
+    process_msg(&msg, &store, Socket::Admin).unwrap();
+
+    let checkpoint = Store::new(checkpoint_path, EVENT_THRESHOLD_MICROS);
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result = process_msg(&msg, &checkpoint, Socket::Data).unwrap();
+    let result: Vec<Event> = deserialize(&result).unwrap();
+    assert_eq!(result.len(), 2);
+
+    drop(checkpoint);
+    fs::remove_dir_all(checkpoint_path).unwrap();
+}
+
+#[test]
+fn rekey_events_should_preserve_events_and_cursors() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let keys = put_spaced_events(&store, 5);
+
+    for key in [keys[1], keys[3]].iter() {
+        let mut payload = [Command::DeleteEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(key).unwrap());
+        process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    }
+
+    let msg = Message::from_slice(&[Command::RekeyEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Admin).unwrap();
+    let (min, max): (u64, u64) = deserialize::<Option<(u64, u64)>>(&result).unwrap().unwrap();
+    assert_eq!(max, keys[4]);
+    assert_eq!(max - min, 2);
+
+    // the events moved, but kept the ingest_ts they had before the rekey
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let result: Vec<Event> = deserialize(&result).unwrap();
+    let ingest_ts: Vec<u64> = result.iter().map(|event| event.ingest_ts).collect();
+    assert_eq!(ingest_ts, vec![keys[0], keys[2], keys[4]]);
+
+    // cursors issued before the rekey return exactly the events after them, also after a reopen
+    drop(store);
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    for (cursor, expected) in [
+        (keys[0], vec![keys[2], keys[4]]),
+        (keys[1], vec![keys[2], keys[4]]),
+        (keys[2], vec![keys[4]]),
+    ]
+    .iter()
+    {
+        let mut payload = [Command::GetEvents.value()].to_vec();
+        payload.extend_from_slice(&serialize(&cursor.to_string()).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        let result: Vec<Event> = deserialize(&result).unwrap();
+        let ingest_ts: Vec<u64> = result.iter().map(|event| event.ingest_ts).collect();
+        assert_eq!(&ingest_ts, expected);
+    }
+
+    // ranges are by ingest_ts too
+    let mut payload = [Command::EventsDiff.value()].to_vec();
+    payload.extend_from_slice(&serialize(&(keys[0] - 1, keys[2])).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let result: Vec<Event> = deserialize(&result).unwrap();
+    let ingest_ts: Vec<u64> = result.iter().map(|event| event.ingest_ts).collect();
+    assert_eq!(ingest_ts, vec![keys[0], keys[2]]);
+}
+
+#[test]
+fn delete_event_should_find_rekeyed_events() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let (first, last, moved_to) = rekey_with_gap(&store);
+
+    // the key the event moved to isn't its ingest_ts
+    let mut payload = [Command::DeleteEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&moved_to).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    assert!(!deserialize::<bool>(&result).unwrap());
+
+    let mut payload = [Command::DeleteEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&first).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    assert!(deserialize::<bool>(&result).unwrap());
+
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let result: Vec<Event> = deserialize(&result).unwrap();
+    let ingest_ts: Vec<u64> = result.iter().map(|event| event.ingest_ts).collect();
+    assert_eq!(ingest_ts, vec![last]);
+
+    // neither the rekey nor the delete leaves index entries behind
+    let mut payload = [Command::VerifyIndexes.value()].to_vec();
+    payload.extend_from_slice(&serialize(&false).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    let report: IndexReport = deserialize(&result).unwrap();
+    assert_eq!((report.events, report.missing, report.orphaned), (1, 0, 0));
+}
+
+#[test]
+fn annotations_should_stay_with_rekeyed_events() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let keys = put_spaced_events(&store, 3);
+    let mut payload = [Command::AnnotateEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&(keys[0], "verified")).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+
+    let mut payload = [Command::DeleteEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&keys[1]).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    let msg = Message::from_slice(&[Command::RekeyEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Admin).unwrap();
+    let (min, _): (u64, u64) = deserialize::<Option<(u64, u64)>>(&result).unwrap().unwrap();
+
+    // events are still annotated by their ingest_ts, not the key they moved to
+    let mut payload = [Command::AnnotateEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&(min, "stale")).unwrap());
+    assert!(process_msg(&Message::from_slice(&payload), &store, Socket::Data).is_err());
+
+    let mut payload = [Command::AnnotateEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&(keys[0], "false alarm")).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+
+    let mut payload = [Command::GetAnnotations.value()].to_vec();
+    payload.extend_from_slice(&serialize(&(keys[0], None::<u64>)).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let annotations: Vec<Annotation> = deserialize(&result).unwrap();
+    let notes: Vec<&str> = annotations.iter().map(|a| a.note.as_str()).collect();
+    assert_eq!(notes, vec!["verified", "false alarm"]);
+}
+
+#[test]
+fn put_event_batch_should_detect_duplicates_of_rekeyed_events() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let (first, _, _) = rekey_with_gap(&store);
+    let mut event = get_test_event();
+    event.ingest_ts = first;
+
+    let mut payload = [Command::PutEventBatch.value()].to_vec();
+    payload.extend_from_slice(&serialize(&(vec![event], true)).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data);
+    assert!(result.is_err());
+
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    assert_eq!(deserialize::<Vec<Event>>(&result).unwrap().len(), 2);
+}
+
+#[test]
+fn get_events_compressed_should_round_trip() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let event = get_test_event();
+
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&event).unwrap());
+    let msg = Message::from_slice(&payload);
+    process_msg(&msg, &store, Socket::Data).unwrap();
+    process_msg(&msg, &store, Socket::Data).unwrap();
+
+    let msg = Message::from_slice(&[Command::GetEventsCompressed.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    assert_eq!(result[0], ZSTD_FLAG);
+    let envelope = zstd::decode_all(&result[1..]).unwrap();
+
+    let msg = Message::from_slice(&[Command::GetEvents.value()]);
+    let expected = process_msg(&msg, &store, Socket::Data).unwrap();
+    assert_eq!(envelope, expected);
+
+    let result: Vec<Event> = deserialize(&envelope).unwrap();
+    assert_eq!(result.len(), 2);
+    assert_eq!(result[0].title, event.title);
+}
+
+#[test]
+fn catch_up_should_return_events_missed_during_a_gap() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let event = get_test_event();
+
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&event).unwrap());
+    let put_msg = Message::from_slice(&payload);
+    let last_seen: u64 =
+        deserialize(&process_msg(&put_msg, &store, Socket::Data).unwrap()).unwrap();
+
+    // the client disconnects, and events are written during the gap
+    let missed_1: u64 = deserialize(&process_msg(&put_msg, &store, Socket::Data).unwrap()).unwrap();
+    let missed_2: u64 = deserialize(&process_msg(&put_msg, &store, Socket::Data).unwrap()).unwrap();
+
+    let mut payload = [Command::CatchUp.value()].to_vec();
+    payload.extend_from_slice(&serialize(&last_seen).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let (high_water_mark, events): (u64, Vec<Event>) = deserialize(&result).unwrap();
+    let ingest_ts: Vec<u64> = events.iter().map(|event| event.ingest_ts).collect();
+    assert_eq!(ingest_ts, vec![missed_1, missed_2]);
+    assert_eq!(high_water_mark, missed_2);
+
+    // catching up again from the high-water mark returns nothing new
+    let mut payload = [Command::CatchUp.value()].to_vec();
+    payload.extend_from_slice(&serialize(&high_water_mark).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let (next_high_water_mark, events): (u64, Vec<Event>) = deserialize(&result).unwrap();
+    assert!(events.is_empty());
+    assert_eq!(next_high_water_mark, high_water_mark);
+}
+
+#[test]
+fn import_events_should_write_in_bounded_batches() {
+    destroy_store();
+    let import_path = "wx_test_import.ndjson";
+    let config = Config {
+        import_batch_size: 10,
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+    let line = serde_json::to_string(&get_test_event()).unwrap();
+    let lines: Vec<String> = (0..25).map(|_| line.clone()).collect();
+    fs::write(import_path, lines.join("\n")).unwrap();
+
+    let mut payload = [Command::ImportEvents.value()].to_vec();
+    payload.extend_from_slice(&serialize(&import_path).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    let (imported, batches): (u64, u64) = deserialize(&result).unwrap();
+    assert_eq!(imported, 25);
+    assert_eq!(batches, 3);
+
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let result: Vec<Event> = deserialize(&result).unwrap();
+    assert_eq!(result.len(), 25);
+    fs::remove_file(import_path).unwrap();
+}
+
+#[test]
+fn import_events_should_reject_stored_ingest_ts() {
+    destroy_store();
+    let import_path = "wx_test_import_collision.ndjson";
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let keys = put_spaced_events(&store, 1);
+
+    let mut event = get_test_event();
+    event.ingest_ts = keys[0];
+    event.title = String::from("imported");
+    fs::write(import_path, serde_json::to_string(&event).unwrap()).unwrap();
+    let mut payload = [Command::ImportEvents.value()].to_vec();
+    payload.extend_from_slice(&serialize(&import_path).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Admin);
+    assert!(result.is_err());
+
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result: Vec<Event> =
+        deserialize(&process_msg(&msg, &store, Socket::Data).unwrap()).unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].title, "title");
+
+    // A file repeating an ingest_ts is rejected without writing either event
+    event.ingest_ts = keys[0] + 1000;
+    let line = serde_json::to_string(&event).unwrap();
+    fs::write(import_path, format!("{}\n{}", line, line)).unwrap();
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Admin);
+    assert!(result.is_err());
+    let result: Vec<Event> =
+        deserialize(&process_msg(&msg, &store, Socket::Data).unwrap()).unwrap();
+    assert_eq!(result.len(), 1);
+
+    // The sequence didn't advance for the rejected events
+    put_spaced_events(&store, 1);
+    let mut payload = [Command::GetEventsBySeq.value()].to_vec();
+    payload.extend_from_slice(&serialize(&0u64).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let next: u64 = deserialize(&result[..8]).unwrap();
+    assert_eq!(next, 2);
+    fs::remove_file(import_path).unwrap();
+}
+
+#[test]
+fn probe_schema_should_sample_the_newest_event() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let probe_msg = Message::from_slice(&[Command::ProbeSchema.value()]);
+
+    let result = process_msg(&probe_msg, &store, Socket::Data).unwrap();
+    let (version, sample): (u32, Option<Vec<u8>>) = deserialize(&result).unwrap();
+    assert_eq!(version, SCHEMA_VERSION);
+    assert!(sample.is_none());
+
+    let event = get_test_event();
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&event).unwrap());
+    let key = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let key: u64 = deserialize(&key).unwrap();
+
+    let result = process_msg(&probe_msg, &store, Socket::Data).unwrap();
+    let (version, sample): (u32, Option<Vec<u8>>) = deserialize(&result).unwrap();
+    assert_eq!(version, SCHEMA_VERSION);
+    let sample: Event = deserialize(&sample.unwrap()).unwrap();
+    assert_eq!(sample.ingest_ts, key);
+    assert_eq!(sample.title, event.title);
+}
+
+#[test]
+fn process_msg_should_log_the_trace_id() {
+    destroy_store();
+    let (logger, lines) = get_capture_logger();
+    let config = Config {
+        logger,
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+    let trace_id = "trace-123";
+
+    let mut payload = [
+        Command::GetEvents.value() | TRACE_FLAG,
+        trace_id.len() as u8,
+    ]
+    .to_vec();
+    payload.extend_from_slice(trace_id.as_bytes());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let result: Vec<Event> = deserialize(&result).unwrap();
+    assert!(result.is_empty());
+
+    let msg = Message::from_slice(&[Command::GetEvents.value()]);
+    process_msg(&msg, &store, Socket::Data).unwrap();
+
+    let lines = lines.lock().unwrap();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].contains("trace_id=trace-123"));
+    assert!(lines[0].contains("command=Some(GetEvents)"));
+    assert!(lines[0].contains("result=ok"));
+
+    // a trace id is generated when one isn't supplied
+    assert!(lines[1].contains("trace_id="));
+    assert!(!lines[1].contains("trace_id=trace-123"));
+}
+
+#[test]
+fn events_histogram_by_time_should_tally_buckets() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let event = get_test_event();
+    let bucket_micros: u64 = 1000 * 200; // 200ms
+    let mut keys = Vec::new();
+
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&event).unwrap());
+    let msg = Message::from_slice(&payload);
+    for i in 0..6 {
+        if i == 3 {
+            thread::sleep(time::Duration::from_millis(250));
+        }
+        let key: u64 = deserialize(&process_msg(&msg, &store, Socket::Data).unwrap()).unwrap();
+        keys.push(key);
+    }
+
+    let mut expected: Vec<(u64, u64)> = Vec::new();
+    for key in keys {
+        let bucket = key / bucket_micros * bucket_micros;
+        match expected.last_mut() {
+            Some((start, count)) if *start == bucket => *count += 1,
+            _ => expected.push((bucket, 1)),
+        }
+    }
+
+    let mut payload = [Command::EventsHistogramByTime.value()].to_vec();
+    payload.extend_from_slice(&serialize(&bucket_micros).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let result: Vec<(u64, u64)> = deserialize(&result).unwrap();
+    assert!(result.len() >= 2);
+    assert_eq!(result.iter().map(|(_, count)| count).sum::<u64>(), 6);
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn warmup_should_only_run_when_enabled() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    assert!(store.warmup_duration().is_none());
+    drop(store);
+
+    let config = Config {
+        warmup: true,
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+    assert!(store.warmup_duration().is_some());
+}
+
+#[test]
+fn delete_by_prefix_should_only_delete_matching_keys() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let keys = ["radar:1", "radar:2", "radar:3", "radars", "satellite:1"];
+
+    for key in keys.iter() {
+        let mut payload = [Command::Put.value()].to_vec();
+        payload.extend_from_slice(&serialize(&(key, "value".as_bytes())).unwrap());
+        process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    }
+
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+
+    let mut payload = [Command::DeleteByPrefix.value()].to_vec();
+    payload.extend_from_slice(&serialize(&"radar:").unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let count: u64 = deserialize(&result).unwrap();
+    assert_eq!(count, 3);
+
+    for (key, expected) in keys.iter().zip([false, false, false, true, true].iter()) {
+        let mut payload = [Command::Get.value()].to_vec();
+        payload.extend_from_slice(key.as_bytes());
+        let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        assert_eq!(!result.is_empty(), *expected);
+    }
+
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
     let result: Vec<Event> = deserialize(&result).unwrap();
-    assert!(result.len() == 0);
+    assert_eq!(result.len(), 1);
+}
+
+#[test]
+fn delete_by_prefix_should_reject_an_empty_prefix_and_skip_reserved_keys() {
+    destroy_store();
+    let probe = &b"\x1fwx_storage_write_probe"[..];
+    {
+        let db = DB::open_default(TEST_STORE_PATH).unwrap();
+        db.put(probe, b"probe").unwrap();
+    }
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut payload = [Command::Put.value()].to_vec();
+    payload.extend_from_slice(&serialize(&("radar", "value".as_bytes())).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+
+    let mut payload = [Command::DeleteByPrefix.value()].to_vec();
+    payload.extend_from_slice(&serialize(&"").unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data);
+    assert!(result.unwrap_err().to_string().contains("invalid prefix"));
+
+    let mut payload = [Command::DeleteByPrefix.value()].to_vec();
+    payload.extend_from_slice(&serialize(&"\u{1f}").unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    assert_eq!(deserialize::<u64>(&result).unwrap(), 0);
+
+    let mut payload = [Command::GetRaw.value()].to_vec();
+    payload.extend_from_slice(&serialize(&("default", probe)).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    assert_eq!(deserialize::<Vec<u8>>(&result).unwrap(), b"probe");
+
+    let mut payload = [Command::Get.value()].to_vec();
+    payload.extend_from_slice(b"radar");
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    assert_eq!(result, b"value");
+}
+
+#[test]
+fn delete_by_prefix_should_reject_paused_writes() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut payload = [Command::Put.value()].to_vec();
+    payload.extend_from_slice(&serialize(&("radar:1", "value".as_bytes())).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+
+    let pause = Message::from_slice(&[Command::Pause.value()]);
+    process_msg(&pause, &store, Socket::Admin).unwrap();
+    let mut payload = [Command::DeleteByPrefix.value()].to_vec();
+    payload.extend_from_slice(&serialize(&"radar:").unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data);
+    assert!(result.unwrap_err().to_string().contains("paused"));
+
+    let mut payload = [Command::Get.value()].to_vec();
+    payload.extend_from_slice(b"radar:1");
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    assert_eq!(result, b"value");
+}
+
+#[test]
+fn event_checksums_should_skip_corrupt_events() {
+    destroy_store();
+    let config = Config {
+        event_checksums: true,
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+    let event = get_test_event();
+
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&event).unwrap());
+    let msg = Message::from_slice(&payload);
+    let corrupt_key: u64 = deserialize(&process_msg(&msg, &store, Socket::Data).unwrap()).unwrap();
+    let valid_key: u64 = deserialize(&process_msg(&msg, &store, Socket::Data).unwrap()).unwrap();
+    drop(store);
+
+    // flip a byte in one of the stored events behind the store's back
+    {
+        let db = DB::open_cf(
+            &Options::default(),
+            TEST_STORE_PATH,
+            [
+                "annotations",
+                "claims",
+                "events",
+                "event_totals",
+                "event_types",
+                "fetch_failures",
+                "metadata",
+                "offsets",
+                "rollups",
+                "sequence",
+            ],
+        )
+        .unwrap();
+        let cf = db.cf_handle("events").unwrap();
+        let key = corrupt_key.to_string();
+        let mut value = db.get_cf(cf, key.as_bytes()).unwrap().unwrap().to_vec();
+        let last = value.len() - 1;
+        value[last] ^= 0xff;
+        db.put_cf(cf, key.as_bytes(), &value).unwrap();
+    }
+
+    let config = Config {
+        event_checksums: true,
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let result: Vec<Event> = deserialize(&result).unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].ingest_ts, valid_key);
+}
+
+#[test]
+fn unreadable_events_should_be_skipped_rather_than_panic() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let keys = put_spaced_events(&store, 2);
+    drop(store);
+
+    // overwrite one event with bytes that don't deserialize, which no checksum catches
+    {
+        let cfs = DB::list_cf(&Options::default(), TEST_STORE_PATH).unwrap();
+        let db = DB::open_cf(&Options::default(), TEST_STORE_PATH, &cfs).unwrap();
+        let cf = db.cf_handle("events").unwrap();
+        db.put_cf(cf, keys[0].to_string().as_bytes(), b"\xff")
+            .unwrap();
+    }
+
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut requests = Vec::new();
+    let mut payload = [Command::SearchEvents.value()].to_vec();
+    payload.extend_from_slice(&serialize(&("summary", 0u64, 10u32)).unwrap());
+    requests.push(payload);
+    let mut payload = [Command::GetEventsByType.value()].to_vec();
+    payload.extend_from_slice(&serialize(&EventType::NwsLsr).unwrap());
+    requests.push(payload);
+    let mut payload = [Command::Poll.value()].to_vec();
+    payload.extend_from_slice(&serialize(&"consumer").unwrap());
+    requests.push(payload);
+
+    for request in requests {
+        let result = process_msg(&Message::from_slice(&request), &store, Socket::Data).unwrap();
+        let events: Vec<Event> = deserialize(&result).unwrap();
+        let ingest_ts: Vec<u64> = events.iter().map(|event| event.ingest_ts).collect();
+        assert_eq!(ingest_ts, vec![keys[1]]);
+    }
+
+    // a malformed event is rejected rather than panicking the worker
+    let msg = Message::from_slice(&[Command::PutEvent.value(), 0xff]);
+    let result = process_msg(&msg, &store, Socket::Data);
+    assert!(result.unwrap_err().to_string().contains("invalid event"));
+}
+
+#[test]
+fn unreadable_events_should_be_logged_by_their_micros() {
+    destroy_store();
+    let config = Config {
+        key_encoding: KeyEncoding::BigEndian,
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+    let keys = put_spaced_events(&store, 1);
+    drop(store);
+
+    {
+        let cfs = DB::list_cf(&Options::default(), TEST_STORE_PATH).unwrap();
+        let db = DB::open_cf(&Options::default(), TEST_STORE_PATH, &cfs).unwrap();
+        let cf = db.cf_handle("events").unwrap();
+        db.put_cf(cf, keys[0].to_be_bytes(), b"\xff").unwrap();
+    }
+
+    let (logger, lines) = get_capture_logger();
+    let config = Config {
+        key_encoding: KeyEncoding::BigEndian,
+        logger,
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    process_msg(&msg, &store, Socket::Data).unwrap();
+
+    let lines = lines.lock().unwrap();
+    let warning = lines
+        .iter()
+        .find(|line| line.starts_with("deserialize"))
+        .unwrap();
+    assert!(warning.contains(&format!("key={}", keys[0])));
+}
+
+#[test]
+fn info_should_report_uptime_and_request_counts() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let info_msg = Message::from_slice(&[Command::Info.value()]);
+
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let msg = Message::from_slice(b"\x7f");
+    assert!(process_msg(&msg, &store, Socket::Data).is_err());
+
+    let first: Info = deserialize(&process_msg(&info_msg, &store, Socket::Data).unwrap()).unwrap();
+    assert_eq!(first.requests, 3);
+    assert_eq!(first.errors, 1);
+    assert_eq!(first.events, 1);
+    assert_eq!(first.fetch_failures, 0);
+
+    thread::sleep(time::Duration::from_millis(10));
+    let second: Info = deserialize(&process_msg(&info_msg, &store, Socket::Data).unwrap()).unwrap();
+    assert!(second.uptime_micros > first.uptime_micros);
+    assert_eq!(second.requests, 4);
+}
+
+#[test]
+fn concurrent_readers_should_be_bounded() {
+    destroy_store();
+    let config = Config {
+        max_concurrent_readers: 2,
+        ..Config::default()
+    };
+    let store = Arc::new(Store::with_config(TEST_STORE_PATH, config));
+
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    for _ in 0..5 {
+        process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    }
+
+    let readers: Vec<_> = (0..16)
+        .map(|_| {
+            let store = store.clone();
+            thread::spawn(move || {
+                let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+                for _ in 0..10 {
+                    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+                    let result: Vec<Event> = deserialize(&result).unwrap();
+                    assert_eq!(result.len(), 5);
+                }
+            })
+        })
+        .collect();
+
+    for reader in readers {
+        reader.join().unwrap();
+    }
+
+    let peak = store.peak_concurrent_readers();
+    assert!((1..=2).contains(&peak));
+}
+
+#[test]
+fn get_fetch_failure_by_key_should_return_a_single_failure() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+
+    let mut keys = Vec::new();
+    for failure in ["first", "second"].iter() {
+        let mut payload = [Command::PutFetchFailure.value()].to_vec();
+        payload.extend_from_slice(&serialize(failure).unwrap());
+        let key = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        keys.push(key);
+    }
+
+    let mut payload = [Command::GetFetchFailureByKey.value()].to_vec();
+    payload.extend_from_slice(&keys[0]);
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let result: String = deserialize(&result).unwrap();
+    assert_eq!(result, "first");
+
+    let mut payload = [Command::GetFetchFailureByKey.value()].to_vec();
+    payload.extend_from_slice(&serialize(&0u64).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    assert!(result.is_empty());
+}
+
+#[test]
+fn ttl_expiry_should_drop_old_records_on_compaction() {
+    destroy_store();
+    let config = Config {
+        event_threshold_micros: 1000 * 1000, // 1s
+        fetch_failure_threshold_micros: 1000 * 1000,
+        ttl_expiry: true,
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let mut payload = [Command::PutFetchFailure.value()].to_vec();
+    payload.extend_from_slice(&serialize(&"failure").unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let mut payload = [Command::Put.value()].to_vec();
+    payload.extend_from_slice(&serialize(&("key", "value".as_bytes())).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    thread::sleep(time::Duration::from_millis(1100));
+
+    let msg = Message::from_slice(&[Command::Compact.value()]);
+    process_msg(&msg, &store, Socket::Admin).unwrap();
+
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let result: Vec<Event> = deserialize(&result).unwrap();
+    assert!(result.is_empty());
+
+    let mut payload = [Command::GetFetchFailuresSince.value()].to_vec();
+    payload.extend_from_slice(&serialize(&0u64).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let result: Vec<String> = deserialize(&result).unwrap();
+    assert!(result.is_empty());
+
+    let mut payload = [Command::Get.value()].to_vec();
+    payload.extend_from_slice(b"key");
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    assert!(!result.is_empty());
+}
+
+#[test]
+fn ttl_expiry_should_keep_expired_events_until_compacted() {
+    destroy_store();
+    let config = Config {
+        event_threshold_micros: 1000 * 1000, // 1s
+        ttl_expiry: true,
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+    let expired = put_spaced_events(&store, 1);
+    thread::sleep(time::Duration::from_millis(1100));
+    let fresh = put_spaced_events(&store, 1);
+
+    // Unlike DBWithTTL, nothing hides expired events from reads before compaction drops them
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result: Vec<Event> =
+        deserialize(&process_msg(&msg, &store, Socket::Data).unwrap()).unwrap();
+    let keys: Vec<u64> = result.iter().map(|event| event.ingest_ts).collect();
+    assert_eq!(keys, vec![expired[0], fresh[0]]);
+
+    let compact = Message::from_slice(&[Command::CompactEvents.value()]);
+    process_msg(&compact, &store, Socket::Admin).unwrap();
+
+    let result: Vec<Event> =
+        deserialize(&process_msg(&msg, &store, Socket::Data).unwrap()).unwrap();
+    let keys: Vec<u64> = result.iter().map(|event| event.ingest_ts).collect();
+    assert_eq!(keys, fresh);
+}
+
+#[test]
+fn swap_should_exchange_values() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+
+    for (key, value) in [("active", "blue"), ("standby", "green")].iter() {
+        let mut payload = [Command::Put.value()].to_vec();
+        payload.extend_from_slice(&serialize(&(key, value.as_bytes())).unwrap());
+        process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    }
+
+    let mut payload = [Command::Swap.value()].to_vec();
+    payload.extend_from_slice(&serialize(&("active", "standby", false)).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+
+    for (key, value) in [("active", "green"), ("standby", "blue")].iter() {
+        let mut payload = [Command::Get.value()].to_vec();
+        payload.extend_from_slice(key.as_bytes());
+        let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        assert_eq!(result, value.as_bytes());
+    }
+
+    // a missing key is only allowed when create-if-absent is set
+    let mut payload = [Command::Swap.value()].to_vec();
+    payload.extend_from_slice(&serialize(&("active", "missing", false)).unwrap());
+    assert!(process_msg(&Message::from_slice(&payload), &store, Socket::Data).is_err());
+
+    let mut payload = [Command::Swap.value()].to_vec();
+    payload.extend_from_slice(&serialize(&("active", "missing", true)).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+
+    let mut payload = [Command::Get.value()].to_vec();
+    payload.extend_from_slice(b"missing");
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    assert_eq!(result, b"green");
+    let mut payload = [Command::Get.value()].to_vec();
+    payload.extend_from_slice(b"active");
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    assert!(result.is_empty());
+}
+
+#[test]
+fn swap_should_not_lose_concurrent_compare_and_swaps() {
+    destroy_store();
+    let store = Arc::new(Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS));
+    for (key, value) in [("a", "a"), ("b", "b")].iter() {
+        let mut payload = [Command::Put.value()].to_vec();
+        payload.extend_from_slice(&serialize(&(key, value.as_bytes())).unwrap());
+        process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    }
+
+    // Each successful compare and swap appends an x to whichever value "a" holds at the time
+    let appender = {
+        let store = store.clone();
+        thread::spawn(move || {
+            let mut swapped = 0;
+            for _ in 0..500 {
+                let mut payload = [Command::Get.value()].to_vec();
+                payload.extend_from_slice(b"a");
+                let current =
+                    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+                let new = [&current[..], b"x"].concat();
+                let mut payload = [Command::CompareAndSwap.value()].to_vec();
+                payload
+                    .extend_from_slice(&serialize(&("a", Some(&current[..]), &new[..])).unwrap());
+                let result =
+                    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+                if deserialize::<bool>(&result).unwrap() {
+                    swapped += 1;
+                }
+            }
+            swapped
+        })
+    };
+    for _ in 0..500 {
+        let mut payload = [Command::Swap.value()].to_vec();
+        payload.extend_from_slice(&serialize(&("a", "b", false)).unwrap());
+        process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    }
+    let swapped = appender.join().unwrap();
+
+    let mut appended = 0;
+    for key in [b"a", b"b"].iter() {
+        let mut payload = [Command::Get.value()].to_vec();
+        payload.extend_from_slice(&key[..]);
+        let value = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        appended += value.len() - 1;
+    }
+    assert_eq!(appended, swapped);
+}
+
+#[test]
+fn swap_should_reject_long_keys_and_paused_writes() {
+    destroy_store();
+    let config = Config {
+        max_key_length: 8,
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+
+    for (key, value) in [("active", "blue"), ("standby", "green")].iter() {
+        let mut payload = [Command::Put.value()].to_vec();
+        payload.extend_from_slice(&serialize(&(key, value.as_bytes())).unwrap());
+        process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    }
+
+    let mut payload = [Command::Swap.value()].to_vec();
+    payload.extend_from_slice(&serialize(&("active", "much_too_long", true)).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data);
+    assert!(result.unwrap_err().to_string().contains("exceeds maximum"));
+
+    let pause = Message::from_slice(&[Command::Pause.value()]);
+    process_msg(&pause, &store, Socket::Admin).unwrap();
+    let mut payload = [Command::Swap.value()].to_vec();
+    payload.extend_from_slice(&serialize(&("active", "standby", false)).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data);
+    assert!(result.unwrap_err().to_string().contains("paused"));
+
+    for (key, value) in [("active", "blue"), ("standby", "green")].iter() {
+        let mut payload = [Command::Get.value()].to_vec();
+        payload.extend_from_slice(key.as_bytes());
+        let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        assert_eq!(result, value.as_bytes());
+    }
+}
+
+#[test]
+fn envelope_version_should_prefix_get_events_responses() {
+    destroy_store();
+    let config = Config {
+        envelope_version: true,
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+
+    for command in [Command::GetEvents, Command::GetAllEvents].iter() {
+        let msg = Message::from_slice(&[command.value()]);
+        let result = process_msg(&msg, &store, Socket::Data).unwrap();
+        assert_eq!(result[0], ENVELOPE_VERSION);
+        let events: Vec<Event> = deserialize(&result[1..]).unwrap();
+        assert_eq!(events.len(), 1);
+    }
+}
+
+#[test]
+fn drain_events_before_should_return_and_delete_old_events() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    let msg = Message::from_slice(&payload);
+    let mut keys = Vec::new();
+    for _ in 0..3 {
+        let key: u64 = deserialize(&process_msg(&msg, &store, Socket::Data).unwrap()).unwrap();
+        keys.push(key);
+    }
+
+    let mut payload = [Command::DrainEventsBefore.value()].to_vec();
+    payload.extend_from_slice(&serialize(&keys[2]).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    let drained: Vec<Event> = deserialize(&result).unwrap();
+    let drained: Vec<u64> = drained.iter().map(|event| event.ingest_ts).collect();
+    assert_eq!(drained, &keys[..2]);
+
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let result: Vec<Event> = deserialize(&result).unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].ingest_ts, keys[2]);
+}
+
+#[test]
+fn put_should_reject_over_long_keys() {
+    destroy_store();
+    let config = Config {
+        max_key_length: 8,
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+
+    let mut payload = [Command::Put.value()].to_vec();
+    payload.extend_from_slice(&serialize(&("too_long_key", "value".as_bytes())).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data);
+    assert!(result.unwrap_err().to_string().contains("exceeds maximum"));
+
+    let mut payload = [Command::Get.value()].to_vec();
+    payload.extend_from_slice(b"too_long_key");
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    assert!(result.is_empty());
+
+    let mut payload = [Command::Put.value()].to_vec();
+    payload.extend_from_slice(&serialize(&("key", "value".as_bytes())).unwrap());
+    assert!(process_msg(&Message::from_slice(&payload), &store, Socket::Data).is_ok());
+}
+
+#[test]
+fn cf_stats_should_cover_each_column_family() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    for _ in 0..3 {
+        process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    }
+    let mut payload = [Command::Put.value()].to_vec();
+    payload.extend_from_slice(&serialize(&("key", "value".as_bytes())).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+
+    let msg = Message::from_slice(&[Command::CfStats.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let stats: BTreeMap<String, CfStats> = deserialize(&result).unwrap();
+    let names: Vec<&str> = stats.keys().map(String::as_str).collect();
+    assert_eq!(
+        names,
+        vec![
+            "annotations",
+            "claims",
+            "default",
+            "event_totals",
+            "event_types",
+            "events",
+            "fetch_failures",
+            "metadata",
+            "offsets",
+            "rollups",
+            "sequence"
+        ]
+    );
+    assert_eq!(stats["events"].keys, 3);
+    assert_eq!(stats["default"].keys, 1);
+    assert_eq!(stats["metadata"].keys, 1);
+    assert_eq!(stats["fetch_failures"].keys, 0);
+}
+
+#[test]
+fn audit_log_should_record_each_request() {
+    destroy_store();
+    let audit_path = "wx_test_audit.log";
+    let _ = fs::remove_file(audit_path);
+    let config = Config {
+        audit_log_path: Some(audit_path.to_string()),
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let msg = Message::from_slice(&[Command::Compact.value()]);
+    assert!(process_msg(&msg, &store, Socket::Data).is_err());
+
+    let audit = fs::read_to_string(audit_path).unwrap();
+    let entries: Vec<serde_json::Value> = audit
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["command"], Command::PutEvent.value());
+    assert_eq!(entries[0]["payload_bytes"], payload.len() - 1);
+    assert_eq!(entries[0]["result"], "ok");
+    assert_eq!(entries[1]["command"], Command::Compact.value());
+    assert_eq!(entries[1]["result"], "error");
+    assert!(entries[1]["latency_micros"].is_u64());
+    fs::remove_file(audit_path).unwrap();
+}
+
+#[test]
+fn get_events_near_should_return_the_closest_events() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    let msg = Message::from_slice(&payload);
+    let mut keys = Vec::new();
+    for _ in 0..5 {
+        let key: u64 = deserialize(&process_msg(&msg, &store, Socket::Data).unwrap()).unwrap();
+        keys.push(key);
+    }
+
+    let get_near = |target: u64, count: u32| {
+        let mut payload = [Command::GetEventsNear.value()].to_vec();
+        payload.extend_from_slice(&serialize(&(target, count)).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        let events: Vec<Event> = deserialize(&result).unwrap();
+        events
+            .iter()
+            .map(|event| event.ingest_ts)
+            .collect::<Vec<u64>>()
+    };
+
+    assert_eq!(get_near(keys[2], 1), &keys[2..3]);
+    assert_eq!(get_near(keys[2] + 1, 3).len(), 3);
+    assert!(get_near(keys[2] + 1, 3).contains(&keys[2]));
+
+    // targets outside the stored range only walk in one direction
+    assert_eq!(get_near(0, 2), &keys[..2]);
+    assert_eq!(get_near(keys[4] + 1_000_000, 2), &keys[3..]);
+    assert_eq!(get_near(keys[4], 10), keys);
+}
+
+#[test]
+fn throttled_store_should_still_compact() {
+    destroy_store();
+    let config = Config {
+        max_background_jobs: Some(1),
+        rate_limit_bytes_per_sec: Some(1024 * 1024),
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let msg = Message::from_slice(&[Command::Compact.value()]);
+    process_msg(&msg, &store, Socket::Admin).unwrap();
+
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let result: Vec<Event> = deserialize(&result).unwrap();
+    assert_eq!(result.len(), 1);
+}
+
+#[test]
+fn export_events_by_type_should_only_write_matching_events() {
+    destroy_store();
+    let export_path = "wx_test_export.ndjson";
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+
+    let mut other = get_test_event();
+    other.event_type = get_other_event_type();
+    for event in [get_test_event(), other, get_test_event()].iter() {
+        let mut payload = [Command::PutEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(event).unwrap());
+        process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    }
+
+    let mut payload = [Command::ExportEventsByType.value()].to_vec();
+    payload.extend_from_slice(&serialize(&(EventType::NwsLsr, export_path)).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    let exported: u64 = deserialize(&result).unwrap();
+    assert_eq!(exported, 2);
+
+    let contents = fs::read_to_string(export_path).unwrap();
+    let events: Vec<Event> = contents
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(events.len(), 2);
+    assert!(events
+        .iter()
+        .all(|event| event.event_type == EventType::NwsLsr));
+    fs::remove_file(export_path).unwrap();
+}
+
+#[test]
+fn get_raw_should_return_stored_bytes() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut event = get_test_event();
+
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&event).unwrap());
+    let key = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    event.ingest_ts = deserialize(&key).unwrap();
+
+    let key = event.ingest_ts.to_string();
+    let mut payload = [Command::GetRaw.value()].to_vec();
+    payload.extend_from_slice(&serialize(&("events", key.as_bytes())).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    let raw: Vec<u8> = deserialize(&result).unwrap();
+    assert_eq!(raw, serialize(&event).unwrap());
+
+    let mut payload = [Command::GetRaw.value()].to_vec();
+    payload.extend_from_slice(&serialize(&("unknown", key.as_bytes())).unwrap());
+    assert!(process_msg(&Message::from_slice(&payload), &store, Socket::Admin).is_err());
+}
+
+#[test]
+fn auto_flush_should_flush_until_shutdown() {
+    destroy_store();
+    let config = Config {
+        auto_flush_interval: Some(time::Duration::from_millis(100)),
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    thread::sleep(time::Duration::from_millis(350));
+
+    let msg = Message::from_slice(&[Command::CfStats.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let stats: BTreeMap<String, CfStats> = deserialize(&result).unwrap();
+    assert!(stats["events"].sst_bytes > 0);
+
+    // the flush thread is joined on drop, releasing the DB lock promptly
+    let start = time::Instant::now();
+    drop(store);
+    assert!(start.elapsed() < time::Duration::from_secs(1));
+    Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+}
+
+#[test]
+fn get_events_columnar_should_round_trip() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+
+    let mut other = get_test_event();
+    other.event_type = get_other_event_type();
+    other.text = None;
+    for event in [get_test_event(), other].iter() {
+        let mut payload = [Command::PutEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(event).unwrap());
+        process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    }
+
+    let msg = Message::from_slice(&[Command::GetEvents.value()]);
+    let expected: Vec<Event> =
+        deserialize(&process_msg(&msg, &store, Socket::Data).unwrap()).unwrap();
+
+    let msg = Message::from_slice(&[Command::GetEventsColumnar.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    #[rustfmt::skip]
+    #[allow(clippy::type_complexity)]
+    let (event_ts, event_type, expires_ts, ext_uri, ingest_ts, location, md, outlook, report, text,
+        title, valid_ts, warning, watch): (Vec<u64>, Vec<EventType>, Vec<_>, Vec<_>, Vec<u64>,
+        Vec<_>, Vec<_>, Vec<_>, Vec<_>, Vec<_>, Vec<String>, Vec<_>, Vec<_>, Vec<_>) =
+        deserialize(&result).unwrap();
+    assert_eq!(event_ts.len(), 2);
+
+    let mut columns = (
+        event_type.into_iter(),
+        expires_ts.into_iter(),
+        ext_uri.into_iter(),
+        location.into_iter(),
+        md.into_iter(),
+        outlook.into_iter(),
+        report.into_iter(),
+        text.into_iter(),
+        title.into_iter(),
+        valid_ts.into_iter(),
+        warning.into_iter(),
+        watch.into_iter(),
+    );
+    let events: Vec<Event> = event_ts
+        .into_iter()
+        .zip(ingest_ts)
+        .map(|(event_ts, ingest_ts)| Event {
+            event_ts,
+            event_type: columns.0.next().unwrap(),
+            expires_ts: columns.1.next().unwrap(),
+            ext_uri: columns.2.next().unwrap(),
+            ingest_ts,
+            location: columns.3.next().unwrap(),
+            md: columns.4.next().unwrap(),
+            outlook: columns.5.next().unwrap(),
+            report: columns.6.next().unwrap(),
+            text: columns.7.next().unwrap(),
+            title: columns.8.next().unwrap(),
+            valid_ts: columns.9.next().unwrap(),
+            warning: columns.10.next().unwrap(),
+            watch: columns.11.next().unwrap(),
+        })
+        .collect();
+    assert_eq!(serialize(&events).unwrap(), serialize(&expected).unwrap());
+}
+
+#[test]
+fn get_events_proto_should_round_trip() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+
+    let mut located = get_test_event();
+    located.expires_ts = Some(1548378960000000);
+    located.ext_uri = Some(String::from("https://example.com"));
+    located.valid_ts = Some(1548378900000000);
+    located.location = Some(Location {
+        wfo: Some(String::from("KMPX")),
+        point: Some(Coordinates {
+            lat: 44.9,
+            lon: -93.2,
+        }),
+        poly: Some(vec![
+            Coordinates {
+                lat: 44.0,
+                lon: -93.0,
+            },
+            Coordinates {
+                lat: 45.0,
+                lon: -94.0,
+            },
+        ]),
+    });
+    let mut other = get_test_event();
+    other.event_type = get_other_event_type();
+    other.text = None;
+    other.location = Some(Location {
+        wfo: None,
+        point: None,
+        poly: Some(Vec::new()),
+    });
+    for event in [located, other].iter() {
+        let mut payload = [Command::PutEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(event).unwrap());
+        process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    }
+
+    let msg = Message::from_slice(&[Command::GetEvents.value()]);
+    let expected: Vec<Event> =
+        deserialize(&process_msg(&msg, &store, Socket::Data).unwrap()).unwrap();
+
+    let msg = Message::from_slice(&[Command::GetEventsProto.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let events = proto::decode_events(&result).unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(serialize(&events).unwrap(), serialize(&expected).unwrap());
+}
+
+#[test]
+fn get_config_should_return_the_effective_settings() {
+    destroy_store();
+    let config = Config {
+        event_threshold_micros: 1000,
+        max_key_length: 64,
+        ttl_expiry: true,
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+
+    let msg = Message::from_slice(&[Command::GetConfig.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let settings: Settings = deserialize(&result).unwrap();
+    let defaults = Config::default();
+    assert_eq!(settings.event_threshold_micros, 1000);
+    assert_eq!(
+        settings.fetch_failure_threshold_micros,
+        defaults.fetch_failure_threshold_micros
+    );
+    assert_eq!(settings.compression_type, defaults.compression_name());
+    assert_eq!(settings.key_encoding, defaults.key_encoding.name());
+    assert_eq!(settings.max_key_length, 64);
+    assert_eq!(
+        settings.max_concurrent_readers,
+        defaults.max_concurrent_readers as u64
+    );
+    assert!(settings.ttl_expiry);
+    assert!(!settings.event_checksums);
+}
+
+#[test]
+fn retry_should_only_retry_transient_failures() {
+    let retry = Retry {
+        retries: 3,
+        backoff: time::Duration::from_millis(1),
+    };
+
+    // stalls twice, then succeeds
+    let mut attempts = 0;
+    let result = retry.run(|| {
+        attempts += 1;
+        if attempts < 3 {
+            Err(String::from("Resource busy: write stall"))
+        } else {
+            Ok(attempts)
+        }
+    });
+    assert_eq!(result, Ok(3));
+
+    // never recovers, so gives up after the configured retries
+    let mut attempts = 0;
+    let result: Result<(), String> = retry.run(|| {
+        attempts += 1;
+        Err(String::from("Operation timed out: write stall"))
+    });
+    assert!(result.is_err());
+    assert_eq!(attempts, 4);
+
+    // hard failures are returned immediately
+    let mut attempts = 0;
+    let result: Result<(), String> = retry.run(|| {
+        attempts += 1;
+        Err(String::from("Corruption: bad block"))
+    });
+    assert!(result.is_err());
+    assert_eq!(attempts, 1);
+}
+
+#[test]
+fn events_digest_should_match_only_for_identical_stores() {
+    let import_path = "wx_test_digest.ndjson";
+    let lines: Vec<String> = (0..5)
+        .map(|i| {
+            let mut event = get_test_event();
+            event.ingest_ts = 1548378900711570 + i;
+            serde_json::to_string(&event).unwrap()
+        })
+        .collect();
+    fs::write(import_path, lines.join("\n")).unwrap();
+
+    let mut import = [Command::ImportEvents.value()].to_vec();
+    import.extend_from_slice(&serialize(&import_path).unwrap());
+    let digest = |store: &Store, range: Option<(u64, u64)>| {
+        let mut payload = [Command::EventsDigest.value()].to_vec();
+        payload.extend_from_slice(&serialize(&range).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), store, Socket::Data).unwrap();
+        deserialize::<u64>(&result).unwrap()
+    };
+
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    process_msg(&Message::from_slice(&import), &store, Socket::Admin).unwrap();
+    let primary = digest(&store, None);
+    drop(store);
+
+    // a replica with the same events has the same digest
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    process_msg(&Message::from_slice(&import), &store, Socket::Admin).unwrap();
+    assert_eq!(digest(&store, None), primary);
+
+    // until it diverges, although a range excluding the new event still matches
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    assert_ne!(digest(&store, None), primary);
+    assert_eq!(digest(&store, Some((0, 1548378900711575))), primary);
+    fs::remove_file(import_path).unwrap();
+}
+
+#[test]
+fn get_events_chunk_should_walk_every_event() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    let msg = Message::from_slice(&payload);
+    let mut keys = Vec::new();
+    for _ in 0..5 {
+        let key: u64 = deserialize(&process_msg(&msg, &store, Socket::Data).unwrap()).unwrap();
+        keys.push(key);
+    }
+
+    let mut token = Some(0u64);
+    let mut chunks = 0;
+    let mut received = Vec::new();
+    while let Some(cursor) = token {
+        let mut payload = [Command::GetEventsChunk.value()].to_vec();
+        payload.extend_from_slice(&serialize(&(cursor, 2u32)).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        let next: Option<u64> = deserialize(&result).unwrap();
+        let token_len = serialize(&next).unwrap().len();
+        let events: Vec<Event> = deserialize(&result[token_len..]).unwrap();
+        assert!(events.len() <= 2);
+        received.extend(events.iter().map(|event| event.ingest_ts));
+        token = next;
+        chunks += 1;
+    }
+
+    assert_eq!(chunks, 3);
+    assert_eq!(received, keys);
+}
+
+#[test]
+fn offsets_should_be_tracked_per_consumer() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let get_offset = |store: &Store, consumer: &str| {
+        let mut payload = [Command::GetOffset.value()].to_vec();
+        payload.extend_from_slice(&serialize(consumer).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), store, Socket::Data).unwrap();
+        deserialize::<Option<u64>>(&result).unwrap()
+    };
+
+    assert_eq!(get_offset(&store, "alerts"), None);
+    for (consumer, offset) in [("alerts", 100u64), ("archiver", 50), ("alerts", 200)].iter() {
+        let mut payload = [Command::CommitOffset.value()].to_vec();
+        payload.extend_from_slice(&serialize(&(consumer, offset)).unwrap());
+        process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    }
+    drop(store);
+
+    // offsets survive a restart
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    assert_eq!(get_offset(&store, "alerts"), Some(200));
+    assert_eq!(get_offset(&store, "archiver"), Some(50));
+}
+
+#[test]
+fn wal_limits_should_still_recover_on_reopen() {
+    destroy_store();
+    let wal_config = || Config {
+        max_total_wal_size: Some(64 * 1024),
+        wal_size_limit_mb: Some(1),
+        recycle_log_file_num: Some(2),
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, wal_config());
+
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    let msg = Message::from_slice(&payload);
+    let mut keys = BTreeSet::new();
+    for _ in 0..2000 {
+        let key: u64 = deserialize(&process_msg(&msg, &store, Socket::Data).unwrap()).unwrap();
+        keys.insert(key);
+    }
+    drop(store);
+
+    let store = Store::with_config(TEST_STORE_PATH, wal_config());
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let result: Vec<Event> = deserialize(&result).unwrap();
+    // puts landing in the same micro share a key, so count distinct keys
+    assert_eq!(result.len(), keys.len());
+}
+
+#[test]
+fn get_events_by_seq_should_return_each_event_once() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let poll = |store: &Store, last_seen: u64| {
+        let mut payload = [Command::GetEventsBySeq.value()].to_vec();
+        payload.extend_from_slice(&serialize(&last_seen).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), store, Socket::Data).unwrap();
+        let next: u64 = deserialize(&result[..8]).unwrap();
+        let events: Vec<Event> = deserialize(&result[8..]).unwrap();
+        (next, events)
+    };
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    let put_msg = Message::from_slice(&payload);
+
+    process_msg(&put_msg, &store, Socket::Data).unwrap();
+    process_msg(&put_msg, &store, Socket::Data).unwrap();
+    let (next, events) = poll(&store, 0);
+    assert_eq!(next, 2);
+    assert_eq!(events.len(), 2);
+
+    let (next, events) = poll(&store, next);
+    assert_eq!(next, 2);
+    assert!(events.is_empty());
+    drop(store);
+
+    // numbering resumes after a restart rather than starting over
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let key: u64 = deserialize(&process_msg(&put_msg, &store, Socket::Data).unwrap()).unwrap();
+    let (next, events) = poll(&store, next);
+    assert_eq!(next, 3);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].ingest_ts, key);
+}
+
+#[test]
+fn validate_event_should_report_problems_without_storing() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let validate = |payload: &[u8]| {
+        let mut msg = [Command::ValidateEvent.value()].to_vec();
+        msg.extend_from_slice(payload);
+        let result = process_msg(&Message::from_slice(&msg), &store, Socket::Data).unwrap();
+        deserialize::<Vec<String>>(&result).unwrap()
+    };
+
+    assert!(validate(&serialize(&get_test_event()).unwrap()).is_empty());
+
+    let mut event = get_test_event();
+    event.event_ts = 0;
+    event.title = String::from(" ");
+    let problems = validate(&serialize(&event).unwrap());
+    assert_eq!(problems, vec!["event_ts is missing", "title is missing"]);
+
+    let problems = validate(b"garbage");
+    assert_eq!(problems.len(), 1);
+    assert!(problems[0].starts_with("payload is not a valid event"));
+    assert!(!problems[0].contains("valid event:"));
+
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let result: Vec<Event> = deserialize(&result).unwrap();
+    assert!(result.is_empty());
+}
+
+#[test]
+fn validate_event_should_describe_invalid_payloads_with_error_detail() {
+    destroy_store();
+    let config = Config {
+        error_detail: true,
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+    let mut msg = [Command::ValidateEvent.value()].to_vec();
+    msg.extend_from_slice(b"garbage");
+    let result = process_msg(&Message::from_slice(&msg), &store, Socket::Data).unwrap();
+    let problems: Vec<String> = deserialize(&result).unwrap();
+    assert_eq!(problems.len(), 1);
+    assert!(problems[0].starts_with("payload is not a valid event: payload ended early"));
+}
+
+#[test]
+fn open_should_retry_while_the_lock_is_held() {
+    destroy_store();
+    let holder = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let releaser = thread::spawn(move || {
+        thread::sleep(time::Duration::from_millis(200));
+        drop(holder);
+    });
+
+    let config = Config {
+        open_retry: Retry {
+            retries: 10,
+            backoff: time::Duration::from_millis(20),
+        },
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+    releaser.join().unwrap();
+
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    assert!(process_msg(&msg, &store, Socket::Data).is_ok());
+}
+
+#[test]
+fn event_size_stats_should_describe_the_window() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let stats_msg = Message::from_slice(&[Command::EventSizeStats.value()]);
+
+    let result = process_msg(&stats_msg, &store, Socket::Data).unwrap();
+    let stats: SizeStats = deserialize(&result).unwrap();
+    assert_eq!(stats.sampled, 0);
+    assert_eq!(stats.max, 0);
+
+    let mut sizes = Vec::new();
+    for len in [10, 100, 1000, 10000].iter() {
+        let mut event = get_test_event();
+        event.text = Some("x".repeat(*len));
+        let event_bytes = serialize(&event).unwrap();
+        sizes.push(event_bytes.len() as u64);
+        let mut payload = [Command::PutEvent.value()].to_vec();
+        payload.extend_from_slice(&event_bytes);
+        process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    }
+
+    let result = process_msg(&stats_msg, &store, Socket::Data).unwrap();
+    let stats: SizeStats = deserialize(&result).unwrap();
+    assert_eq!(stats.sampled, 4);
+    assert_eq!(stats.min, sizes[0]);
+    assert_eq!(stats.max, sizes[3]);
+    assert_eq!(stats.mean, sizes.iter().sum::<u64>() / 4);
+    assert!(stats.min <= stats.p50 && stats.p50 <= stats.p95 && stats.p95 <= stats.max);
+}
+
+#[test]
+fn normalize_cursor_should_snap_to_the_nearest_event() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    let msg = Message::from_slice(&payload);
+    let mut keys = Vec::new();
+    for _ in 0..3 {
+        let key: u64 = deserialize(&process_msg(&msg, &store, Socket::Data).unwrap()).unwrap();
+        keys.push(key);
+    }
+    let mut payload = [Command::DeleteEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&keys[1]).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+
+    // malformed cursors are rejected by GetEvents itself
+    let bad_cursor = serialize(&format!("{}x", keys[1])).unwrap();
+    let mut payload = [Command::GetEvents.value()].to_vec();
+    payload.extend_from_slice(&bad_cursor);
+    assert!(process_msg(&Message::from_slice(&payload), &store, Socket::Data).is_err());
+
+    let normalize = |cursor: &[u8]| {
+        let mut payload = [Command::NormalizeCursor.value()].to_vec();
+        payload.extend_from_slice(cursor);
+        let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        deserialize::<Option<u64>>(&result).unwrap()
+    };
+
+    // a cursor pointing at the deleted event snaps forward past the gap
+    let gap_cursor = serialize(&keys[1].to_string()).unwrap();
+    assert_eq!(normalize(&gap_cursor), Some(keys[2]));
+    assert_eq!(normalize(&bad_cursor), Some(keys[2]));
+    assert_eq!(normalize(b"garbage"), Some(keys[0]));
+    let past_end = serialize(&(keys[2] + 1).to_string()).unwrap();
+    assert_eq!(normalize(&past_end), None);
+}
+
+#[test]
+fn encryption_should_encrypt_at_rest_and_read_legacy_values() {
+    destroy_store();
+    let key_path = "wx_test_encryption.key";
+    fs::write(key_path, [7u8; 32]).unwrap();
+
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut payload = [Command::Put.value()].to_vec();
+    payload.extend_from_slice(&serialize(&("legacy", "plaintext".as_bytes())).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    drop(store);
+
+    let config = Config {
+        encryption_key_path: Some(key_path.to_string()),
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+
+    let mut payload = [Command::Put.value()].to_vec();
+    payload.extend_from_slice(&serialize(&("secret", "testval".as_bytes())).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let mut event = get_test_event();
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&event).unwrap());
+    let key = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    event.ingest_ts = deserialize(&key).unwrap();
+
+    let get = |key: &[u8]| {
+        let mut payload = [Command::Get.value()].to_vec();
+        payload.extend_from_slice(key);
+        process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap()
+    };
+    assert_eq!(get(b"secret"), b"testval");
+    assert_eq!(get(b"legacy"), b"plaintext");
+
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let events: Vec<Event> = deserialize(&result).unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(serialize(&events[0]).unwrap(), serialize(&event).unwrap());
+
+    let get_raw = |cf: &str, key: &[u8]| {
+        let mut payload = [Command::GetRaw.value()].to_vec();
+        payload.extend_from_slice(&serialize(&(cf, key)).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+        deserialize::<Vec<u8>>(&result).unwrap()
+    };
+    let raw = get_raw("default", b"secret");
+    assert!(raw.starts_with(b"wxe\x01"));
+    assert!(!raw.windows(7).any(|window| window == b"testval"));
+    let raw = get_raw("events", event.ingest_ts.to_string().as_bytes());
+    assert!(raw.starts_with(b"wxe\x01"));
+    assert_ne!(raw, serialize(&event).unwrap());
+
+    fs::remove_file(key_path).unwrap();
+}
+
+#[test]
+fn write_probe_should_return_latency_and_clean_up() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+
+    let msg = Message::from_slice(&[Command::WriteProbe.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let latency: u64 = deserialize(&result).unwrap();
+    assert!(latency > 0 && latency < 10 * 1000 * 1000);
+
+    let mut payload = [Command::GetRaw.value()].to_vec();
+    payload
+        .extend_from_slice(&serialize(&("default", &b"\x1fwx_storage_write_probe"[..])).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    let raw: Vec<u8> = deserialize(&result).unwrap();
+    assert!(raw.is_empty());
+}
+
+#[test]
+fn get_events_by_location_should_only_return_events_in_bounds() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let located = |lat: f32, lon: f32, title: &str| Event {
+        location: Some(Location {
+            wfo: None,
+            point: Some(Coordinates { lat, lon }),
+            poly: None,
+        }),
+        title: String::from(title),
+        ..get_test_event()
+    };
+
+    for event in &[
+        located(44.9, -93.2, "minneapolis"),
+        located(41.9, -87.6, "chicago"),
+        get_test_event(),
+    ] {
+        let mut payload = [Command::PutEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(event).unwrap());
+        process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        thread::sleep(time::Duration::from_millis(1));
+    }
+
+    let mut payload = [Command::GetEventsByLocation.value()].to_vec();
+    payload.extend_from_slice(&serialize(&(43.0f32, -97.0f32, 49.0f32, -89.0f32)).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let events: Vec<Event> = deserialize(&result).unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].title, "minneapolis");
+
+    let mut payload = [Command::GetEventsByLocation.value()].to_vec();
+    payload.extend_from_slice(&serialize(&(0.0f32, 0.0f32, 1.0f32, 1.0f32)).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let events: Vec<Event> = deserialize(&result).unwrap();
+    assert!(events.is_empty());
+}
+
+#[test]
+fn delete_events_should_only_remove_listed_keys() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut keys = Vec::new();
+
+    for _ in 0..4 {
+        let mut payload = [Command::PutEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+        let key = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        keys.push(deserialize::<u64>(&key).unwrap());
+        thread::sleep(time::Duration::from_millis(1));
+    }
+
+    let mut payload = [Command::DeleteEvents.value()].to_vec();
+    payload.extend_from_slice(&serialize(&vec![keys[0], keys[2], 1]).unwrap());
+    assert!(process_msg(&Message::from_slice(&payload), &store, Socket::Data).is_err());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    let deleted: u64 = deserialize(&result).unwrap();
+    assert_eq!(deleted, 2);
+
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let events: Vec<Event> = deserialize(&result).unwrap();
+    let remaining: Vec<u64> = events.iter().map(|event| event.ingest_ts).collect();
+    assert_eq!(remaining, vec![keys[1], keys[3]]);
+}
+
+#[test]
+fn big_endian_keys_should_migrate_and_sort_numerically() {
+    destroy_store();
+    let import_path = "wx_test_key_encoding.ndjson";
+    let lines: Vec<String> = [9, 10, 100]
+        .iter()
+        .map(|&ingest_ts| {
+            serde_json::to_string(&Event {
+                ingest_ts,
+                ..get_test_event()
+            })
+            .unwrap()
+        })
+        .collect();
+    fs::write(import_path, lines.join("\n")).unwrap();
+
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut payload = [Command::ImportEvents.value()].to_vec();
+    payload.extend_from_slice(&serialize(&import_path).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    drop(store);
+
+    let config = Config {
+        key_encoding: KeyEncoding::BigEndian,
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+    let ingest_ts = |result: Vec<u8>| -> Vec<u64> {
+        let events: Vec<Event> = deserialize(&result).unwrap();
+        events.iter().map(|event| event.ingest_ts).collect()
+    };
+
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    assert_eq!(ingest_ts(result), vec![9, 10, 100]);
+
+    let mut payload = [Command::GetEvents.value()].to_vec();
+    payload.extend_from_slice(&serialize(&"9").unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    assert_eq!(ingest_ts(result), vec![10, 100]);
+
+    let mut payload = [Command::GetEventsBySeq.value()].to_vec();
+    payload.extend_from_slice(&serialize(&0u64).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    assert_eq!(ingest_ts(result[8..].to_vec()), vec![9, 10, 100]);
+
+    let mut payload = [Command::GetRaw.value()].to_vec();
+    payload.extend_from_slice(&serialize(&("events", &10u64.to_be_bytes()[..])).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    let raw: Vec<u8> = deserialize(&result).unwrap();
+    assert_eq!(
+        raw,
+        serialize(&Event {
+            ingest_ts: 10,
+            ..get_test_event()
+        })
+        .unwrap()
+    );
+    fs::remove_file(import_path).unwrap();
+}
+
+#[test]
+fn migrate_key_encoding_should_rekey_events_online_and_resume() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let put_event = |store: &Store| -> u64 {
+        let mut payload = [Command::PutEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), store, Socket::Data).unwrap();
+        deserialize(&result).unwrap()
+    };
+    let mut keys: Vec<u64> = (0..5).map(|_| put_event(&store)).collect();
+    let migrate = |store: &Store| -> (u64, u64, u8) {
+        let mut payload = [Command::MigrateKeyEncoding.value()].to_vec();
+        payload.extend_from_slice(&serialize(&("big_endian", 2u32)).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), store, Socket::Admin).unwrap();
+        deserialize(&result).unwrap()
+    };
+    let ingest_ts = |store: &Store, command: Command, payload: &[u8]| -> Vec<u64> {
+        let mut msg = [command.value()].to_vec();
+        msg.extend_from_slice(payload);
+        let result = process_msg(&Message::from_slice(&msg), store, Socket::Data).unwrap();
+        let events: Vec<Event> = deserialize(&result).unwrap();
+        events.iter().map(|event| event.ingest_ts).collect()
+    };
+
+    assert_eq!(migrate(&store), (2, 5, 40));
+
+    // Partway through, events under either encoding are read in order, and writes carry on
+    keys.push(put_event(&store));
+    assert_eq!(ingest_ts(&store, Command::GetAllEvents, &[]), keys);
+    let cursor = serialize(&keys[1].to_string()).unwrap();
+    assert_eq!(
+        ingest_ts(&store, Command::GetEvents, &cursor),
+        keys[2..].to_vec()
+    );
+    let mut payload = [Command::GetEventsBySeq.value()].to_vec();
+    payload.extend_from_slice(&serialize(&0u64).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let events: Vec<Event> = deserialize(&result[8..]).unwrap();
+    assert_eq!(events.len(), keys.len());
+
+    // Progress survives a restart
+    drop(store);
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    assert_eq!(migrate(&store), (4, 5, 80));
+    assert_eq!(migrate(&store), (6, 5, 100));
+    assert_eq!(migrate(&store), (0, 0, 100));
+    assert_eq!(ingest_ts(&store, Command::GetAllEvents, &[]), keys);
+
+    let mut payload = [Command::GetRaw.value()].to_vec();
+    payload.extend_from_slice(&serialize(&("events", &keys[0].to_be_bytes()[..])).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    let raw: Vec<u8> = deserialize(&result).unwrap();
+    assert!(!raw.is_empty());
+    drop(store);
+
+    let config = Config {
+        key_encoding: KeyEncoding::BigEndian,
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+    assert_eq!(ingest_ts(&store, Command::GetAllEvents, &[]), keys);
+    let mut payload = [Command::GetEventsBySeq.value()].to_vec();
+    payload.extend_from_slice(&serialize(&0u64).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let events: Vec<Event> = deserialize(&result[8..]).unwrap();
+    let sequenced: Vec<u64> = events.iter().map(|event| event.ingest_ts).collect();
+    assert_eq!(sequenced, keys);
+}
+
+#[test]
+fn migrated_key_encoding_should_outlast_a_stale_config() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let key: u64 = deserialize(&result).unwrap();
+    let mut payload = [Command::MigrateKeyEncoding.value()].to_vec();
+    payload.extend_from_slice(&serialize(&("big_endian", 10u32)).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    let progress: (u64, u64, u8) = deserialize(&result).unwrap();
+    assert_eq!(progress, (1, 1, 100));
+    drop(store);
+
+    // Reopening with the default, decimal, config leaves the events where the migration put them
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let get_raw = |store: &Store, key: &[u8]| -> Vec<u8> {
+        let mut payload = [Command::GetRaw.value()].to_vec();
+        payload.extend_from_slice(&serialize(&("events", key)).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), store, Socket::Admin).unwrap();
+        deserialize(&result).unwrap()
+    };
+    assert!(!get_raw(&store, &key.to_be_bytes()).is_empty());
+    assert!(get_raw(&store, key.to_string().as_bytes()).is_empty());
+
+    let payload = [Command::GetAllEvents.value()];
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let events: Vec<Event> = deserialize(&result).unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].ingest_ts, key);
+
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let key: u64 = deserialize(&result).unwrap();
+    assert!(!get_raw(&store, &key.to_be_bytes()).is_empty());
+}
+
+#[test]
+fn events_diff_should_return_events_ingested_in_the_interval() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let keys: Vec<u64> = (0..4)
+        .map(|_| {
+            let mut payload = [Command::PutEvent.value()].to_vec();
+            payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+            let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+            deserialize(&result).unwrap()
+        })
+        .collect();
+
+    let mut payload = [Command::EventsDiff.value()].to_vec();
+    payload.extend_from_slice(&serialize(&(keys[0], keys[2])).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let events: Vec<Event> = deserialize(&result).unwrap();
+    let ingested: Vec<u64> = events.iter().map(|event| event.ingest_ts).collect();
+    assert_eq!(ingested, keys[1..3].to_vec());
+}
+
+#[test]
+fn streaming_reads_beyond_the_iterator_limit_should_be_rejected() {
+    destroy_store();
+    let config = Config {
+        max_open_iterators: 1,
+        ..Config::default()
+    };
+    let store = Arc::new(Store::with_config(TEST_STORE_PATH, config));
+    let follower = {
+        let store = store.clone();
+        thread::spawn(move || {
+            let mut payload = [Command::FollowFrom.value()].to_vec();
+            payload.extend_from_slice(&serialize(&(0u64, 1000u64)).unwrap());
+            process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        })
+    };
+    thread::sleep(time::Duration::from_millis(200));
+
+    let mut payload = [Command::GetEventsChunk.value()].to_vec();
+    payload.extend_from_slice(&serialize(&(0u64, 10u32)).unwrap());
+    let chunk_msg = Message::from_slice(&payload);
+    let result = process_msg(&chunk_msg, &store, Socket::Data);
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("too many open iterators"));
+
+    // Other reads aren't limited
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    assert!(process_msg(&msg, &store, Socket::Data).is_ok());
+
+    follower.join().unwrap();
+    assert!(process_msg(&chunk_msg, &store, Socket::Data).is_ok());
+}
+
+#[test]
+fn replicate_to_should_copy_every_event_into_another_store() {
+    destroy_store();
+    let replica_path = "wx_test_replica";
+    DB::destroy(&Options::default(), replica_path).unwrap();
+    let address = "tcp://127.0.0.1:31399";
+    let config = Config {
+        import_batch_size: 2,
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    for _ in 0..5 {
+        process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    }
+
+    // Serves the three batches the way the data socket's listeners do
+    let replica = Arc::new(Store::new(replica_path, EVENT_THRESHOLD_MICROS));
+    let ctx = zmq::Context::new();
+    let sock = ctx.socket(zmq::REP).unwrap();
+    sock.bind(address).unwrap();
+    let server = {
+        let replica = replica.clone();
+        thread::spawn(move || {
+            let mut msg = Message::new();
+            for _ in 0..3 {
+                sock.recv(&mut msg, 0).unwrap();
+                let result = process_msg(&msg, &replica, Socket::Data).unwrap();
+                let mut reply = [Status::OkByte.value()].to_vec();
+                reply.extend_from_slice(&result);
+                sock.send(reply, 0).unwrap();
+            }
+        })
+    };
+
+    let mut payload = [Command::ReplicateTo.value()].to_vec();
+    payload.extend_from_slice(&serialize(&address).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    assert_eq!(deserialize::<u64>(&result).unwrap(), 5);
+    server.join().unwrap();
+
+    let mut payload = [Command::EventsDigest.value()].to_vec();
+    payload.extend_from_slice(&serialize(&None::<(u64, u64)>).unwrap());
+    let msg = Message::from_slice(&payload);
+    let source = process_msg(&msg, &store, Socket::Data).unwrap();
+    let copy = process_msg(&msg, &replica, Socket::Data).unwrap();
+    assert_eq!(source, copy);
+    drop(replica);
+    DB::destroy(&Options::default(), replica_path).unwrap();
+}
+
+#[test]
+fn put_event_batch_should_not_overwrite_stored_events() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    let key = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let key: u64 = deserialize(&key).unwrap();
+
+    let clobber = Event {
+        ingest_ts: key,
+        title: String::from("clobbered"),
+        ..get_test_event()
+    };
+    let new = || Event {
+        ingest_ts: key + 1,
+        ..get_test_event()
+    };
+    for events in [vec![new(), clobber], vec![new(), new()]].iter() {
+        let mut payload = [Command::PutEventBatch.value()].to_vec();
+        payload.extend_from_slice(&serialize(&(events, true)).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("event already exists"));
+    }
+
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let events: Vec<Event> = deserialize(&result).unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].title, get_test_event().title);
+}
+
+#[test]
+fn write_throughput_should_report_recent_write_rates() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    for _ in 0..30 {
+        process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    }
+
+    let msg = Message::from_slice(&[Command::WriteThroughput.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let throughput: Throughput = deserialize(&result).unwrap();
+    assert!((throughput.last_10s - 3.0).abs() < 1e-9);
+    assert!((throughput.last_1m - 0.5).abs() < 1e-9);
+    assert!((throughput.last_5m - 0.1).abs() < 1e-9);
+}
+
+#[test]
+fn compare_and_swap_should_only_write_when_the_current_value_matches() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut payload = [Command::Put.value()].to_vec();
+    payload.extend_from_slice(&serialize(&("leader", &b"a"[..])).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let cas = |key: &str, expected: Option<&[u8]>, new: &[u8]| -> bool {
+        let mut payload = [Command::CompareAndSwap.value()].to_vec();
+        payload.extend_from_slice(&serialize(&(key, expected, new)).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        deserialize(&result).unwrap()
+    };
+    let get = |key: &str| -> Vec<u8> {
+        let mut payload = [Command::Get.value()].to_vec();
+        payload.extend_from_slice(key.as_bytes());
+        process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap()
+    };
+
+    assert!(!cas("leader", Some(&b"b"[..]), b"c"));
+    assert_eq!(get("leader"), b"a");
+    assert!(cas("leader", Some(&b"a"[..]), b"c"));
+    assert_eq!(get("leader"), b"c");
+
+    assert!(cas("lease", None, b"x"));
+    assert!(!cas("lease", None, b"y"));
+    assert_eq!(get("lease"), b"x");
+}
+
+#[test]
+fn top_event_types_should_rank_types_by_count() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let event_type = |i: u32| deserialize::<EventType>(&serialize(&i).unwrap()).unwrap();
+    for &(i, count) in [(0u32, 1), (1, 3), (2, 3)].iter() {
+        for _ in 0..count {
+            let event = Event {
+                event_type: event_type(i),
+                ..get_test_event()
+            };
+            let mut payload = [Command::PutEvent.value()].to_vec();
+            payload.extend_from_slice(&serialize(&event).unwrap());
+            process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        }
+    }
+    let top = |limit: Option<u32>| -> Vec<(EventType, u64)> {
+        let mut payload = [Command::TopEventTypes.value()].to_vec();
+        payload.extend_from_slice(&serialize(&limit).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        deserialize(&result).unwrap()
+    };
+
+    assert_eq!(top(Some(2)), vec![(event_type(1), 3), (event_type(2), 3)]);
+    assert_eq!(
+        top(None),
+        vec![(event_type(1), 3), (event_type(2), 3), (event_type(0), 1)]
+    );
+}
+
+#[test]
+fn annotations_should_be_stored_and_queried_by_event() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    let msg = Message::from_slice(&payload);
+    let keys: Vec<u64> = (0..2)
+        .map(|_| deserialize(&process_msg(&msg, &store, Socket::Data).unwrap()).unwrap())
+        .collect();
+    let annotate = |ingest_ts: u64, note: &str| {
+        let mut payload = [Command::AnnotateEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(&(ingest_ts, note)).unwrap());
+        process_msg(&Message::from_slice(&payload), &store, Socket::Data)
+    };
+    let notes = |start: u64, end: Option<u64>| -> Vec<(u64, String)> {
+        let mut payload = [Command::GetAnnotations.value()].to_vec();
+        payload.extend_from_slice(&serialize(&(start, end)).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        let annotations: Vec<Annotation> = deserialize(&result).unwrap();
+        annotations
+            .into_iter()
+            .map(|annotation| (annotation.ingest_ts, annotation.note))
+            .collect()
+    };
+
+    annotate(keys[0], "false alarm").unwrap();
+    annotate(keys[0], "confirmed by spotter").unwrap();
+    annotate(keys[1], "verified").unwrap();
+    assert!(annotate(keys[1] + 1, "no such event").is_err());
+
+    assert_eq!(
+        notes(keys[0], None),
+        vec![
+            (keys[0], String::from("false alarm")),
+            (keys[0], String::from("confirmed by spotter"))
+        ]
+    );
+    assert_eq!(notes(keys[0], Some(keys[1] + 1)).len(), 3);
+
+    // The event itself is untouched
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let events: Vec<Event> =
+        deserialize(&process_msg(&msg, &store, Socket::Data).unwrap()).unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].title, "title");
+}
+
+#[test]
+fn concurrent_annotations_should_not_overwrite_each_other() {
+    destroy_store();
+    let store = Arc::new(Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS));
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let key: u64 = deserialize(&result).unwrap();
+
+    // Every thread annotates the same event at once, so many notes land in the same micro
+    let annotators: Vec<_> = (0..4)
+        .map(|worker| {
+            let store = store.clone();
+            thread::spawn(move || {
+                for i in 0..100 {
+                    let note = format!("{}-{}", worker, i);
+                    let mut payload = [Command::AnnotateEvent.value()].to_vec();
+                    payload.extend_from_slice(&serialize(&(key, note.as_str())).unwrap());
+                    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+                }
+            })
+        })
+        .collect();
+    for annotator in annotators {
+        annotator.join().unwrap();
+    }
+
+    let mut payload = [Command::GetAnnotations.value()].to_vec();
+    payload.extend_from_slice(&serialize(&(key, None::<u64>)).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let annotations: Vec<Annotation> = deserialize(&result).unwrap();
+    assert_eq!(annotations.len(), 400);
+}
+
+#[test]
+fn verify_indexes_should_flag_and_repair_index_drift() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    let msg = Message::from_slice(&payload);
+    let keys: Vec<u64> = (0..2)
+        .map(|_| deserialize(&process_msg(&msg, &store, Socket::Data).unwrap()).unwrap())
+        .collect();
+    drop(store);
+
+    // drop the first event's index entry, and add one for an event that never existed, behind
+    // the store's back
+    {
+        let db = DB::open_cf(
+            &Options::default(),
+            TEST_STORE_PATH,
+            [
+                "annotations",
+                "claims",
+                "events",
+                "event_totals",
+                "event_types",
+                "fetch_failures",
+                "metadata",
+                "offsets",
+                "rollups",
+                "sequence",
+            ],
+        )
+        .unwrap();
+        let mut type_key = serialize(&EventType::NwsLsr).unwrap();
+        type_key.extend_from_slice(keys[0].to_string().as_bytes());
+        db.delete_cf(db.cf_handle("event_types").unwrap(), &type_key)
+            .unwrap();
+        let mut type_key = serialize(&EventType::NwsLsr).unwrap();
+        type_key.extend_from_slice((keys[1] + 1).to_string().as_bytes());
+        db.put_cf(db.cf_handle("event_types").unwrap(), &type_key, b"")
+            .unwrap();
+    }
+
+    // deleting an event takes its index entry with it, so isn't reported
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut payload = [Command::DeleteEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&keys[1]).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    let verify = |repair: bool| -> IndexReport {
+        let mut payload = [Command::VerifyIndexes.value()].to_vec();
+        payload.extend_from_slice(&serialize(&repair).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+        deserialize(&result).unwrap()
+    };
+
+    let report = verify(false);
+    assert_eq!((report.events, report.missing, report.orphaned), (1, 1, 1));
+    let report = verify(true);
+    assert_eq!((report.events, report.missing, report.orphaned), (1, 1, 1));
+    let report = verify(false);
+    assert_eq!((report.events, report.missing, report.orphaned), (1, 0, 0));
+
+    let mut payload = [Command::GetEventsByType.value()].to_vec();
+    payload.extend_from_slice(&serialize(&EventType::NwsLsr).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let events: Vec<Event> = deserialize(&result).unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].ingest_ts, keys[0]);
+}
+
+#[test]
+fn sweep_events_should_enforce_stored_retention_policy() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    let msg = Message::from_slice(&payload);
+    let keys: Vec<u64> = (0..3)
+        .map(|_| deserialize(&process_msg(&msg, &store, Socket::Data).unwrap()).unwrap())
+        .collect();
+    let policy = RetentionPolicy {
+        type_retention: vec![],
+        max_events: Some(2),
+        max_bytes: None,
+    };
+    let mut payload = [Command::SetRetentionPolicy.value()].to_vec();
+    payload.extend_from_slice(&serialize(&policy).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    drop(store);
+
+    // a policy left in the default column family by an older build is carried over
+    {
+        let opts = Options::default();
+        let cfs = DB::list_cf(&opts, TEST_STORE_PATH).unwrap();
+        let cfs: Vec<&str> = cfs.iter().map(String::as_str).collect();
+        let db = DB::open_cf(&opts, TEST_STORE_PATH, &cfs).unwrap();
+        let key = &b"\x1fwx_storage_retention"[..];
+        let value = db.get_cf(db.cf_handle("metadata").unwrap(), key).unwrap();
+        assert!(value.is_some());
+        db.put(key, &value.unwrap()[..]).unwrap();
+        db.delete_cf(db.cf_handle("metadata").unwrap(), key)
+            .unwrap();
+    }
+
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let msg = Message::from_slice(&[Command::GetRetentionPolicy.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let stored: RetentionPolicy = deserialize(&result).unwrap();
+    assert_eq!(stored, policy);
+
+    let sweep = || -> u64 {
+        let msg = Message::from_slice(&[Command::SweepEvents.value()]);
+        deserialize(&process_msg(&msg, &store, Socket::Admin).unwrap()).unwrap()
+    };
+    let orphaned = || -> u64 {
+        let mut payload = [Command::VerifyIndexes.value()].to_vec();
+        payload.extend_from_slice(&serialize(&false).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+        deserialize::<IndexReport>(&result).unwrap().orphaned
+    };
+    assert_eq!(sweep(), 1);
+    assert_eq!(sweep(), 0);
+    assert_eq!(orphaned(), 0);
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let events: Vec<Event> = deserialize(&result).unwrap();
+    let ingest_ts: Vec<u64> = events.iter().map(|event| event.ingest_ts).collect();
+    assert_eq!(ingest_ts, keys[1..].to_vec());
+
+    let policy = RetentionPolicy {
+        type_retention: vec![(EventType::NwsLsr, 1)],
+        max_events: None,
+        max_bytes: None,
+    };
+    let mut payload = [Command::SetRetentionPolicy.value()].to_vec();
+    payload.extend_from_slice(&serialize(&policy).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    thread::sleep(time::Duration::from_millis(1));
+    assert_eq!(sweep(), 2);
+    assert_eq!(orphaned(), 0);
+}
+
+#[test]
+fn get_recent_paged_should_cover_every_event_once() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    let put = Message::from_slice(&payload);
+    let mut keys: Vec<u64> = (0..5)
+        .map(|_| deserialize(&process_msg(&put, &store, Socket::Data).unwrap()).unwrap())
+        .collect();
+    keys.reverse();
+    let page = |cursor: Option<u64>| -> (Option<u64>, Vec<Event>) {
+        let mut payload = [Command::GetRecentPaged.value()].to_vec();
+        payload.extend_from_slice(&serialize(&(cursor, 2u32)).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        deserialize(&result).unwrap()
+    };
+
+    let mut seen = Vec::new();
+    let (mut cursor, events) = page(None);
+    seen.extend(events.iter().map(|event| event.ingest_ts));
+    // arrivals mid-scroll belong to a fresh first page, not the older ones
+    process_msg(&put, &store, Socket::Data).unwrap();
+
+    while cursor.is_some() {
+        let (next, events) = page(cursor);
+        assert!(events.len() <= 2);
+        seen.extend(events.iter().map(|event| event.ingest_ts));
+        cursor = next;
+    }
+
+    assert_eq!(seen, keys);
+}
+
+#[test]
+fn storage_efficiency_should_report_sane_ratios() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    let put = Message::from_slice(&payload);
+    for _ in 0..200 {
+        process_msg(&put, &store, Socket::Data).unwrap();
+    }
+    let flush = Message::from_slice(&[Command::Flush.value()]);
+    process_msg(&flush, &store, Socket::Admin).unwrap();
+
+    let msg = Message::from_slice(&[Command::StorageEfficiency.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let efficiency: StorageEfficiency = deserialize(&result).unwrap();
+    assert!(efficiency.logical_bytes > 0);
+    assert!(efficiency.sst_bytes > 0);
+    assert!(efficiency.compression_ratio > 0.1 && efficiency.compression_ratio < 100.0);
+    assert!(efficiency.bytes_written > 0);
+    assert!(efficiency.flush_bytes > 0);
+    assert!(efficiency.write_amplification > 0.0 && efficiency.write_amplification < 100.0);
+}
+
+#[test]
+fn get_expiring_soon_should_only_return_events_lapsing_in_lookahead() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let minute = 60 * 1000 * 1000;
+    let now = wx::util::get_system_micros();
+    let expiries = [
+        Some(now + 4 * minute),
+        Some(now + 10 * minute),
+        Some(now - minute),
+        None,
+        Some(now + minute),
+    ];
+
+    for expires_ts in expiries.iter() {
+        let mut event = get_test_event();
+        event.expires_ts = *expires_ts;
+        let mut payload = [Command::PutEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(&event).unwrap());
+        process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    }
+
+    let mut payload = [Command::GetExpiringSoon.value()].to_vec();
+    payload.extend_from_slice(&serialize(&(5 * minute)).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let events: Vec<Event> = deserialize(&result).unwrap();
+    let expires: Vec<Option<u64>> = events.iter().map(|event| event.expires_ts).collect();
+    assert_eq!(expires, vec![expiries[4], expiries[0]]);
+}
+
+#[test]
+fn swap_store_should_serve_staging_data_once_swapped() {
+    let staging_path = "wx_test_staging";
+    let old_path = format!("{}.old", TEST_STORE_PATH);
+    let _ = fs::remove_dir_all(&old_path);
+    DB::destroy(&Options::default(), staging_path).unwrap();
+    destroy_store();
+    let put = |store: &Store, text: &str| {
+        let mut event = get_test_event();
+        event.text = Some(String::from(text));
+        let mut payload = [Command::PutEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(&event).unwrap());
+        process_msg(&Message::from_slice(&payload), store, Socket::Data)
+    };
+    {
+        let staging = Store::new(staging_path, EVENT_THRESHOLD_MICROS);
+        put(&staging, "rebuilt").unwrap();
+    }
+
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    put(&store, "live").unwrap();
+    let mut payload = [Command::SwapStore.value()].to_vec();
+    payload.extend_from_slice(&serialize(&"wx_test_missing").unwrap());
+    assert!(process_msg(&Message::from_slice(&payload), &store, Socket::Admin).is_err());
+    assert_eq!(store.pending_swap(), None);
+
+    let mut payload = [Command::SwapStore.value()].to_vec();
+    payload.extend_from_slice(&serialize(&staging_path).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    assert_eq!(store.pending_swap(), Some(String::from(staging_path)));
+    assert!(put(&store, "lost").is_err());
+
+    drop(store);
+    // a store left by an earlier swap is never overwritten
+    fs::create_dir(&old_path).unwrap();
+    assert!(Store::swap_directories(TEST_STORE_PATH, staging_path).is_err());
+    assert!(fs::metadata(staging_path).is_ok());
+    fs::remove_dir(&old_path).unwrap();
+    Store::swap_directories(TEST_STORE_PATH, staging_path).unwrap();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    put(&store, "after").unwrap();
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let events: Vec<Event> = deserialize(&result).unwrap();
+    let texts: Vec<&str> = events
+        .iter()
+        .map(|event| event.text.as_ref().unwrap().as_str())
+        .collect();
+    assert_eq!(texts, vec!["rebuilt", "after"]);
+    assert!(fs::metadata(staging_path).is_err());
+
+    drop(store);
+    fs::remove_dir_all(&old_path).unwrap();
+}
+
+#[test]
+fn shared_store_should_swap_without_waiting_on_idle_listeners() {
+    let path = "wx_test_swap_live";
+    let staging_path = "wx_test_swap_staging";
+    let newer_path = "wx_test_swap_newer";
+    let admin_address = "tcp://127.0.0.1:31398";
+    let _ = fs::remove_dir_all(format!("{}.old", path));
+    for path in [path, staging_path, newer_path].iter() {
+        DB::destroy(&Options::default(), path).unwrap();
+    }
+    let put = |text: &str| {
+        let mut event = get_test_event();
+        event.text = Some(String::from(text));
+        let mut payload = [Command::PutEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(&event).unwrap());
+        Message::from_slice(&payload)
+    };
+    {
+        let staging = Store::new(staging_path, EVENT_THRESHOLD_MICROS);
+        process_msg(&put("rebuilt"), &staging, Socket::Data).unwrap();
+    }
+    {
+        Store::new(newer_path, EVENT_THRESHOLD_MICROS);
+        let opts = Options::default();
+        let cfs = DB::list_cf(&opts, newer_path).unwrap();
+        let cfs: Vec<&str> = cfs.iter().map(String::as_str).collect();
+        let db = DB::open_cf(&opts, newer_path, &cfs).unwrap();
+        let format = serialize(&(SCHEMA_VERSION + 1, "decimal")).unwrap();
+        db.put_cf(
+            db.cf_handle("metadata").unwrap(),
+            b"\x1fwx_storage_format",
+            format,
+        )
+        .unwrap();
+    }
+    let store = Arc::new(SharedStore::open(path, Config::default).unwrap());
+    store.process(&put("live"), Socket::Data).unwrap();
+
+    // a staging store this build can't open is turned away, leaving the live store writable
+    let mut payload = [Command::SwapStore.value()].to_vec();
+    payload.extend_from_slice(&serialize(&newer_path).unwrap());
+    let result = store.process(&Message::from_slice(&payload), Socket::Admin);
+    assert!(result.unwrap_err().to_string().contains("schema version"));
+    store.process(&put("still live"), Socket::Data).unwrap();
+
+    let logger = Logger::root(Discard, o!());
+    let ctx = zmq::Context::new();
+    for i in 0..2 {
+        let ctx = ctx.clone();
+        let open = move || -> Result<zmq::Socket, zmq::Error> {
+            let sock = ctx.socket(zmq::REP)?;
+            sock.bind(&format!("inproc://wx_test_swap_data_{}", i))?;
+            Ok(sock)
+        };
+        spawn_listener(open, Socket::Data, store.clone(), logger.clone());
+    }
+    let admin_ctx = ctx.clone();
+    let open_admin = move || -> Result<zmq::Socket, zmq::Error> {
+        let sock = admin_ctx.socket(zmq::REP)?;
+        sock.bind(admin_address)?;
+        Ok(sock)
+    };
+    spawn_listener(open_admin, Socket::Admin, store.clone(), logger);
+
+    // the data listeners are idle in recv the whole time
+    let client = ctx.socket(zmq::REQ).unwrap();
+    client.set_rcvtimeo(10 * 1000).unwrap();
+    client.connect(admin_address).unwrap();
+    let mut request = [Command::SwapStore.value()].to_vec();
+    request.extend_from_slice(&serialize(&staging_path).unwrap());
+    client.send(request, 0).unwrap();
+    let reply = client.recv_bytes(0).unwrap();
+    assert_eq!(reply[0], Status::OkByte.value());
+
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result = store.process(&msg, Socket::Data).unwrap();
+    let events: Vec<Event> = deserialize(&result).unwrap();
+    let texts: Vec<&str> = events
+        .iter()
+        .map(|event| event.text.as_ref().unwrap().as_str())
+        .collect();
+    assert_eq!(texts, vec!["rebuilt"]);
+    store.process(&put("after"), Socket::Data).unwrap();
+}
+
+/// Serves requests from a channel and sends replies to another. Sends fail if `fail_sends` is set.
+struct ChannelTransport {
+    requests: Arc<Mutex<mpsc::Receiver<Vec<u8>>>>,
+    replies: mpsc::Sender<Vec<u8>>,
+    fail_sends: bool,
+}
+
+impl Transport for ChannelTransport {
+    fn recv(&mut self, msg: &mut Message) -> Result<(), zmq::Error> {
+        let request = self.requests.lock().unwrap().recv().unwrap();
+        *msg = Message::from_slice(&request);
+        Ok(())
+    }
+
+    fn send(&mut self, payload: Vec<u8>) -> Result<(), zmq::Error> {
+        if self.fail_sends {
+            return Err(zmq::Error::EFSM);
+        }
+        self.replies.send(payload).unwrap();
+        Ok(())
+    }
+}
+
+#[test]
+fn listener_should_replace_a_socket_that_fails_to_send() {
+    let path = "wx_test_listener_reset";
+    DB::destroy(&Options::default(), path).unwrap();
+    let store = Arc::new(SharedStore::open(path, Config::default).unwrap());
+    let (request_tx, request_rx) = mpsc::channel();
+    let (reply_tx, reply_rx) = mpsc::channel();
+    let requests = Arc::new(Mutex::new(request_rx));
+    let opened = Arc::new(Mutex::new(0));
+    let open_count = opened.clone();
+    let open = move || {
+        let mut opened = open_count.lock().unwrap();
+        *opened += 1;
+        // the replacement fails to open at first, as a rebind can while the old socket closes
+        if *opened == 2 {
+            return Err(zmq::Error::EADDRINUSE);
+        }
+        Ok(ChannelTransport {
+            requests: requests.clone(),
+            replies: reply_tx.clone(),
+            fail_sends: *opened == 1,
+        })
+    };
+    spawn_listener(open, Socket::Data, store, Logger::root(Discard, o!()));
+
+    // the first reply is lost, and the next request is served on a fresh socket
+    let mut event = get_test_event();
+    event.text = Some(String::from("lost reply"));
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&event).unwrap());
+    request_tx.send(payload).unwrap();
+    request_tx
+        .send([Command::GetAllEvents.value()].to_vec())
+        .unwrap();
+
+    let reply = reply_rx
+        .recv_timeout(time::Duration::from_secs(10))
+        .unwrap();
+    assert_eq!(reply[0], Status::OkByte.value());
+    let events: Vec<Event> = deserialize(&reply[1..]).unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].text, Some(String::from("lost reply")));
+    assert_eq!(*opened.lock().unwrap(), 3);
+}
+
+#[test]
+fn sample_events_should_be_reproducible_by_seed() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    let put = Message::from_slice(&payload);
+    for _ in 0..20 {
+        process_msg(&put, &store, Socket::Data).unwrap();
+    }
+    let sample = |size: u32, seed: Option<u64>| -> Vec<u64> {
+        let mut payload = [Command::SampleEvents.value()].to_vec();
+        payload.extend_from_slice(&serialize(&(size, seed)).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        let events: Vec<Event> = deserialize(&result).unwrap();
+        events.iter().map(|event| event.ingest_ts).collect()
+    };
+
+    let first = sample(5, Some(1));
+    assert_eq!(first.len(), 5);
+    assert!(first.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(sample(5, Some(1)), first);
+    assert_ne!(sample(5, Some(2)), first);
+    assert_eq!(sample(5, None).len(), 5);
+    assert_eq!(sample(50, Some(1)).len(), 20);
+}
+
+#[test]
+fn sample_events_should_reject_sizes_over_the_maximum() {
+    destroy_store();
+    let config = Config {
+        max_sample_size: 10,
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+    let sample = |size: u32| {
+        let mut payload = [Command::SampleEvents.value()].to_vec();
+        payload.extend_from_slice(&serialize(&(size, Some(1u64))).unwrap());
+        process_msg(&Message::from_slice(&payload), &store, Socket::Data)
+    };
+
+    assert!(sample(10).is_ok());
+    let result = sample(11);
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("exceeds maximum of 10"));
+    let result = sample(u32::MAX);
+    assert!(result.unwrap_err().to_string().contains("exceeds maximum"));
+}
+
+#[test]
+fn get_event_headers_should_exclude_bodies() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let event = Event {
+        text: Some(String::from("summary\nthe rest of a very long body")),
+        ..get_test_event()
+    };
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&event).unwrap());
+    let key = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+
+    let msg = Message::from_slice(&[Command::GetEventHeaders.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    assert!(!result.windows(4).any(|window| window == b"body"));
+    let headers: Vec<EventHeader> = deserialize(&result).unwrap();
+    assert_eq!(headers.len(), 1);
+    assert_eq!(headers[0].ingest_ts, deserialize::<u64>(&key).unwrap());
+    assert_eq!(headers[0].event_type, EventType::NwsLsr);
+    assert_eq!(headers[0].title, "title");
+    assert_eq!(headers[0].summary, Some(String::from("summary")));
+    assert_eq!(headers[0].expires_ts, None);
+}
+
+#[test]
+fn wait_for_event_should_wake_on_put_or_time_out() {
+    destroy_store();
+    let store = Arc::new(Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS));
+    let wait = |store: &Store, cursor: u64, timeout_millis: u64| {
+        let mut payload = [Command::WaitForEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(&(cursor, timeout_millis)).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), store, Socket::Data).unwrap();
+        deserialize::<Vec<Event>>(&result).unwrap()
+    };
+
+    let start = time::Instant::now();
+    assert!(wait(&store, 0, 50).is_empty());
+    assert!(start.elapsed() >= time::Duration::from_millis(50));
+
+    let writer = {
+        let store = store.clone();
+        thread::spawn(move || {
+            thread::sleep(time::Duration::from_millis(100));
+            let mut payload = [Command::PutEvent.value()].to_vec();
+            payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+            process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        })
+    };
+
+    let start = time::Instant::now();
+    let events = wait(&store, 0, 10 * 1000);
+    assert_eq!(events.len(), 1);
+    assert!(start.elapsed() < time::Duration::from_secs(5));
+    writer.join().unwrap();
+
+    assert!(wait(&store, events[0].ingest_ts, 10).is_empty());
+}
+
+#[test]
+fn sequence_index_should_match_events_after_unflushed_reopen() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut keys = BTreeSet::new();
+
+    for _ in 0..50 {
+        let mut payload = [Command::PutEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+        let key = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        keys.insert(deserialize::<u64>(&key).unwrap());
+    }
+
+    // Nothing was flushed, so everything is recovered from the write-ahead log
+    drop(store);
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let events: Vec<Event> = deserialize(&result).unwrap();
+    let stored: BTreeSet<u64> = events.iter().map(|event| event.ingest_ts).collect();
+    assert_eq!(stored, keys);
+
+    let mut payload = [Command::GetEventsBySeq.value()].to_vec();
+    payload.extend_from_slice(&serialize(&0u64).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let events: Vec<Event> = deserialize(&result[8..]).unwrap();
+    let sequenced: BTreeSet<u64> = events.iter().map(|event| event.ingest_ts).collect();
+    assert_eq!(sequenced, keys);
+}
+
+#[test]
+fn get_events_sse_should_return_well_formed_frames() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let event = Event {
+        text: Some(String::from("line one\nline two")),
+        ..get_test_event()
+    };
+    let mut keys = Vec::new();
+
+    for _ in 0..2 {
+        let mut payload = [Command::PutEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(&event).unwrap());
+        let key = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        keys.push(deserialize::<u64>(&key).unwrap());
+        thread::sleep(time::Duration::from_millis(1));
+    }
+
+    let msg = Message::from_slice(&[Command::GetEventsSse.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let text = String::from_utf8(result).unwrap();
+    assert!(text.ends_with("\n\n"));
+    let frames: Vec<&str> = text.trim_end_matches('\n').split("\n\n").collect();
+    assert_eq!(frames.len(), 2);
+
+    for (frame, key) in frames.iter().zip(keys) {
+        let lines: Vec<&str> = frame.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], format!("id: {}", key));
+        assert!(lines[1].starts_with("data: "));
+        let parsed: Event = serde_json::from_str(&lines[1]["data: ".len()..]).unwrap();
+        assert_eq!(parsed.ingest_ts, key);
+        assert_eq!(parsed.text, event.text);
+    }
+}
+
+#[test]
+fn replace_window_should_swap_in_only_the_new_events() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut keys = Vec::new();
+
+    for _ in 0..4 {
+        let mut payload = [Command::PutEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+        let key = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        keys.push(deserialize::<u64>(&key).unwrap());
+        thread::sleep(time::Duration::from_millis(1));
+    }
+
+    let replacement = |ingest_ts: u64| Event {
+        ingest_ts,
+        title: String::from("replacement"),
+        ..get_test_event()
+    };
+    let (start, end) = (keys[1], keys[3]);
+    let outside = vec![replacement(keys[3])];
+    let mut payload = [Command::ReplaceWindow.value()].to_vec();
+    payload.extend_from_slice(&serialize(&(start, end, outside, true)).unwrap());
+    assert!(process_msg(&Message::from_slice(&payload), &store, Socket::Admin).is_err());
+
+    let new_events = vec![
+        replacement(start),
+        replacement(start + 1),
+        replacement(start + 2),
+    ];
+    let mut payload = [Command::ReplaceWindow.value()].to_vec();
+    payload.extend_from_slice(&serialize(&(start, end, new_events, true)).unwrap());
+    assert!(process_msg(&Message::from_slice(&payload), &store, Socket::Data).is_err());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    let (deleted, inserted): (u64, u64) = deserialize(&result).unwrap();
+    assert_eq!((deleted, inserted), (2, 3));
+
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let events: Vec<Event> = deserialize(&result).unwrap();
+    let stored: Vec<(u64, &str)> = events
+        .iter()
+        .map(|event| (event.ingest_ts, event.title.as_str()))
+        .collect();
+    assert_eq!(
+        stored,
+        vec![
+            (keys[0], "title"),
+            (start, "replacement"),
+            (start + 1, "replacement"),
+            (start + 2, "replacement"),
+            (keys[3], "title"),
+        ]
+    );
+}
+
+#[test]
+fn compaction_backlog_should_be_empty_on_a_quiet_store() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut payload = [Command::Put.value()].to_vec();
+    payload.extend_from_slice(&serialize(&("test", "testval".as_bytes())).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+
+    let msg = Message::from_slice(&[Command::CompactionBacklog.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let backlog: CompactionBacklog = deserialize(&result).unwrap();
+    assert_eq!(backlog.running, 0);
+    assert!(!backlog.pending);
+    assert_eq!(backlog.pending_bytes, 0);
+}
+
+#[test]
+fn get_events_by_type_should_only_read_that_type() {
+    destroy_store();
+    let stats_path = "wx_test_type_stats.log";
+    let _ = fs::remove_file(stats_path);
+    let config = Config {
+        stats_dump_path: Some(String::from(stats_path)),
+        stats_dump_interval: time::Duration::from_millis(50),
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+    let other = Event {
+        event_type: get_other_event_type(),
+        ..get_test_event()
+    };
+    let mut other_keys = Vec::new();
+
+    for i in 0..200 {
+        let mut payload = [Command::PutEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+        process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+
+        if i % 100 == 50 {
+            thread::sleep(time::Duration::from_millis(1));
+            let mut payload = [Command::PutEvent.value()].to_vec();
+            payload.extend_from_slice(&serialize(&other).unwrap());
+            let key = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+            other_keys.push(deserialize::<u64>(&key).unwrap());
+            thread::sleep(time::Duration::from_millis(1));
+        }
+    }
+
+    // Iterator steps are counted in RocksDB's statistics, which are dumped periodically
+    let iterator_nexts = || {
+        thread::sleep(time::Duration::from_millis(250));
+        let dumped = fs::read_to_string(stats_path).unwrap();
+        let line = dumped
+            .lines()
+            .rfind(|line| line.starts_with("rocksdb.number.db.next "))
+            .unwrap()
+            .to_string();
+        line.rsplit(' ').next().unwrap().parse::<u64>().unwrap()
+    };
+
+    let before = iterator_nexts();
+    let mut payload = [Command::GetEventsByType.value()].to_vec();
+    payload.extend_from_slice(&serialize(&other.event_type).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let typed_nexts = iterator_nexts() - before;
+    let events: Vec<Event> = deserialize(&result).unwrap();
+    let keys: Vec<u64> = events.iter().map(|event| event.ingest_ts).collect();
+    assert_eq!(keys, other_keys);
+
+    let before = iterator_nexts();
+    let msg = Message::from_slice(&[Command::GetEvents.value()]);
+    process_msg(&msg, &store, Socket::Data).unwrap();
+    let full_nexts = iterator_nexts() - before;
+    assert!(typed_nexts * 10 < full_nexts);
+
+    drop(store);
+    fs::remove_file(stats_path).unwrap();
+}
+
+#[test]
+fn poll_should_return_disjoint_complete_sets() {
+    destroy_store();
+    let store = Arc::new(Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS));
+    let put = |store: &Store| {
+        let mut payload = [Command::PutEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+        let key = process_msg(&Message::from_slice(&payload), store, Socket::Data).unwrap();
+        thread::sleep(time::Duration::from_millis(1));
+        deserialize::<u64>(&key).unwrap()
+    };
+    let poll = |store: &Store, consumer: &str| {
+        let mut payload = [Command::Poll.value()].to_vec();
+        payload.extend_from_slice(&serialize(&consumer).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), store, Socket::Data).unwrap();
+        let events: Vec<Event> = deserialize(&result).unwrap();
+        events
+            .iter()
+            .map(|event| event.ingest_ts)
+            .collect::<Vec<u64>>()
+    };
+
+    let first = vec![put(&store), put(&store)];
+    assert_eq!(poll(&store, "consumer"), first);
+    assert!(poll(&store, "consumer").is_empty());
+    let second = vec![put(&store), put(&store), put(&store)];
+    assert_eq!(poll(&store, "consumer"), second);
+    assert_eq!(poll(&store, "other").len(), 5);
+
+    // Concurrent polls by one consumer split the new events between them without overlap
+    let third: Vec<u64> = (0..3).map(|_| put(&store)).collect();
+    let pollers: Vec<_> = (0..4)
+        .map(|_| {
+            let store = store.clone();
+            thread::spawn(move || poll(&store, "consumer"))
+        })
+        .collect();
+    let mut polled: Vec<u64> = pollers
+        .into_iter()
+        .flat_map(|poller| poller.join().unwrap())
+        .collect();
+    polled.sort();
+    assert_eq!(polled, third);
+}
+
+#[test]
+fn verify_checkpoint_should_match_until_the_store_changes() {
+    destroy_store();
+    let checkpoint_path = "wx_test_verify_checkpoint";
+    let _ = fs::remove_dir_all(checkpoint_path);
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+
+    for _ in 0..3 {
+        let mut payload = [Command::PutEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+        process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        thread::sleep(time::Duration::from_millis(1));
+    }
+
+    let mut payload = [Command::Checkpoint.value()].to_vec();
+    payload.extend_from_slice(&serialize(&checkpoint_path).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    let sst_files = || {
+        fs::read_dir(checkpoint_path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name.ends_with(".sst"))
+            .collect::<BTreeSet<String>>()
+    };
+    let before = sst_files();
+
+    let verify = |range: Option<(u64, u64)>| {
+        let mut payload = [Command::VerifyCheckpoint.value()].to_vec();
+        payload.extend_from_slice(&serialize(&(checkpoint_path, range)).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+        deserialize::<(bool, u64, u64)>(&result).unwrap()
+    };
+    let (matches, checkpoint_digest, live_digest) = verify(None);
+    assert!(matches);
+    assert_eq!(checkpoint_digest, live_digest);
+    // verifying leaves the checkpoint's data files as they were
+    assert_eq!(sst_files(), before);
+
+    thread::sleep(time::Duration::from_millis(1));
+    let cutoff = wx::util::get_system_micros();
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    assert!(!verify(None).0);
+    assert!(verify(Some((0, cutoff))).0);
+
+    let mut payload = [Command::VerifyCheckpoint.value()].to_vec();
+    payload.extend_from_slice(
+        &serialize(&("wx_test_missing_checkpoint", None::<(u64, u64)>)).unwrap(),
+    );
+    assert!(process_msg(&Message::from_slice(&payload), &store, Socket::Admin).is_err());
+    fs::remove_dir_all(checkpoint_path).unwrap();
+}
+
+#[test]
+fn vector_memtable_should_store_heavy_ingest_correctly() {
+    destroy_store();
+    let config = Config {
+        event_memtable: Memtable::Vector,
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+    let mut keys = BTreeSet::new();
+
+    for _ in 0..2000 {
+        let mut payload = [Command::PutEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+        let key = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        keys.insert(deserialize::<u64>(&key).unwrap());
+    }
+
+    let get_all = |store: &Store| {
+        let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+        let result = process_msg(&msg, store, Socket::Data).unwrap();
+        let events: Vec<Event> = deserialize(&result).unwrap();
+        events
+            .iter()
+            .map(|event| event.ingest_ts)
+            .collect::<Vec<u64>>()
+    };
+    let expected: Vec<u64> = keys.into_iter().collect();
+    assert_eq!(get_all(&store), expected);
+
+    let msg = Message::from_slice(&[Command::Flush.value()]);
+    process_msg(&msg, &store, Socket::Admin).unwrap();
+    assert_eq!(get_all(&store), expected);
+}
+
+#[test]
+fn get_events_with_keys_should_expose_sorted_stringified_keys() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut keys = Vec::new();
+
+    for _ in 0..3 {
+        let mut payload = [Command::PutEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+        let key = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        keys.push(deserialize::<u64>(&key).unwrap());
+        thread::sleep(time::Duration::from_millis(1));
+    }
+
+    let msg = Message::from_slice(&[Command::GetEventsWithKeys.value()]);
+    assert!(process_msg(&msg, &store, Socket::Data).is_err());
+    let result = process_msg(&msg, &store, Socket::Admin).unwrap();
+    let events: Vec<(String, Event)> = deserialize(&result).unwrap();
+    let stored: Vec<&str> = events.iter().map(|(key, _)| key.as_str()).collect();
+    let expected: Vec<String> = keys.iter().map(u64::to_string).collect();
+    assert_eq!(stored, expected);
+    assert!(stored.windows(2).all(|pair| pair[0] < pair[1]));
+
+    for (key, event) in &events {
+        assert_eq!(key, &event.ingest_ts.to_string());
+    }
+}
+
+#[test]
+fn set_db_option_should_only_accept_settable_options() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let set = |name: &str, value: &str| {
+        let mut payload = [Command::SetDbOption.value()].to_vec();
+        payload.extend_from_slice(&serialize(&(name, value)).unwrap());
+        process_msg(&Message::from_slice(&payload), &store, Socket::Admin)
+    };
+
+    assert!(set("write_buffer_size", "33554432").is_ok());
+    assert!(set("disable_auto_compactions", "true").is_ok());
+    assert!(set("write_buffer_size", "not a number").is_err());
+    assert!(set("create_if_missing", "false").is_err());
+
+    let mut payload = [Command::Put.value()].to_vec();
+    payload.extend_from_slice(&serialize(&("test", "testval".as_bytes())).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+}
+
+#[test]
+fn oldest_active_by_type_should_return_minima_per_type() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let other = Event {
+        event_type: get_other_event_type(),
+        ..get_test_event()
+    };
+    let put = |event: &Event| {
+        let mut payload = [Command::PutEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(event).unwrap());
+        let key = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        thread::sleep(time::Duration::from_millis(1));
+        deserialize::<u64>(&key).unwrap()
+    };
+
+    let oldest_test = put(&get_test_event());
+    let oldest_other = put(&other);
+    put(&get_test_event());
+    put(&other);
+
+    let msg = Message::from_slice(&[Command::OldestActiveByType.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let oldest: Vec<(EventType, u64)> = deserialize(&result).unwrap();
+    assert_eq!(
+        oldest,
+        vec![
+            (EventType::NwsLsr, oldest_test),
+            (other.event_type, oldest_other)
+        ]
+    );
+}
+
+#[test]
+fn compact_events_should_only_compact_events() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let event = Event {
+        text: Some("x".repeat(1000)),
+        ..get_test_event()
+    };
+
+    for _ in 0..200 {
+        let mut payload = [Command::PutEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(&event).unwrap());
+        process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    }
+
+    let flush = Message::from_slice(&[Command::Flush.value()]);
+    process_msg(&flush, &store, Socket::Admin).unwrap();
+    let msg = Message::from_slice(&[Command::ClearEvents.value()]);
+    process_msg(&msg, &store, Socket::Admin).unwrap();
+    process_msg(&flush, &store, Socket::Admin).unwrap();
+
+    let cf_stats = || {
+        let msg = Message::from_slice(&[Command::CfStats.value()]);
+        let result = process_msg(&msg, &store, Socket::Data).unwrap();
+        deserialize::<BTreeMap<String, CfStats>>(&result).unwrap()
+    };
+    let before = cf_stats();
+
+    let msg = Message::from_slice(&[Command::CompactEvents.value()]);
+    assert!(process_msg(&msg, &store, Socket::Data).is_err());
+    let result = process_msg(&msg, &store, Socket::Admin).unwrap();
+    let reclaimed: u64 = deserialize(&result).unwrap();
+    assert!(reclaimed > 0);
+
+    let after = cf_stats();
+    assert_eq!(
+        after["events"].sst_bytes,
+        before["events"].sst_bytes - reclaimed
+    );
+    for name in &["sequence", "event_types"] {
+        assert_eq!(after[*name].sst_bytes, before[*name].sst_bytes);
+    }
+}
+
+#[test]
+fn search_events_should_page_through_matches() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let events = vec![
+        Event {
+            title: String::from("Tornado Warning"),
+            ..get_test_event()
+        },
+        get_test_event(),
+        Event {
+            text: Some(String::from("a TORNADO was reported")),
+            ..get_test_event()
+        },
+        get_test_event(),
+        Event {
+            title: String::from("tornado debris signature"),
+            ..get_test_event()
+        },
+    ];
+    let mut keys = Vec::new();
+
+    for event in &events {
+        let mut payload = [Command::PutEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(event).unwrap());
+        let key = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        keys.push(deserialize::<u64>(&key).unwrap());
+        thread::sleep(time::Duration::from_millis(1));
+    }
+
+    let search = |cursor: u64| {
+        let mut payload = [Command::SearchEvents.value()].to_vec();
+        payload.extend_from_slice(&serialize(&("tornado", cursor, 2u32)).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        let next_token: Option<u64> = deserialize(&result).unwrap();
+        let offset = serialize(&next_token).unwrap().len();
+        let events: Vec<Event> = deserialize(&result[offset..]).unwrap();
+        let found: Vec<u64> = events.iter().map(|event| event.ingest_ts).collect();
+        (next_token, found)
+    };
+
+    let (next_token, found) = search(0);
+    assert_eq!(found, vec![keys[0], keys[2]]);
+    assert_eq!(next_token, Some(keys[2]));
+    let (next_token, found) = search(keys[2]);
+    assert_eq!(found, vec![keys[4]]);
+    assert_eq!(next_token, None);
+}
+
+#[test]
+fn slow_requests_should_be_warned_about_above_threshold() {
+    destroy_store();
+    let (logger, lines) = get_capture_logger();
+    let config = Config {
+        logger,
+        slow_request_threshold: time::Duration::from_millis(50),
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+
+    let msg = Message::from_slice(&[Command::GetEvents.value()]);
+    process_msg(&msg, &store, Socket::Data).unwrap();
+
+    // Waiting with nothing to wake it is slow by design
+    let mut payload = [Command::WaitForEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&(0u64, 100u64)).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+
+    let lines = lines.lock().unwrap();
+    let slow: Vec<&String> = lines
+        .iter()
+        .filter(|line| line.starts_with("slow_request"))
+        .collect();
+    assert_eq!(slow.len(), 1);
+    assert!(slow[0].contains("command=Some(WaitForEvent)"));
+    assert!(slow[0].contains("latency_micros="));
+}
+
+#[test]
+fn event_totals_should_outlive_the_events_they_count() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let other = Event {
+        event_type: get_other_event_type(),
+        ..get_test_event()
+    };
+
+    for event in &[get_test_event(), other, get_test_event(), get_test_event()] {
+        let mut payload = [Command::PutEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(event).unwrap());
+        process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        thread::sleep(time::Duration::from_millis(1));
+    }
+
+    let msg = Message::from_slice(&[Command::ClearEvents.value()]);
+    process_msg(&msg, &store, Socket::Admin).unwrap();
+    drop(store);
+
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let msg = Message::from_slice(&[Command::GetEventTotals.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let totals: Vec<(EventType, u64)> = deserialize(&result).unwrap();
+    assert_eq!(totals.len(), 2);
+    let total = |event_type: EventType| {
+        totals
+            .iter()
+            .find(|(stored, _)| *stored == event_type)
+            .map(|(_, total)| *total)
+    };
+    assert_eq!(total(EventType::NwsLsr), Some(3));
+    assert_eq!(total(get_other_event_type()), Some(1));
+}
+
+#[test]
+fn latest_event_should_return_the_newest_event() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let msg = Message::from_slice(&[Command::LatestEvent.value()]);
+    assert!(process_msg(&msg, &store, Socket::Data).is_err());
+
+    let mut last_key: u64 = 0;
+    for title in &["first", "second", "third"] {
+        let event = Event {
+            title: String::from(*title),
+            ..get_test_event()
+        };
+        let mut payload = [Command::PutEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(&event).unwrap());
+        let key = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        last_key = deserialize(&key).unwrap();
+        thread::sleep(time::Duration::from_millis(1));
+    }
+
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let event: Event = deserialize(&result).unwrap();
+    assert_eq!(event.title, "third");
+    assert_eq!(event.ingest_ts, last_key);
+}
+
+#[test]
+fn error_detail_should_describe_malformed_payloads() {
+    destroy_store();
+    let get_events_error = |store: &Store, cursor: &[u8]| {
+        let mut payload = [Command::GetEvents.value()].to_vec();
+        payload.extend_from_slice(cursor);
+        process_msg(&Message::from_slice(&payload), store, Socket::Data)
+            .unwrap_err()
+            .to_string()
+    };
+    let not_a_number = serialize(&"12x").unwrap();
+    let truncated = &serialize(&"1234567890").unwrap()[..10];
+
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let message = get_events_error(&store, &not_a_number);
+    assert!(message.contains("invalid cursor"));
+    assert!(!message.contains("not a number"));
+    drop(store);
+
+    let config = Config {
+        error_detail: true,
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+    let message = get_events_error(&store, &not_a_number);
+    assert!(message.contains("invalid cursor: not a number"));
+    assert!(!message.contains("12x"));
+    let message = get_events_error(&store, truncated);
+    assert!(message.contains("invalid cursor: payload ended early"));
+}
+
+#[test]
+fn pause_should_reject_writes_until_resumed() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut put_event = [Command::PutEvent.value()].to_vec();
+    put_event.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    let mut put = [Command::Put.value()].to_vec();
+    put.extend_from_slice(&serialize(&("key", "value".as_bytes())).unwrap());
+    let mut put_fetch_failure = [Command::PutFetchFailure.value()].to_vec();
+    put_fetch_failure.extend_from_slice(b"failure");
+    let mut delete = [Command::Delete.value()].to_vec();
+    delete.extend_from_slice(b"key");
+    let mut delete_namespaced = [Command::DeleteNamespaced.value()].to_vec();
+    delete_namespaced.extend_from_slice(&serialize(&("app", "key")).unwrap());
+    let mut commit_offset = [Command::CommitOffset.value()].to_vec();
+    commit_offset.extend_from_slice(&serialize(&("consumer", 1u64)).unwrap());
+    let mut poll = [Command::Poll.value()].to_vec();
+    poll.extend_from_slice(&serialize(&"consumer").unwrap());
+    let mut claim = [Command::ClaimNextEvent.value()].to_vec();
+    claim.extend_from_slice(&serialize(&1000u64).unwrap());
+    let write_probe = [Command::WriteProbe.value()].to_vec();
+    let mut delete_event = [Command::DeleteEvent.value()].to_vec();
+    delete_event.extend_from_slice(&serialize(&1u64).unwrap());
+    let writes = [
+        (&put_event, Socket::Data),
+        (&put, Socket::Data),
+        (&put_fetch_failure, Socket::Data),
+        (&delete, Socket::Data),
+        (&delete_namespaced, Socket::Data),
+        (&commit_offset, Socket::Data),
+        (&poll, Socket::Data),
+        (&claim, Socket::Data),
+        (&write_probe, Socket::Data),
+        (&delete_event, Socket::Admin),
+    ];
+    process_msg(&Message::from_slice(&put_event), &store, Socket::Data).unwrap();
+
+    let pause = Message::from_slice(&[Command::Pause.value()]);
+    assert!(process_msg(&pause, &store, Socket::Data).is_err());
+    let result = process_msg(&pause, &store, Socket::Admin).unwrap();
+    assert!(!deserialize::<bool>(&result).unwrap());
+
+    for (write, socket) in writes.iter() {
+        let result = process_msg(&Message::from_slice(write), &store, *socket);
+        assert!(result.unwrap_err().to_string().contains("paused"));
+    }
+
+    let msg = Message::from_slice(&[Command::GetEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let events: Vec<Event> = deserialize(&result).unwrap();
+    assert_eq!(events.len(), 1);
+
+    let resume = Message::from_slice(&[Command::Resume.value()]);
+    let result = process_msg(&resume, &store, Socket::Admin).unwrap();
+    assert!(deserialize::<bool>(&result).unwrap());
+
+    for (write, socket) in writes.iter() {
+        process_msg(&Message::from_slice(write), &store, *socket).unwrap();
+    }
+}
+
+#[test]
+fn sst_files_should_list_flushed_files() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    let key = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let key: u64 = deserialize(&key).unwrap();
+    let flush = Message::from_slice(&[Command::Flush.value()]);
+    process_msg(&flush, &store, Socket::Admin).unwrap();
+
+    let msg = Message::from_slice(&[Command::SstFiles.value()]);
+    assert!(process_msg(&msg, &store, Socket::Data).is_err());
+    let result = process_msg(&msg, &store, Socket::Admin).unwrap();
+    let files: Vec<SstFile> = deserialize(&result).unwrap();
+    let events: Vec<&SstFile> = files
+        .iter()
+        .filter(|file| file.column_family == "events")
+        .collect();
+    assert_eq!(events.len(), 1);
+    assert!(events[0].size > 0);
+    assert_eq!(events[0].num_entries, 1);
+    assert_eq!(events[0].start_key, Some(key.to_string().into_bytes()));
+    assert_eq!(events[0].start_key, events[0].end_key);
+}
+
+#[test]
+fn type_retention_should_drop_only_expired_types_on_compaction() {
+    destroy_store();
+    let config = Config {
+        type_retention: vec![(get_other_event_type(), time::Duration::from_millis(100))],
+        event_checksums: true,
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+    let other = Event {
+        event_type: get_other_event_type(),
+        ..get_test_event()
+    };
+
+    for event in &[get_test_event(), other] {
+        let mut payload = [Command::PutEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(event).unwrap());
+        process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    }
+    thread::sleep(time::Duration::from_millis(150));
+
+    let msg = Message::from_slice(&[Command::Compact.value()]);
+    process_msg(&msg, &store, Socket::Admin).unwrap();
+
+    let msg = Message::from_slice(&[Command::GetAllEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let result: Vec<Event> = deserialize(&result).unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].event_type, EventType::NwsLsr);
+}
+
+#[test]
+fn ingest_lag_stats_should_measure_lag_and_count_skew() {
+    destroy_store();
+    let import_path = "wx_test_ingest_lag.ndjson";
+    let now = wx::util::get_system_micros();
+    // The last event claims to have happened after it was ingested
+    let lines: Vec<String> = [(1, 1000), (2, 4000), (3, 2000), (4, 3000), (5, 0)]
+        .iter()
+        .map(|&(offset, lag)| {
+            let ingest_ts = now - 1000 * 1000 + offset;
+            serde_json::to_string(&Event {
+                ingest_ts,
+                event_ts: if lag > 0 {
+                    ingest_ts - lag
+                } else {
+                    ingest_ts + 500
+                },
+                ..get_test_event()
+            })
+            .unwrap()
+        })
+        .collect();
+    fs::write(import_path, lines.join("\n")).unwrap();
+
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut payload = [Command::ImportEvents.value()].to_vec();
+    payload.extend_from_slice(&serialize(&import_path).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    fs::remove_file(import_path).unwrap();
+
+    let msg = Message::from_slice(&[Command::IngestLagStats.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let stats: LagStats = deserialize(&result).unwrap();
+    assert_eq!(stats.measured, 4);
+    assert_eq!(stats.skewed, 1);
+    assert_eq!(stats.min, 1000);
+    assert_eq!(stats.mean, 2500);
+    assert_eq!(stats.p95, 4000);
+    assert_eq!(stats.max, 4000);
+}
+
+#[test]
+fn follow_from_should_deliver_each_event_exactly_once() {
+    destroy_store();
+    let store = Arc::new(Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS));
+    let follow = |store: &Store, last_seen: u64| {
+        let mut payload = [Command::FollowFrom.value()].to_vec();
+        payload.extend_from_slice(&serialize(&(last_seen, 2000u64)).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), store, Socket::Data).unwrap();
+        let next: u64 = deserialize(&result[..8]).unwrap();
+        let events: Vec<Event> = deserialize(&result[8..]).unwrap();
+        (next, events)
+    };
+
+    let writer = {
+        let store = store.clone();
+        thread::spawn(move || {
+            let mut payload = [Command::PutEvent.value()].to_vec();
+            payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+            let msg = Message::from_slice(&payload);
+            (0..20)
+                .map(|i| {
+                    thread::sleep(time::Duration::from_millis(i % 3));
+                    let key = process_msg(&msg, &store, Socket::Data).unwrap();
+                    deserialize::<u64>(&key).unwrap()
+                })
+                .collect::<Vec<u64>>()
+        })
+    };
+
+    let mut next = 0;
+    let mut received = Vec::new();
+    while received.len() < 20 {
+        let (following, events) = follow(&store, next);
+        assert!(following > next);
+        assert_eq!(events.len() as u64, following - next);
+        received.extend(events.iter().map(|event| event.ingest_ts));
+        next = following;
+    }
+    assert_eq!(received, writer.join().unwrap());
+
+    let start = time::Instant::now();
+    let (following, events) = follow(&store, next);
+    assert_eq!(following, next);
+    assert!(events.is_empty());
+    assert!(start.elapsed() >= time::Duration::from_secs(2));
+}
+
+#[test]
+fn legacy_stores_should_be_detected_and_read_after_upgrade() {
+    destroy_store();
+    let event = Event {
+        ingest_ts: wx::util::get_system_micros() - 1000,
+        ..get_test_event()
+    };
+
+    // a store as the baseline build wrote it, with stringified keys in the default column family
+    {
+        let db = DB::open_default(TEST_STORE_PATH).unwrap();
+        let key = event.ingest_ts.to_string();
+        db.put(key.as_bytes(), serialize(&event).unwrap()).unwrap();
+    }
+
+    let config = Config {
+        key_encoding: KeyEncoding::BigEndian,
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+    let msg = Message::from_slice(&[Command::GetEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let events: Vec<Event> = deserialize(&result).unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].ingest_ts, event.ingest_ts);
+
+    let mut payload = [Command::GetRaw.value()].to_vec();
+    payload.extend_from_slice(&serialize(&("metadata", &b"\x1fwx_storage_format"[..])).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    let format: Vec<u8> = deserialize(&result).unwrap();
+    let format: (u32, String) = deserialize(&format).unwrap();
+    assert_eq!(format, (SCHEMA_VERSION, String::from("big_endian")));
+}
+
+#[test]
+fn open_should_fail_on_an_unreadable_or_newer_format() {
+    let format_key = &b"\x1fwx_storage_format"[..];
+    let mut opts = Options::default();
+    opts.create_if_missing(true);
+    opts.create_missing_column_families(true);
+
+    destroy_store();
+    {
+        let db = DB::open_cf(&opts, TEST_STORE_PATH, ["metadata"]).unwrap();
+        let cf = db.cf_handle("metadata").unwrap();
+        db.put_cf(cf, format_key, b"garbage").unwrap();
+    }
+    let result = Store::open(TEST_STORE_PATH, Config::default());
+    assert!(result
+        .err()
+        .unwrap()
+        .to_string()
+        .contains("invalid stored format"));
+
+    // recorded in the default column family, as older builds did
+    destroy_store();
+    {
+        let db = DB::open_default(TEST_STORE_PATH).unwrap();
+        let format = serialize(&(SCHEMA_VERSION + 1, "decimal")).unwrap();
+        db.put(format_key, format).unwrap();
+    }
+    let result = Store::open(TEST_STORE_PATH, Config::default());
+    assert!(result.err().unwrap().to_string().contains("schema version"));
+}
+
+#[test]
+fn kv_writes_should_reject_reserved_keys() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let reserved = "\u{1f}wx_storage_format";
+
+    let mut put = [Command::Put.value()].to_vec();
+    put.extend_from_slice(&serialize(&(reserved, "value".as_bytes())).unwrap());
+    let mut delete = [Command::Delete.value()].to_vec();
+    delete.extend_from_slice(reserved.as_bytes());
+    let mut swap = [Command::Swap.value()].to_vec();
+    swap.extend_from_slice(&serialize(&("key", reserved, true)).unwrap());
+    let mut compare_and_swap = [Command::CompareAndSwap.value()].to_vec();
+    compare_and_swap
+        .extend_from_slice(&serialize(&(reserved, None::<&[u8]>, "value".as_bytes())).unwrap());
+
+    for write in [&put, &delete, &swap, &compare_and_swap].iter() {
+        let result = process_msg(&Message::from_slice(write), &store, Socket::Data);
+        assert!(result.unwrap_err().to_string().contains("reserved key"));
+    }
+}
+
+#[test]
+fn baseline_stores_should_have_events_moved_out_of_the_default_column_family() {
+    destroy_store();
+    let event = Event {
+        ingest_ts: wx::util::get_system_micros() - 1000,
+        ..get_test_event()
+    };
+
+    // a store from before column families, with events and KV pairs side by side
+    {
+        let db = DB::open_default(TEST_STORE_PATH).unwrap();
+        let key = event.ingest_ts.to_string();
+        db.put(key.as_bytes(), serialize(&event).unwrap()).unwrap();
+        db.put(b"12345", b"not an event").unwrap();
+        db.put(b"radar", b"KMPX").unwrap();
+    }
+
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let msg = Message::from_slice(&[Command::GetEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let events: Vec<Event> = deserialize(&result).unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].ingest_ts, event.ingest_ts);
+
+    let mut payload = [Command::GetEventsByType.value()].to_vec();
+    payload.extend_from_slice(&serialize(&event.event_type).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let events: Vec<Event> = deserialize(&result).unwrap();
+    assert_eq!(events.len(), 1);
+
+    let mut payload = [Command::GetRaw.value()].to_vec();
+    let key = event.ingest_ts.to_string();
+    payload.extend_from_slice(&serialize(&("default", key.as_bytes())).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    let value: Vec<u8> = deserialize(&result).unwrap();
+    assert!(value.is_empty());
+
+    for (key, value) in [(&b"12345"[..], &b"not an event"[..]), (b"radar", b"KMPX")].iter() {
+        let mut payload = [Command::Get.value()].to_vec();
+        payload.extend_from_slice(key);
+        let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        assert_eq!(&result[..], *value);
+    }
+}
+
+#[test]
+fn get_events_last_n_should_return_events_in_the_relative_window() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    let put = Message::from_slice(&payload);
+    process_msg(&put, &store, Socket::Data).unwrap();
+    thread::sleep(time::Duration::from_millis(300));
+    let recent: u64 = deserialize(&process_msg(&put, &store, Socket::Data).unwrap()).unwrap();
+
+    let last_n = |micros: u64| {
+        let mut payload = [Command::GetEventsLastN.value()].to_vec();
+        payload.extend_from_slice(&serialize(&micros).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        deserialize::<Vec<Event>>(&result).unwrap()
+    };
+
+    let events = last_n(200 * 1000);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].ingest_ts, recent);
+    assert_eq!(last_n(10 * 1000 * 1000).len(), 2);
+}
+
+#[test]
+fn rollups_should_be_idempotent_and_outlive_events() {
+    destroy_store();
+    let micros_per_day = 1000 * 1000 * 60 * 60 * 24;
+    let day = wx::util::get_system_micros() / micros_per_day - 3;
+    let import_path = "wx_test_rollups.ndjson";
+    let lines: Vec<String> = vec![
+        (1000, EventType::NwsLsr),
+        (2000, get_other_event_type()),
+        (3000, EventType::NwsLsr),
+        (micros_per_day + 1000, EventType::NwsLsr),
+    ]
+    .into_iter()
+    .map(|(offset, event_type)| {
+        serde_json::to_string(&Event {
+            ingest_ts: day * micros_per_day + offset,
+            event_type,
+            ..get_test_event()
+        })
+        .unwrap()
+    })
+    .collect();
+    fs::write(import_path, lines.join("\n")).unwrap();
+
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut payload = [Command::ImportEvents.value()].to_vec();
+    payload.extend_from_slice(&serialize(&import_path).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Admin).unwrap();
+    fs::remove_file(import_path).unwrap();
+
+    let mut payload = [Command::RollupDay.value()].to_vec();
+    payload.extend_from_slice(&serialize(&day).unwrap());
+    let rollup_day = Message::from_slice(&payload);
+    assert!(process_msg(&rollup_day, &store, Socket::Data).is_err());
+    for _ in 0..2 {
+        let result = process_msg(&rollup_day, &store, Socket::Admin).unwrap();
+        let counts: Vec<(EventType, u64)> = deserialize(&result).unwrap();
+        assert_eq!(counts.len(), 2);
+    }
+
+    let msg = Message::from_slice(&[Command::ClearEvents.value()]);
+    process_msg(&msg, &store, Socket::Admin).unwrap();
+
+    let mut payload = [Command::GetRollups.value()].to_vec();
+    payload.extend_from_slice(&serialize(&(day - 1, day + 2)).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    let rollups: Vec<(u64, EventType, u64)> = deserialize(&result).unwrap();
+    assert_eq!(rollups.len(), 2);
+    let count = |event_type: EventType| {
+        rollups
+            .iter()
+            .find(|(rolled_up, stored, _)| *rolled_up == day && *stored == event_type)
+            .map(|(_, _, count)| *count)
+    };
+    assert_eq!(count(EventType::NwsLsr), Some(2));
+    assert_eq!(count(get_other_event_type()), Some(1));
+}
+
+#[test]
+fn get_events_ndjson_gz_should_decompress_to_json_lines() {
+    destroy_store();
+    let store = Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS);
+    let mut keys = Vec::new();
+    for _ in 0..3 {
+        let mut payload = [Command::PutEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+        let key = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        keys.push(deserialize::<u64>(&key).unwrap());
+        thread::sleep(time::Duration::from_millis(1));
+    }
+
+    let mut payload = [Command::GetEventsNdjsonGz.value()].to_vec();
+    payload.extend_from_slice(&serialize(&keys[0].to_string()).unwrap());
+    let result = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+    assert_eq!(result[0], GZIP_FLAG);
+
+    let mut ndjson = String::new();
+    GzDecoder::new(&result[1..])
+        .read_to_string(&mut ndjson)
+        .unwrap();
+    let events: Vec<Event> = ndjson
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let ingest_ts: Vec<u64> = events.iter().map(|event| event.ingest_ts).collect();
+    assert_eq!(ingest_ts, &keys[1..]);
+    assert_eq!(events[0].title, "title");
+}
+
+#[test]
+fn claim_next_event_should_hand_each_event_to_one_worker() {
+    destroy_store();
+    let store = Arc::new(Store::new(TEST_STORE_PATH, EVENT_THRESHOLD_MICROS));
+    let mut keys = Vec::new();
+    for _ in 0..10 {
+        let mut payload = [Command::PutEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+        let key = process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+        keys.push(deserialize::<u64>(&key).unwrap());
+        thread::sleep(time::Duration::from_millis(1));
+    }
+    let claim = |store: &Store, lease_millis: u64| {
+        let mut payload = [Command::ClaimNextEvent.value()].to_vec();
+        payload.extend_from_slice(&serialize(&lease_millis).unwrap());
+        let result = process_msg(&Message::from_slice(&payload), store, Socket::Data).unwrap();
+        deserialize::<Option<Event>>(&result).unwrap()
+    };
+
+    let workers: Vec<_> = (0..2)
+        .map(|_| {
+            let store = store.clone();
+            thread::spawn(move || {
+                let mut claimed = Vec::new();
+                while let Some(event) = claim(&store, 500) {
+                    claimed.push(event.ingest_ts);
+                }
+                claimed
+            })
+        })
+        .collect();
+    let mut claimed: Vec<u64> = workers
+        .into_iter()
+        .flat_map(|worker| worker.join().unwrap())
+        .collect();
+    claimed.sort();
+    assert_eq!(claimed, keys);
+
+    // leases that run out make their events claimable again, oldest first
+    thread::sleep(time::Duration::from_millis(600));
+    assert_eq!(claim(&store, 500).unwrap().ingest_ts, keys[0]);
+    assert_eq!(claim(&store, 500).unwrap().ingest_ts, keys[1]);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn direct_io_should_open_and_serve_events() {
+    destroy_store();
+    let config = Config {
+        direct_reads: true,
+        direct_io_for_flush_and_compaction: true,
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+
+    // reads after flushing and compacting go through direct I/O rather than the memtable
+    let flush = Message::from_slice(&[Command::Flush.value()]);
+    process_msg(&flush, &store, Socket::Admin).unwrap();
+    let compact = Message::from_slice(&[Command::Compact.value()]);
+    process_msg(&compact, &store, Socket::Admin).unwrap();
+
+    let msg = Message::from_slice(&[Command::GetEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let events: Vec<Event> = deserialize(&result).unwrap();
+    assert_eq!(events.len(), 1);
+}
+
+#[test]
+fn get_events_redacted_should_blank_only_configured_fields() {
+    destroy_store();
+    let config = Config {
+        redacted_fields: vec![String::from("text")],
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+    let mut payload = [Command::PutEvent.value()].to_vec();
+    payload.extend_from_slice(&serialize(&get_test_event()).unwrap());
+    process_msg(&Message::from_slice(&payload), &store, Socket::Data).unwrap();
+
+    let msg = Message::from_slice(&[Command::GetEventsRedacted.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let events: Vec<Event> = deserialize(&result).unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].text, None);
+    assert_eq!(events[0].title, "title");
+    assert_eq!(events[0].event_ts, get_test_event().event_ts);
+
+    // the stored event is untouched
+    let msg = Message::from_slice(&[Command::GetEvents.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let events: Vec<Event> = deserialize(&result).unwrap();
+    assert_eq!(events[0].text, Some(String::from("summary")));
+}
+
+#[test]
+fn error_stats_should_break_down_errors_by_command_and_kind() {
+    destroy_store();
+    let config = Config {
+        max_key_length: 4,
+        ..Config::default()
+    };
+    let store = Store::with_config(TEST_STORE_PATH, config);
+    let fail = |msg: &[u8], socket: Socket| {
+        assert!(process_msg(&Message::from_slice(msg), &store, socket).is_err());
+    };
+
+    for cursor in &["12x", "345y"] {
+        let mut payload = [Command::GetEvents.value()].to_vec();
+        payload.extend_from_slice(&serialize(cursor).unwrap());
+        fail(&payload, Socket::Data);
+    }
+    for key in &["long key", "longer key"] {
+        let mut payload = [Command::Put.value()].to_vec();
+        payload.extend_from_slice(&serialize(&(key, "value".as_bytes())).unwrap());
+        fail(&payload, Socket::Data);
+    }
+    fail(&[Command::Compact.value()], Socket::Data);
+    fail(&[0x7f], Socket::Data);
+
+    let msg = Message::from_slice(&[Command::ErrorStats.value()]);
+    let result = process_msg(&msg, &store, Socket::Data).unwrap();
+    let counts: Vec<ErrorCount> = deserialize(&result).unwrap();
+    let count = |command: &str, error: &str| {
+        counts
+            .iter()
+            .find(|count| count.command == command && count.error.contains(error))
+            .map(|count| count.count)
+    };
+    assert_eq!(counts.len(), 4);
+    assert_eq!(count("GetEvents", "invalid cursor"), Some(2));
+    assert_eq!(count("Put", "key length N exceeds maximum of N"), Some(2));
+    assert_eq!(count("Compact", "not permitted"), Some(1));
+    assert_eq!(count("unknown", "unknown command"), Some(1));
 }